@@ -1,13 +1,26 @@
-CREATE EXTENSION "uuid-ossp";
-CREATE TYPE user_role AS ENUM('admin', 'staff', 'user', 'guest', 'naughty');
+CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
 
-CREATE TYPE app_user_permission AS ENUM(
-    'deleteuser',
-    'markasnaughty',
-    'prodemoteuser',
-    'edituserpermissions',
-    'read'
-);
+DO $$
+BEGIN
+    CREATE TYPE user_role AS ENUM('admin', 'staff', 'user', 'guest', 'naughty');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END;
+$$;
+
+DO $$
+BEGIN
+    CREATE TYPE app_user_permission AS ENUM(
+        'deleteuser',
+        'markasnaughty',
+        'prodemoteuser',
+        'edituserpermissions',
+        'read'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END;
+$$;
 
 CREATE TABLE IF NOT EXISTS app_user (
     id BIGSERIAL PRIMARY KEY,
@@ -16,7 +29,7 @@ CREATE TABLE IF NOT EXISTS app_user (
     email TEXT NOT NULL UNIQUE,
     password_hash TEXT NOT NULL,
     role user_role NOT NULL DEFAULT 'user',
-    skey uuid UNIQUE NOT NULL DEFAULT uuid_generate_uuid()
+    skey uuid UNIQUE NOT NULL DEFAULT uuid_generate_v4()
 );
 
 CREATE TABLE IF NOT EXISTS app_groups_permissions (
@@ -36,4 +49,5 @@ VALUES
     ('admin', 'markasnaughty'),
     ('admin', 'prodemoteuser'),
     ('admin', 'edituserpermissions'),
-    ('admin', 'read');
+    ('admin', 'read')
+ON CONFLICT DO NOTHING;
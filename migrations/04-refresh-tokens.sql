@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS app_refresh_token (
+    id BIGSERIAL PRIMARY KEY,
+    c_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id BIGINT NOT NULL REFERENCES app_user (id) ON DELETE CASCADE,
+    -- every rotation of a login shares the family, reusing a rotated token revokes all of it
+    family uuid NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS app_refresh_token_family_idx ON app_refresh_token (family);
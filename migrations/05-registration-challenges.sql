@@ -0,0 +1,5 @@
+-- proof-of-work challenges already used to register, kept until they expire to reject replays
+CREATE TABLE IF NOT EXISTS app_registration_challenge (
+    nonce TEXT PRIMARY KEY,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
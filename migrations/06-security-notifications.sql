@@ -0,0 +1,23 @@
+-- language of the emails sent to the user
+ALTER TABLE app_user ADD COLUMN IF NOT EXISTS locale TEXT NOT NULL DEFAULT 'en-US';
+-- set when the owner used a "this wasn't me" link, locked accounts can't log in
+ALTER TABLE app_user ADD COLUMN IF NOT EXISTS locked_at TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE IF NOT EXISTS app_login_source (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES app_user (id) ON DELETE CASCADE,
+    ip TEXT NOT NULL,
+    user_agent TEXT NOT NULL,
+    first_seen TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, ip, user_agent)
+);
+
+CREATE TABLE IF NOT EXISTS app_lock_token (
+    id BIGSERIAL PRIMARY KEY,
+    c_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id BIGINT NOT NULL REFERENCES app_user (id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE
+);
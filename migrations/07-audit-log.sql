@@ -0,0 +1,40 @@
+-- migrate: no-transaction
+-- a new enum value can't be used in the transaction adding it, every statement commits on its own
+ALTER TYPE app_user_permission ADD VALUE IF NOT EXISTS 'viewauditlog';
+
+-- append-only, every record hashes the previous one so edits and deletions break the chain
+CREATE TABLE IF NOT EXISTS app_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    c_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    -- no foreign keys, the records outlive the users they mention
+    actor_id BIGINT NOT NULL,
+    permission app_user_permission NOT NULL,
+    action TEXT NOT NULL,
+    target_id BIGINT,
+    -- JSON kept as text, the hash covers the exact bytes
+    before TEXT NOT NULL,
+    after TEXT NOT NULL,
+    request_id TEXT,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL UNIQUE
+);
+
+CREATE INDEX IF NOT EXISTS app_audit_log_actor_idx ON app_audit_log (actor_id);
+CREATE INDEX IF NOT EXISTS app_audit_log_target_idx ON app_audit_log (target_id);
+
+CREATE OR REPLACE FUNCTION app_audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'app_audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS app_audit_log_append_only ON app_audit_log;
+CREATE TRIGGER app_audit_log_append_only
+    BEFORE UPDATE OR DELETE OR TRUNCATE ON app_audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION app_audit_log_append_only();
+
+INSERT INTO
+    app_groups_permissions (role, permission)
+VALUES
+    ('admin', 'viewauditlog')
+ON CONFLICT DO NOTHING;
@@ -22,22 +22,34 @@ pub enum Route {
         // Fields of the route variant will be passed to the component as props. In this case, the blog component must accept
         // an `id` prop of type `i32`.
         Blog { id: i32 },
-        #[route("/register")]
-        Register {},
+        #[route("/register?:invite")]
+        Register { invite: String },
         #[route("/login")]
         Login {},
+        #[route("/account/lock?:token")]
+        LockAccount { token: String },
         #[nest("/settings")]
             #[layout(UserSettings)]
                 #[route("/")]
                 UserSettingsResume {},
                 #[route("/password")]
                 UpdatePassword {},
+                #[route("/tokens")]
+                ApiTokens {},
+            #[end_layout]
+        #[end_nest]
+        #[route("/admin/invites")]
+        AdminInvites {},
+        #[route("/admin/audit")]
+        AdminAuditLog {},
 }
 
 #[derive(Clone, Copy)]
 pub struct AppGlobalState {
     pub alert: Signal<Option<(Alert, String)>>,
     pub redirect: Signal<String>,
+    /// Opens the password confirmation, see `components::ReauthDialog`.
+    pub reauth: Signal<bool>,
 }
 
 impl Default for AppGlobalState {
@@ -45,6 +57,7 @@ impl Default for AppGlobalState {
         Self {
             alert: Signal::default(),
             redirect: Signal::new("/".into()),
+            reauth: Signal::new(false),
         }
     }
 }
@@ -52,7 +65,6 @@ impl Default for AppGlobalState {
 // handling FOUD, if the store doesn't have `data-theme` will be set the window.matchMedia,
 // components::ThemeControl will get handle it's changes and state by using setAttribute and localStorage.setItem
 static THEME_BOOTSTRAP: &str = r#"
-    <script>
     (function() {
         try {
             const savedTheme = localStorage.getItem('data-theme');
@@ -70,7 +82,6 @@ static THEME_BOOTSTRAP: &str = r#"
             console.error("Theme pre-render script error:", e);
         }
     })();
-    </script>
 "#;
 
 /// App is the main component of our app. Components are the building blocks of dioxus apps. Each component is a function
@@ -87,6 +98,48 @@ pub fn App() -> Element {
     tracing::debug!("is there a logged user {:?}", user);
     use_context_provider(|| Signal::new(user));
 
+    // the double-submit token is rendered by the server and echoed back by the web client
+    let csrf_token = use_server_cached(|| {
+        #[cfg(feature = "server")]
+        {
+            server_context()
+                .request_parts()
+                .extensions
+                .get::<crate::backend::auth::csrf::CsrfToken>()
+                .map(|t| t.0.clone())
+                .unwrap_or_default()
+        }
+        #[cfg(not(feature = "server"))]
+        String::new()
+    });
+    #[cfg(not(any(feature = "desktop", feature = "mobile")))]
+    crate::csrf::set_token(&csrf_token);
+    // the browser samples its traces like the server, and traces nothing when it exports nothing
+    let trace_ratio = use_server_cached(|| {
+        #[cfg(feature = "server")]
+        {
+            crate::backend::otlp::browser_sample_ratio()
+        }
+        #[cfg(not(feature = "server"))]
+        0.0
+    });
+    #[cfg(not(any(feature = "desktop", feature = "mobile")))]
+    crate::trace::set_sample_ratio(trace_ratio);
+    // inline scripts only run with the nonce of the request Content-Security-Policy
+    let csp_nonce = use_server_cached(|| {
+        #[cfg(feature = "server")]
+        {
+            server_context()
+                .request_parts()
+                .extensions
+                .get::<crate::backend::headers::CspNonce>()
+                .map(|n| n.0.clone())
+                .unwrap_or_default()
+        }
+        #[cfg(not(feature = "server"))]
+        String::new()
+    });
+
     use_context_provider(AppGlobalState::default);
     use_init_i18n(|| {
         I18nConfig::new(crate::i18n::EN_US.clone())
@@ -104,7 +157,7 @@ pub fn App() -> Element {
         document::Link { rel: "stylesheet", href: MAIN_CSS }
         document::Link { rel: "stylesheet", href: TAILWIND_CSS }
         div {
-            dangerous_inner_html: "{THEME_BOOTSTRAP}",
+            dangerous_inner_html: r#"<script nonce="{csp_nonce}">{THEME_BOOTSTRAP}</script>"#,
             Router::<Route> {}
         }
     }
@@ -0,0 +1,278 @@
+use std::{fmt, sync::OnceLock};
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::{error, info};
+
+use crate::shared::{
+    audit::{AuditRecord, AuditVerification},
+    user::UserPermission,
+};
+
+use super::errors::BackendError;
+
+/// Previous hash of the first record of the chain.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Keys the hashes of the chain, so rewriting it takes the key and not only the database.
+#[derive(Clone)]
+pub struct AuditKey(pub Vec<u8>);
+
+impl fmt::Debug for AuditKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuditKey(..)")
+    }
+}
+
+static KEY: OnceLock<AuditKey> = OnceLock::new();
+
+/// Sets the key of the chain, once at startup.
+pub fn set_key(key: AuditKey) {
+    let _ = KEY.set(key);
+}
+
+fn key() -> Result<&'static AuditKey, BackendError> {
+    KEY.get().ok_or_else(|| {
+        error!("the audit key isn't set");
+        BackendError::InternalError
+    })
+}
+
+/// Actor of the actions taken with the `manage` cli, no user has this id.
+pub const CLI_ACTOR: i64 = 0;
+
+/// Who performs an audited action, and in which request.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub actor: i64,
+    pub request_id: Option<String>,
+}
+
+impl AuditContext {
+    pub fn new(actor: i64, headers: &HeaderMap) -> Self {
+        Self {
+            actor,
+            request_id: headers
+                .get(crate::shared::request_id::REQUEST_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned),
+        }
+    }
+
+    /// The context of the actions taken with the `manage` cli.
+    pub fn cli() -> Self {
+        Self {
+            actor: CLI_ACTOR,
+            request_id: None,
+        }
+    }
+}
+
+/// The fields of a record the hash covers.
+#[derive(Debug, Clone, Copy)]
+pub struct AuditEntry<'a> {
+    pub c_at: DateTime<Utc>,
+    pub actor: i64,
+    pub permission: UserPermission,
+    pub action: &'a str,
+    pub target: Option<i64>,
+    pub before: &'a str,
+    pub after: &'a str,
+    pub request_id: Option<&'a str>,
+}
+
+impl<'a> AuditEntry<'a> {
+    /// An entry created now, with the microseconds the database stores so the hash
+    /// can be recomputed.
+    pub fn now(
+        context: &'a AuditContext,
+        permission: UserPermission,
+        action: &'a str,
+        target: Option<i64>,
+        before: &'a str,
+        after: &'a str,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            c_at: DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now),
+            actor: context.actor,
+            permission,
+            action,
+            target,
+            before,
+            after,
+            request_id: context.request_id.as_deref(),
+        }
+    }
+
+    pub fn of(record: &'a AuditRecord) -> Self {
+        Self {
+            c_at: record.c_at,
+            actor: record.actor_id,
+            permission: record.permission,
+            action: &record.action,
+            target: record.target_id,
+            before: &record.before,
+            after: &record.after,
+            request_id: record.request_id.as_deref(),
+        }
+    }
+}
+
+/// HMAC of a record, covering every field and the hash of the previous record.
+fn record_hash(key: &AuditKey, prev_hash: &str, entry: &AuditEntry<'_>) -> String {
+    let mut hasher = Hmac::<Sha256>::new_from_slice(&key.0).expect("HMAC accepts keys of any size");
+    for field in [
+        prev_hash,
+        &entry.c_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        &entry.actor.to_string(),
+        &format!("{:?}", entry.permission),
+        entry.action,
+        &entry.target.map(|t| t.to_string()).unwrap_or_default(),
+        entry.before,
+        entry.after,
+        entry.request_id.unwrap_or_default(),
+    ] {
+        // the length prefix keeps field boundaries unambiguous
+        hasher.update(&(field.len() as u64).to_be_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hasher
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// The hash of `entry` appended after `prev_hash`, [`GENESIS`] for the first record.
+///
+/// The appends must be serialized, two records can't share a predecessor.
+pub fn chain(prev_hash: &str, entry: &AuditEntry<'_>) -> Result<String, BackendError> {
+    let hash = record_hash(key()?, prev_hash, entry);
+    // the head of the chain also lands in the logs, which a truncation can't reach
+    info!(
+        "Audit record {} by user {}, chain head {hash}",
+        entry.action, entry.actor
+    );
+    Ok(hash)
+}
+
+/// Checks the records one by one from the first, so they can be streamed.
+pub struct ChainVerifier {
+    key: &'static AuditKey,
+    expected_prev: String,
+    checked: i64,
+}
+
+impl ChainVerifier {
+    pub fn new() -> Result<Self, BackendError> {
+        Ok(Self {
+            key: key()?,
+            expected_prev: GENESIS.to_owned(),
+            checked: 0,
+        })
+    }
+
+    /// Whether the record follows the previous one and matches its hash.
+    pub fn check(&mut self, entry: &AuditEntry<'_>, prev_hash: &str, hash: &str) -> bool {
+        if prev_hash != self.expected_prev || record_hash(self.key, prev_hash, entry) != hash {
+            return false;
+        }
+        hash.clone_into(&mut self.expected_prev);
+        self.checked += 1;
+        true
+    }
+
+    /// The result, `broken_at` is the record that failed [`ChainVerifier::check`].
+    pub fn finish(self, broken_at: Option<i64>) -> AuditVerification {
+        match broken_at {
+            Some(id) => error!("audit log chain broken at record {id}"),
+            None => info!(
+                "Audit log chain intact, {} records, head {}",
+                self.checked, self.expected_prev
+            ),
+        }
+        AuditVerification {
+            checked: self.checked,
+            broken_at,
+            head: self.expected_prev,
+        }
+    }
+}
+
+/// Quotes a field, prefixing the ones a spreadsheet would run as a formula with `'`.
+fn csv_field(value: &str) -> String {
+    let formula = value.starts_with(['=', '+', '-', '@', '\t', '\r']);
+    let prefix = if formula { "'" } else { "" };
+    format!("\"{prefix}{}\"", value.replace('"', "\"\""))
+}
+
+pub fn to_csv(records: &[AuditRecord]) -> String {
+    let mut csv = String::from(
+        "id,c_at,actor_id,actor_email,permission,action,target_id,target_email,before,after,request_id,hash\n",
+    );
+    for r in records {
+        let line = [
+            r.id.to_string(),
+            r.c_at.to_rfc3339(),
+            r.actor_id.to_string(),
+            r.actor_email.clone().unwrap_or_default(),
+            format!("{:?}", r.permission),
+            r.action.clone(),
+            r.target_id.map(|t| t.to_string()).unwrap_or_default(),
+            r.target_email.clone().unwrap_or_default(),
+            r.before.clone(),
+            r.after.clone(),
+            r.request_id.clone().unwrap_or_default(),
+            r.hash.clone(),
+        ]
+        .iter()
+        .map(|f| csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",");
+        csv.push_str(&line);
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use crate::shared::user::UserPermission;
+
+    use super::{AuditEntry, AuditKey, GENESIS, csv_field, record_hash};
+
+    fn hash(key: &[u8]) -> String {
+        let entry = AuditEntry {
+            c_at: DateTime::from_timestamp_micros(1_700_000_000_000_000).unwrap(),
+            actor: 1,
+            permission: UserPermission::ProDemoteUser,
+            action: "set_role",
+            target: Some(2),
+            before: "{}",
+            after: "{}",
+            request_id: None,
+        };
+        record_hash(&AuditKey(key.to_vec()), GENESIS, &entry)
+    }
+
+    #[test]
+    fn the_chain_depends_on_the_key() {
+        assert_eq!(hash(&[1; 32]), hash(&[1; 32]));
+        assert_ne!(hash(&[1; 32]), hash(&[2; 32]));
+    }
+
+    #[test]
+    fn escapes_formulas() {
+        assert_eq!(csv_field("a\"b"), "\"a\"\"b\"");
+        for formula in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(csv_field(formula), format!("\"'{formula}\""));
+        }
+        assert_eq!(csv_field("user@example.com"), "\"user@example.com\"");
+    }
+}
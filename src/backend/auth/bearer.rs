@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::Request,
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_login::AuthnBackend;
+use tracing::debug;
+
+use crate::{
+    backend::{errors::BackendError, native_token},
+    shared::user::UserPermission,
+};
+
+use super::AuthSession;
+
+/// Permissions granted to the token used to authenticate the request.
+///
+/// Only present when the request was authenticated with `Authorization: Bearer`,
+/// the effective permissions are the user permissions restricted to these.
+#[derive(Debug, Clone)]
+pub struct TokenScopes(pub HashSet<UserPermission>);
+
+/// Marks a request authenticated by [`bearer_auth`], with a personal access token or an
+/// access token of the desktop and mobile clients. Only these skip the CSRF check.
+#[derive(Debug, Clone, Copy)]
+pub struct BearerAuthenticated;
+
+/// Authenticates requests carrying a personal access token, or the access token of the
+/// desktop and mobile clients, in `Authorization: Bearer`.
+///
+/// Must run inside the `AuthManagerLayer`, the user is set in the request [`AuthSession`]
+/// without touching the session, so no cookie is issued.
+pub async fn bearer_auth(mut req: Request, next: Next) -> Response {
+    let Some(secret) = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_owned())
+    else {
+        return next.run(req).await;
+    };
+    match resolve(&mut req, &secret).await {
+        Ok(()) => next.run(req).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn resolve(req: &mut Request, secret: &str) -> Result<(), BackendError> {
+    let auth_session = req
+        .extensions_mut()
+        .get_mut::<AuthSession>()
+        .ok_or(BackendError::InternalError)?;
+    if secret.starts_with(native_token::ACCESS_PREFIX) {
+        // desktop and mobile clients get the full permissions of the user
+        let (id, expires, signature) =
+            native_token::parse_access_token(secret).ok_or(BackendError::Unauthorized)?;
+        let user = auth_session
+            .backend
+            .get_user(&id)
+            .await?
+            .filter(|user| {
+                native_token::verify_access_token(
+                    auth_session.backend.key.signing(),
+                    user,
+                    expires,
+                    &signature,
+                )
+            })
+            .ok_or(BackendError::Unauthorized)?;
+        debug!(
+            "request authenticated with an access token of user {}",
+            user.id
+        );
+        auth_session.user = Some(user);
+        req.extensions_mut().insert(BearerAuthenticated);
+        return Ok(());
+    }
+    let (user, scopes) =
+        crate::backend::token::authenticate_token(&*auth_session.backend.tokens, secret)
+            .await?
+            .ok_or(BackendError::Unauthorized)?;
+    debug!("request authenticated with a token of user {}", user.id);
+    auth_session.user = Some(user);
+    req.extensions_mut().insert(TokenScopes(scopes));
+    req.extensions_mut().insert(BearerAuthenticated);
+    Ok(())
+}
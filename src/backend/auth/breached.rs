@@ -0,0 +1,129 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::OnceLock,
+};
+
+use sha1::{Digest, Sha1};
+use tracing::{info, instrument, warn};
+
+use crate::backend::errors::BackendError;
+
+/// The SHA-1 hashes of the most common passwords, bundled with the binary.
+static COMMON_PASSWORDS: &str = include_str!("./common-passwords.sha1");
+
+/// Shortest password accepted, the forms and [`crate::shared::user`] payloads check it too.
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+static BREACHED: OnceLock<BreachedPasswords> = OnceLock::new();
+
+/// Known breached or common passwords, stored as the sorted first 8 bytes of their SHA-1 hash.
+///
+/// Entries are one SHA-1 hex digest per line, the `HASH:COUNT` format used by the
+/// "Have I Been Pwned" downloads is also accepted.
+#[derive(Debug, Default)]
+pub struct BreachedPasswords {
+    prefixes: Vec<u64>,
+}
+
+impl BreachedPasswords {
+    /// Loads the bundled list and, if provided, the entries of the file in `path`.
+    pub fn load(path: Option<&Path>) -> std::io::Result<Self> {
+        let mut prefixes: Vec<u64> = COMMON_PASSWORDS.lines().filter_map(parse_line).collect();
+        if let Some(path) = path {
+            let reader = BufReader::new(File::open(path)?);
+            for line in reader.lines() {
+                prefixes.extend(parse_line(&line?));
+            }
+        }
+        prefixes.sort_unstable();
+        prefixes.dedup();
+        info!("loaded {} breached password entries", prefixes.len());
+        Ok(Self { prefixes })
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        self.prefixes.binary_search(&prefix(password)).is_ok()
+    }
+}
+
+/// The first 8 bytes of the SHA-1 hash of `password`.
+fn prefix(password: &str) -> u64 {
+    let digest = Sha1::digest(password.as_bytes());
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(prefix)
+}
+
+fn parse_line(line: &str) -> Option<u64> {
+    let hash = line.trim().split(':').next()?;
+    if hash.len() != 40 {
+        return None;
+    }
+    u64::from_str_radix(&hash[..16], 16).ok()
+}
+
+/// Installs the breached password list used by [`check_password`].
+pub fn init(path: Option<&Path>) -> std::io::Result<()> {
+    let list = BreachedPasswords::load(path)?;
+    if BREACHED.set(list).is_err() {
+        warn!("breached password list was already initialized");
+    }
+    Ok(())
+}
+
+/// Rejects passwords shorter than [`MIN_PASSWORD_LEN`] or found in the breached password list.
+///
+/// When [`init`] wasn't called, only the bundled list is checked.
+#[instrument(level = "debug", skip(password))]
+pub fn check_password(password: &str) -> Result<(), BackendError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(BackendError::ValidationError("frm-password.err".into()));
+    }
+    let list = BREACHED.get_or_init(|| {
+        BreachedPasswords::load(None).expect("bundled breached password list is always readable")
+    });
+    if list.contains(password) {
+        Err(BackendError::BreachedPassword)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_list_has_the_most_common_passwords() {
+        let list = BreachedPasswords::load(None).unwrap();
+        for password in [
+            "123456",
+            "qwerty",
+            "letmein",
+            "111111",
+            "password1",
+            "Passw0rd",
+        ] {
+            assert!(
+                list.contains(password),
+                "{password} isn't in the bundled list"
+            );
+        }
+        assert!(!list.contains("correct horse battery staple"));
+    }
+
+    #[test]
+    fn short_passwords_are_rejected() {
+        assert!(matches!(
+            check_password("x7#kQ2"),
+            Err(BackendError::ValidationError(_))
+        ));
+        assert!(matches!(
+            check_password("letmein123"),
+            Err(BackendError::BreachedPassword)
+        ));
+        assert!(check_password("x7#kQ2-vR9!m").is_ok());
+    }
+}
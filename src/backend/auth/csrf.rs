@@ -0,0 +1,252 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderMap, HeaderValue, Method,
+        header::{COOKIE, HOST, ORIGIN, REFERER, SET_COOKIE},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{backend::CsrfConfig, backend::errors::BackendError, csrf::CSRF_HEADER};
+
+use super::bearer::BearerAuthenticated;
+
+/// Name of the cookie holding the double-submit token.
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+/// The CSRF token of the request, rendered in the SSR document so the client can echo it back.
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub String);
+
+/// Protects the endpoints authenticated by the session cookie against cross-site requests.
+///
+/// Unsafe requests must come from a trusted origin, checked with `Origin` or else `Referer`,
+/// and when they carry cookies must echo the `csrf_token` cookie in the `X-CSRF-Token` header.
+/// Requests [`super::bearer::bearer_auth`] authenticated with a token don't rely on ambient
+/// credentials and are let through, any other `Authorization` doesn't exempt them.
+pub async fn csrf_protect(
+    State(config): State<Arc<CsrfConfig>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let jar = CookieJar::from_headers(req.headers());
+    let cookie = jar
+        .get(CSRF_COOKIE)
+        .map(|c| c.value().to_owned())
+        .filter(|v| !v.is_empty());
+
+    if let Err(e) = check(&config, &req, cookie.as_deref()) {
+        return e.into_response();
+    }
+
+    let token = cookie
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+    req.extensions_mut().insert(CsrfToken(token.clone()));
+    let mut response = next.run(req).await;
+    if cookie.is_none() {
+        let cookie = Cookie::build((CSRF_COOKIE, token))
+            .path("/")
+            .secure(config.secure_cookie)
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .build();
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            response.headers_mut().append(SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+/// Verifies the unsafe requests, unless they were authenticated with a bearer token.
+fn check(config: &CsrfConfig, req: &Request, cookie: Option<&str>) -> Result<(), BackendError> {
+    let safe = matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    if safe || req.extensions().get::<BearerAuthenticated>().is_some() {
+        return Ok(());
+    }
+    verify(config, req.headers(), cookie)
+}
+
+fn verify(
+    config: &CsrfConfig,
+    headers: &HeaderMap,
+    cookie: Option<&str>,
+) -> Result<(), BackendError> {
+    let origin = headers
+        .get(ORIGIN)
+        .or_else(|| headers.get(REFERER))
+        .and_then(|v| v.to_str().ok());
+    if let Some(origin) = origin
+        && !is_trusted(config, headers, origin)
+    {
+        warn!("rejected a request from the untrusted origin {origin}");
+        return Err(BackendError::CsrfRejected);
+    }
+    // without cookies there are no ambient credentials to abuse
+    if !headers.contains_key(COOKIE) {
+        return Ok(());
+    }
+    let header = headers.get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+    match (cookie, header) {
+        (Some(cookie), Some(header)) if constant_time_eq(cookie.as_bytes(), header.as_bytes()) => {
+            Ok(())
+        }
+        _ => {
+            warn!("rejected a request with a missing or mismatched csrf token");
+            Err(BackendError::CsrfRejected)
+        }
+    }
+}
+
+/// Checks the scheme and authority of `origin`, an `Origin` or `Referer` value,
+/// against the configured origins or else the `Host` of the request.
+fn is_trusted(config: &CsrfConfig, headers: &HeaderMap, origin: &str) -> bool {
+    let Some((scheme, rest)) = origin.split_once("://") else {
+        return false;
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if config.trusted_origins.is_empty() {
+        return headers
+            .get(HOST)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|host| host.eq_ignore_ascii_case(authority));
+    }
+    config
+        .trusted_origins
+        .iter()
+        .any(|trusted| trusted.eq_ignore_ascii_case(&format!("{scheme}://{authority}")))
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{
+            Method,
+            header::{AUTHORIZATION, COOKIE, HOST, ORIGIN, REFERER},
+        },
+    };
+
+    use super::{BearerAuthenticated, check};
+    use crate::{backend::CsrfConfig, backend::errors::BackendError, csrf::CSRF_HEADER};
+
+    const CONFIG: CsrfConfig = CsrfConfig {
+        trusted_origins: Vec::new(),
+        secure_cookie: false,
+    };
+
+    /// A request of the browser at `example.com` with its session and csrf cookies.
+    fn request(method: Method) -> axum::http::request::Builder {
+        Request::builder()
+            .method(method)
+            .uri("/api/set_user_role")
+            .header(HOST, "example.com")
+            .header(COOKIE, "id=session; csrf_token=abc")
+    }
+
+    fn rejected(result: Result<(), BackendError>) -> bool {
+        matches!(result, Err(BackendError::CsrfRejected))
+    }
+
+    #[test]
+    fn accepts_the_same_origin_with_the_token() {
+        let req = request(Method::POST)
+            .header(ORIGIN, "https://example.com")
+            .header(CSRF_HEADER, "abc")
+            .body(Body::empty())
+            .unwrap();
+        assert!(check(&CONFIG, &req, Some("abc")).is_ok());
+    }
+
+    #[test]
+    fn rejects_another_origin() {
+        let req = request(Method::POST)
+            .header(ORIGIN, "https://evil.example")
+            .header(CSRF_HEADER, "abc")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&CONFIG, &req, Some("abc"))));
+        let req = request(Method::POST)
+            .header(REFERER, "https://example.com.evil.example/form")
+            .header(CSRF_HEADER, "abc")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&CONFIG, &req, Some("abc"))));
+        let trusted = CsrfConfig {
+            trusted_origins: vec!["https://app.example.com".into()],
+            secure_cookie: false,
+        };
+        let req = request(Method::POST)
+            .header(ORIGIN, "http://app.example.com")
+            .header(CSRF_HEADER, "abc")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&trusted, &req, Some("abc"))));
+    }
+
+    #[test]
+    fn rejects_a_missing_or_mismatched_token() {
+        let req = request(Method::POST).body(Body::empty()).unwrap();
+        assert!(rejected(check(&CONFIG, &req, Some("abc"))));
+        let req = request(Method::POST)
+            .header(CSRF_HEADER, "abd")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&CONFIG, &req, Some("abc"))));
+        // the cookie was dropped, echoing a made up token doesn't help
+        let req = request(Method::POST)
+            .header(CSRF_HEADER, "abc")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&CONFIG, &req, None)));
+    }
+
+    #[test]
+    fn lets_the_safe_methods_through() {
+        for method in [Method::GET, Method::HEAD, Method::OPTIONS] {
+            let req = request(method)
+                .header(ORIGIN, "https://evil.example")
+                .body(Body::empty())
+                .unwrap();
+            assert!(check(&CONFIG, &req, Some("abc")).is_ok());
+        }
+    }
+
+    #[test]
+    fn only_bearer_authentication_skips_the_check() {
+        // e.g. sent by the browser on its own behind a basic-auth proxy
+        let req = request(Method::POST)
+            .header(ORIGIN, "https://evil.example")
+            .header(AUTHORIZATION, "Basic dXNlcjpwYXNz")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&CONFIG, &req, Some("abc"))));
+        let req = request(Method::POST)
+            .header(AUTHORIZATION, "Basic dXNlcjpwYXNz")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&CONFIG, &req, Some("abc"))));
+
+        let mut req = request(Method::POST)
+            .header(AUTHORIZATION, "Bearer dat_secret")
+            .body(Body::empty())
+            .unwrap();
+        assert!(rejected(check(&CONFIG, &req, Some("abc"))));
+        req.extensions_mut().insert(BearerAuthenticated);
+        assert!(check(&CONFIG, &req, Some("abc")).is_ok());
+    }
+}
@@ -1,16 +1,26 @@
 mod authz;
+pub mod bearer;
+pub mod breached;
+pub mod csrf;
+pub mod session;
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, OnceLock},
+};
 
 use argon2::{
-    Argon2, PasswordHash, PasswordVerifier as _Argon2Verifier,
+    Algorithm, Argon2, Params, PasswordHash, PasswordVerifier as _Argon2Verifier, Version,
     password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
 };
 use axum::{extract::FromRequestParts, http::request::Parts};
-use axum_login::{AuthUser, AuthnBackend, UserId};
-use tracing::instrument;
+use axum_login::{AuthUser, AuthnBackend, AuthzBackend, UserId};
+use tokio::sync::{OnceCell, Semaphore};
+use tracing::{error, instrument, warn};
 
-use crate::shared::user::{Credentials, User};
+use crate::shared::user::{Credentials, User, UserPermission};
 
-use super::{BackendState, errors::BackendError};
+use super::{Argon2Config, BackendState, errors::BackendError};
 
 impl AuthUser for User {
     type Id = i64;
@@ -34,41 +44,48 @@ impl AuthnBackend for BackendState {
         &self,
         creds: Self::Credentials,
     ) -> Result<Option<Self::User>, Self::Error> {
-        let client = self.db.get().await?;
-
-        let stmt = client
-            .prepare_typed_cached(
-                "SELECT id, c_at, m_at, skey, email, role, password_hash \n
-         FROM app_user \n
-         WHERE email = $1",
-                &[tokio_postgres::types::Type::TEXT],
-            )
-            .await?;
-
-        let resp = client.query_opt(&stmt, &[&creds.email]).await;
-        match resp {
-            Ok(None) => Err(BackendError::NotFound("user".into())),
-            Ok(Some(row)) => {
-                verify_password(&creds.password, row.get::<_, &str>(6))?;
-                Ok(Some(User::from(row)))
-            }
-            Err(err) => Err(err.into()),
-        }
+        let result = self.check_credentials(creds).await;
+        super::metrics::record_login(&result);
+        result
     }
 
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
-        let client = self.db.get().await?;
-
-        let stmt = client
-            .prepare_typed_cached(
-                "SELECT id, c_at, m_at, skey, email, role \n
-         FROM app_user \n
-         WHERE id = $1",
-                &[tokio_postgres::types::Type::INT8],
-            )
-            .await?;
-        let user = client.query_opt(&stmt, &[&user_id]).await?.map(User::from);
-        Ok(user)
+        self.users.get(*user_id).await
+    }
+}
+
+impl BackendState {
+    /// Checks the password of `creds`, see [`AuthnBackend::authenticate`].
+    async fn check_credentials(&self, creds: Credentials) -> Result<Option<User>, BackendError> {
+        let stored = self.users.credentials(&creds.email).await?;
+        // guests have no password, they can't log in this way
+        let Some((stored, password_hash)) =
+            stored.and_then(|s| s.password_hash.clone().map(|hash| (s, hash)))
+        else {
+            // spend the same Argon2 work as a known email, so the timing doesn't tell them apart
+            let _ = verify_password(&creds.password, dummy_hash().await?).await;
+            return Err(BackendError::InvalidCredentials);
+        };
+        verify_password(&creds.password, &password_hash)
+            .await
+            .map_err(|e| match e {
+                BackendError::ValidationError(_) => BackendError::InvalidCredentials,
+                e => e,
+            })?;
+        // only told after the password matched, so it doesn't leak which accounts are locked
+        if stored.locked {
+            return Err(BackendError::AccountLocked);
+        }
+        let user = stored.user;
+        if needs_rehash(&password_hash) {
+            // a failed rehash shouldn't block the login, it will be retried on the next one
+            if let Err(e) =
+                super::user::rehash_user_password(&*self.users, user.id, &creds.password).await
+            {
+                warn!("failed to rehash password of user {}: {e}", user.id);
+            }
+        }
+        Ok(Some(user))
     }
 }
 
@@ -77,6 +94,47 @@ pub type AuthSession = axum_login::AuthSession<BackendState>;
 #[derive(Debug, Clone)]
 pub struct SessionWrapper {
     pub session: AuthSession,
+    /// Set when the request was authenticated with a personal access token.
+    pub scopes: Option<bearer::TokenScopes>,
+    /// The cookie session, holds the login and re-authentication times.
+    pub cookie: Option<axum_login::tower_sessions::Session>,
+}
+
+impl SessionWrapper {
+    /// The permissions of `user`, restricted to the token scopes when authenticated by a token.
+    pub async fn permissions(&self, user: &User) -> Result<HashSet<UserPermission>, BackendError> {
+        let perms = self.session.backend.get_all_permissions(user).await?;
+        Ok(match &self.scopes {
+            Some(scopes) => perms.intersection(&scopes.0).copied().collect(),
+            None => perms,
+        })
+    }
+
+    /// Returns the logged user when it has the permission `perm`.
+    pub async fn require_perm(&self, perm: UserPermission) -> Result<User, BackendError> {
+        let user = self
+            .session
+            .user
+            .clone()
+            .ok_or(BackendError::LoginRequired)?;
+        if self.permissions(&user).await?.contains(&perm) {
+            Ok(user)
+        } else {
+            Err(BackendError::Forbidden)
+        }
+    }
+
+    /// Returns the logged user when the password was entered recently, for the sensitive actions.
+    pub async fn require_recent_auth(&self) -> Result<User, BackendError> {
+        let user = self
+            .session
+            .user
+            .clone()
+            .ok_or(BackendError::LoginRequired)?;
+        let cookie = self.cookie.as_ref().ok_or(BackendError::ReauthRequired)?;
+        session::require_recent(cookie, &self.session.backend.session).await?;
+        Ok(user)
+    }
 }
 
 #[derive(Debug)]
@@ -109,34 +167,138 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let session = AuthSession::from_request_parts(parts, state).await;
         match session {
-            Ok(session) => Ok(Self { session }),
+            Ok(session) => Ok(Self {
+                session,
+                scopes: parts.extensions.get::<bearer::TokenScopes>().cloned(),
+                cookie: parts
+                    .extensions
+                    .get::<axum_login::tower_sessions::Session>()
+                    .cloned(),
+            }),
             Err(_) => Err(StateError),
         }
     }
 }
 
+struct PasswordHashing {
+    params: Params,
+    permits: Arc<Semaphore>,
+}
+
+static PASSWORD_HASHING: OnceLock<PasswordHashing> = OnceLock::new();
+static DUMMY_HASH: OnceCell<String> = OnceCell::const_new();
+
+fn password_hashing() -> &'static PasswordHashing {
+    PASSWORD_HASHING.get_or_init(|| PasswordHashing {
+        params: Params::default(),
+        permits: Arc::new(Semaphore::new(
+            std::thread::available_parallelism().map_or(1, usize::from),
+        )),
+    })
+}
+
+/// Sets the Argon2 cost parameters and the number of hashes allowed to run at the same time.
+pub fn init_password_hashing(config: &Argon2Config) -> Result<(), argon2::Error> {
+    let params = Params::new(
+        config.memory_kib,
+        config.iterations,
+        config.parallelism,
+        None,
+    )?;
+    let hashing = PasswordHashing {
+        params,
+        permits: Arc::new(Semaphore::new(config.max_blocking)),
+    };
+    if PASSWORD_HASHING.set(hashing).is_err() {
+        warn!("password hashing was already initialized");
+    }
+    Ok(())
+}
+
+/// Runs `f` on tokio's blocking pool, bounded by the configured number of permits,
+/// so Argon2 work doesn't stall the async worker threads.
+async fn run_argon2<T, F>(operation: &'static str, f: F) -> Result<T, BackendError>
+where
+    T: Send + 'static,
+    F: FnOnce(Argon2<'static>) -> T + Send + 'static,
+{
+    let hashing = password_hashing();
+    let permit = hashing.permits.clone().acquire_owned().await.map_err(|e| {
+        error!("password hashing semaphore closed: {e}");
+        BackendError::InternalError
+    })?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, hashing.params.clone());
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let start = std::time::Instant::now();
+        let result = f(argon2);
+        super::metrics::record_argon2(operation, start);
+        result
+    })
+    .await
+    .map_err(|e| {
+        error!("password hashing task failed: {e}");
+        BackendError::InternalError
+    })
+}
+
 /// Hashes a plain-text password using Argon2.
 ///
-/// This function takes a password string and returns its Argon2 hash.
+/// This function takes a password string and returns its Argon2 hash,
+/// computed with the configured parameters.
 #[instrument(level = "debug", skip(password))]
-pub fn hash_password(password: &str) -> Result<String, BackendError> {
-    let argon2 = Argon2::default();
-    let salt = SaltString::generate(&mut OsRng);
-    Ok(argon2
-        .hash_password(password.as_bytes(), &salt)
-        .map_err(|e| BackendError::AuthError(format!("Failed to hash password: {e}")))?
-        .to_string())
+pub async fn hash_password(password: &str) -> Result<String, BackendError> {
+    let password = password.to_owned();
+    run_argon2("hash", move |argon2| {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(argon2
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| BackendError::AuthError(format!("Failed to hash password: {e}")))?
+            .to_string())
+    })
+    .await?
 }
 
 /// Verifies a plain-text password against a stored Argon2 hash.
 ///
 /// This function is used during the login process to check if the provided password
-/// matches the stored hash.
+/// matches the stored hash. The parameters are read from the hash itself.
 #[instrument(level = "debug", skip(password, password_hash))]
-pub fn verify_password(password: &str, password_hash: &str) -> Result<(), BackendError> {
-    let argon2 = Argon2::default();
-    let parsed_hash = PasswordHash::new(password_hash)
-        .map_err(|e| BackendError::AuthError(format!("Failed to parse password hash: {e}")))?;
+pub async fn verify_password(password: &str, password_hash: &str) -> Result<(), BackendError> {
+    let password = password.to_owned();
+    let password_hash = password_hash.to_owned();
+    run_argon2("verify", move |argon2| {
+        let parsed_hash = PasswordHash::new(&password_hash)
+            .map_err(|e| BackendError::AuthError(format!("Failed to parse password hash: {e}")))?;
 
-    Ok(argon2.verify_password(password.as_bytes(), &parsed_hash)?)
+        Ok(argon2.verify_password(password.as_bytes(), &parsed_hash)?)
+    })
+    .await?
+}
+
+/// A hash of a random password with the configured parameters, verified against when
+/// the email is unknown.
+async fn dummy_hash() -> Result<&'static str, BackendError> {
+    DUMMY_HASH
+        .get_or_try_init(|| async { hash_password(&uuid::Uuid::new_v4().to_string()).await })
+        .await
+        .map(String::as_str)
+}
+
+/// Checks if a stored hash was computed with other parameters than the configured ones.
+pub fn needs_rehash(password_hash: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(password_hash) else {
+        return true;
+    };
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    let current = &password_hashing().params;
+    Params::try_from(&parsed_hash).map_or(true, |params| {
+        params.m_cost() != current.m_cost()
+            || params.t_cost() != current.t_cost()
+            || params.p_cost() != current.p_cost()
+    })
 }
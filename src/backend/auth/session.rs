@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use axum_login::tower_sessions::{Expiry, Session, cookie::time};
+use chrono::Utc;
+use tracing::{info, instrument, warn};
+
+use super::AuthSession;
+use crate::backend::{SessionConfig, errors::BackendError};
+
+/// Unix timestamp of the login, the absolute session age is counted from it.
+const STARTED_AT: &str = "auth.started_at";
+/// Unix timestamp of the last time the password was entered, for the sensitive actions.
+const REAUTH_AT: &str = "auth.reauth_at";
+
+impl SessionConfig {
+    /// The sliding expiry of a new session, longer when the user asked to be remembered.
+    pub fn expiry(&self, remember: bool) -> Expiry {
+        if remember {
+            Expiry::OnInactivity(time::Duration::days(self.remember_days))
+        } else {
+            Expiry::OnInactivity(time::Duration::minutes(self.idle_minutes))
+        }
+    }
+}
+
+/// Applies the expiry policy to a session that was just logged in.
+#[instrument(name = "Session: start", level = "info", skip(session, config))]
+pub async fn start(
+    session: &Session,
+    config: &SessionConfig,
+    remember: bool,
+) -> Result<(), BackendError> {
+    session.set_expiry(Some(config.expiry(remember)));
+    let now = Utc::now().timestamp();
+    session.insert(STARTED_AT, now).await?;
+    session.insert(REAUTH_AT, now).await?;
+    Ok(())
+}
+
+/// Records that the password was just entered again.
+#[instrument(name = "Session: reauthenticate", level = "info", skip(session))]
+pub async fn confirm(session: &Session) -> Result<(), BackendError> {
+    session.insert(REAUTH_AT, Utc::now().timestamp()).await?;
+    Ok(())
+}
+
+/// Fails with [`BackendError::ReauthRequired`] unless the password was entered in the
+/// last `reauth_minutes`.
+///
+/// Requests authenticated by a token have no session, so they are always rejected.
+pub async fn require_recent(session: &Session, config: &SessionConfig) -> Result<(), BackendError> {
+    let reauth_at = session.get::<i64>(REAUTH_AT).await?.unwrap_or_default();
+    if reauth_at + config.reauth_minutes * 60 > Utc::now().timestamp() {
+        Ok(())
+    } else {
+        Err(BackendError::ReauthRequired)
+    }
+}
+
+/// Seconds until the session expires, by inactivity or by reaching its maximum age.
+pub async fn expires_in(session: &Session, config: &SessionConfig) -> Result<i64, BackendError> {
+    let idle = session.expiry_age().whole_seconds();
+    let started = session
+        .get::<i64>(STARTED_AT)
+        .await?
+        .unwrap_or_else(|| Utc::now().timestamp());
+    let absolute = started + config.max_age_hours * 3600 - Utc::now().timestamp();
+    Ok(idle.min(absolute).max(0))
+}
+
+/// Logs out sessions older than the maximum age, however active they were.
+///
+/// Must run inside the `AuthManagerLayer` and before the bearer authentication,
+/// token requests have no session to expire.
+pub async fn session_policy(
+    State(config): State<Arc<SessionConfig>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let session = req.extensions().get::<Session>().cloned();
+    if let Some(session) = session
+        && let Some(auth_session) = req.extensions_mut().get_mut::<AuthSession>()
+        && let Some(user) = auth_session.user.as_ref().map(|user| user.id)
+    {
+        let now = Utc::now().timestamp();
+        match session.get::<i64>(STARTED_AT).await {
+            Ok(Some(started)) if started + config.max_age_hours * 3600 <= now => {
+                info!("Session of user {user} reached its maximum age");
+                if let Err(e) = auth_session.logout().await {
+                    warn!("failed to log out an expired session: {e}");
+                }
+            }
+            Ok(Some(_)) => {}
+            // logged in before the policy existed, the age is counted from now
+            Ok(None) => {
+                if let Err(e) = session.insert(STARTED_AT, now).await {
+                    warn!("failed to stamp the session start: {e}");
+                }
+            }
+            Err(e) => warn!("failed to read the session start: {e}"),
+        }
+    }
+    next.run(req).await
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::shared::challenge::{ChallengeSolution, RegistrationChallenge, is_solution};
+
+use super::{
+    BackendState,
+    errors::BackendError,
+    repository::{ChallengeNonce, ChallengeRepository},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn challenge_signature(key: &[u8], payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// The difficulty grows by one bit for every `signups_per_step` accounts created in the last hour,
+/// guests don't solve a challenge so they aren't counted.
+#[instrument(name = "Challenge: difficulty", level = "debug", skip(state))]
+async fn current_difficulty(state: &BackendState) -> Result<u32, BackendError> {
+    let config = &state.challenge;
+    let recent = state.challenges.recent_signups().await?;
+    let steps =
+        u32::try_from(recent / i64::from(config.signups_per_step.max(1))).unwrap_or(u32::MAX);
+    Ok(config
+        .base_difficulty
+        .saturating_add(steps)
+        .min(config.max_difficulty))
+}
+
+/// Issues a challenge signed with the state key, nothing is stored until it's redeemed.
+#[instrument(name = "Challenge: issue", level = "info", skip(state))]
+pub async fn issue_challenge(state: &BackendState) -> Result<RegistrationChallenge, BackendError> {
+    let difficulty = current_difficulty(state).await?;
+    let expires_at = Utc::now() + Duration::minutes(state.challenge.minutes);
+    Ok(RegistrationChallenge {
+        token: sign_token(state.key.signing(), difficulty, expires_at),
+        difficulty,
+        expires_at,
+    })
+}
+
+/// A token with a new nonce, `{nonce}.{difficulty}.{expiry}.{signature}`.
+fn sign_token(key: &[u8], difficulty: u32, expires_at: DateTime<Utc>) -> String {
+    let payload = format!(
+        "{}.{difficulty}.{}",
+        Uuid::new_v4().simple(),
+        expires_at.timestamp()
+    );
+    let signature: String = challenge_signature(key, &payload)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("{payload}.{signature}")
+}
+
+/// Splits a challenge token into the signed payload, the nonce, the difficulty,
+/// the expiry and the signature.
+fn parse_token(token: &str) -> Option<(&str, &str, u32, i64, Vec<u8>)> {
+    let (payload, signature) = token.rsplit_once('.')?;
+    let mut parts = payload.splitn(3, '.');
+    let nonce = parts.next()?;
+    let difficulty = parts.next()?.parse().ok()?;
+    let expires = parts.next()?.parse().ok()?;
+    if signature.len() % 2 != 0 {
+        return None;
+    }
+    let signature = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some((payload, nonce, difficulty, expires, signature))
+}
+
+/// Checks the signature, the expiry and the proof-of-work of the solution.
+///
+/// The returned nonce still has to be redeemed, with the registration or on its own.
+pub fn verify_solution(
+    key: &[u8],
+    solution: &ChallengeSolution,
+) -> Result<ChallengeNonce, BackendError> {
+    let invalid = || BackendError::ValidationError("challenge.invalid".into());
+    let (payload, nonce, difficulty, expires, signature) =
+        parse_token(&solution.token).ok_or_else(invalid)?;
+    challenge_signature(key, payload)
+        .verify_slice(&signature)
+        .map_err(|_| invalid())?;
+    let expires_at = DateTime::from_timestamp(expires, 0).ok_or_else(invalid)?;
+    if expires_at <= Utc::now() {
+        return Err(BackendError::ValidationError("challenge.expired".into()));
+    }
+    if !is_solution(&solution.token, solution.counter, difficulty) {
+        warn!("rejected a wrong challenge solution");
+        return Err(invalid());
+    }
+    Ok(ChallengeNonce {
+        nonce: nonce.to_owned(),
+        expires_at,
+    })
+}
+
+/// Checks the solution and marks the challenge as used, so it can't be replayed.
+///
+/// A registration redeems it with the account instead, see [`verify_solution`].
+#[instrument(name = "Challenge: redeem", level = "info", skip_all)]
+pub async fn redeem_challenge(
+    challenges: &dyn ChallengeRepository,
+    key: &[u8],
+    solution: &ChallengeSolution,
+) -> Result<(), BackendError> {
+    let nonce = verify_solution(key, solution)?;
+    if !challenges.redeem(&nonce).await? {
+        warn!("rejected a replayed challenge");
+        return Err(BackendError::ValidationError("challenge.invalid".into()));
+    }
+    info!("Challenge redeemed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{parse_token, redeem_challenge, sign_token, verify_solution};
+    use crate::backend::{errors::BackendError, repository::Repositories};
+    use crate::shared::challenge::{ChallengeSolution, is_solution};
+
+    const KEY: &[u8] = &[5; 32];
+
+    fn solve(token: String, difficulty: u32) -> ChallengeSolution {
+        let counter = (0..)
+            .find(|&counter| is_solution(&token, counter, difficulty))
+            .unwrap();
+        ChallengeSolution { token, counter }
+    }
+
+    fn rejection<T: std::fmt::Debug>(result: Result<T, BackendError>) -> String {
+        match result {
+            Err(BackendError::ValidationError(message)) => message,
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn verifies_a_signed_solution() {
+        let expires_at = Utc::now() + Duration::minutes(5);
+        let solution = solve(sign_token(KEY, 8, expires_at), 8);
+        let nonce = verify_solution(KEY, &solution).unwrap();
+        let (_, token_nonce, difficulty, _, _) = parse_token(&solution.token).unwrap();
+        assert_eq!(nonce.nonce, token_nonce);
+        assert_eq!(difficulty, 8);
+        assert_eq!(nonce.expires_at.timestamp(), expires_at.timestamp());
+
+        assert_eq!(
+            rejection(verify_solution(&[6; 32], &solution)),
+            "challenge.invalid"
+        );
+    }
+
+    #[test]
+    fn rejects_a_tampered_difficulty() {
+        let token = sign_token(KEY, 20, Utc::now() + Duration::minutes(5));
+        let (nonce, rest) = token.split_once(".20.").unwrap();
+        // any counter solves a difficulty of 0, only the signature stops it
+        let solution = ChallengeSolution {
+            token: format!("{nonce}.0.{rest}"),
+            counter: 0,
+        };
+        assert_eq!(
+            rejection(verify_solution(KEY, &solution)),
+            "challenge.invalid"
+        );
+    }
+
+    #[test]
+    fn rejects_a_wrong_or_malformed_solution() {
+        let token = sign_token(KEY, 32, Utc::now() + Duration::minutes(5));
+        let (counter, _) = (0..)
+            .map(|counter| (counter, is_solution(&token, counter, 32)))
+            .find(|(_, solved)| !solved)
+            .unwrap();
+        let wrong = ChallengeSolution { token, counter };
+        assert_eq!(rejection(verify_solution(KEY, &wrong)), "challenge.invalid");
+
+        for token in ["", "nonce.1.2", "nonce.1.2.zz", "nonce.x.2.00"] {
+            let malformed = ChallengeSolution {
+                token: token.into(),
+                counter: 0,
+            };
+            assert_eq!(
+                rejection(verify_solution(KEY, &malformed)),
+                "challenge.invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_an_expired_challenge() {
+        let solution = solve(sign_token(KEY, 0, Utc::now() - Duration::seconds(1)), 0);
+        assert_eq!(
+            rejection(verify_solution(KEY, &solution)),
+            "challenge.expired"
+        );
+    }
+
+    #[tokio::test]
+    async fn redeems_a_challenge_once() {
+        let repositories = Repositories::memory();
+        let solution = solve(sign_token(KEY, 4, Utc::now() + Duration::minutes(5)), 4);
+        redeem_challenge(&*repositories.challenges, KEY, &solution)
+            .await
+            .unwrap();
+        assert_eq!(
+            rejection(redeem_challenge(&*repositories.challenges, KEY, &solution).await),
+            "challenge.invalid"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{Extensions, HeaderMap, header::USER_AGENT, request::Parts},
+};
+
+use super::BackendState;
+
+/// Resolves the client ip, from `X-Forwarded-For` behind `trusted_proxies` proxies,
+/// otherwise from the connection.
+///
+/// Every proxy appends the address it was reached from, so only the last `trusted_proxies`
+/// entries can be trusted, the client can put anything before them.
+pub fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trusted_proxies: usize,
+) -> Option<IpAddr> {
+    let forwarded = trusted_proxies
+        .checked_sub(1)
+        .and_then(|hops| forwarded_for(headers).into_iter().rev().nth(hops))
+        .and_then(|ip| ip.parse().ok());
+    forwarded.or_else(|| {
+        extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip())
+    })
+}
+
+/// The entries of every `X-Forwarded-For` header, in the order they were added.
+fn forwarded_for(headers: &HeaderMap) -> Vec<&str> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+/// Where a request comes from, used to tell known devices apart.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let trusted_proxies = parts
+            .extensions
+            .get::<BackendState>()
+            .map_or(0, |state| state.trusted_proxies);
+        Ok(Self {
+            ip: client_ip(&parts.headers, &parts.extensions, trusted_proxies),
+            user_agent: parts
+                .headers
+                .get(USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        extract::ConnectInfo,
+        http::{Extensions, HeaderMap, HeaderValue},
+    };
+
+    use super::client_ip;
+
+    fn request(forwarded_for: &[&str]) -> (HeaderMap, Extensions) {
+        let mut headers = HeaderMap::new();
+        for value in forwarded_for {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+        (headers, extensions)
+    }
+
+    #[test]
+    fn ignores_the_header_without_trusted_proxies() {
+        let (headers, extensions) = request(&["203.0.113.7"]);
+
+        assert_eq!(
+            client_ip(&headers, &extensions, 0),
+            Some([10, 0, 0, 1].into())
+        );
+    }
+
+    #[test]
+    fn takes_the_entry_added_by_the_outermost_trusted_proxy() {
+        let (headers, extensions) = request(&["1.1.1.1, 203.0.113.7", "198.51.100.2"]);
+
+        assert_eq!(
+            client_ip(&headers, &extensions, 1),
+            Some([198, 51, 100, 2].into())
+        );
+        assert_eq!(
+            client_ip(&headers, &extensions, 2),
+            Some([203, 0, 113, 7].into())
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_connection_when_the_chain_is_short() {
+        let (headers, extensions) = request(&["203.0.113.7"]);
+
+        assert_eq!(
+            client_ip(&headers, &extensions, 2),
+            Some([10, 0, 0, 1].into())
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Layered settings: a TOML file, then the environment, then the `--set` flags.
+//!
+//! Every setting is named after its environment variable, e.g. `POSTGRES_HOST`. In the file
+//! the tables prefix their keys, so `host` in `[postgres]` is `POSTGRES_HOST` too, and the
+//! flags take either form, `--set postgres.host=db`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// File read when `--config` isn't given, skipped when it doesn't exist.
+const DEFAULT_FILE: &str = "config.toml";
+
+#[derive(Debug, Default, Clone, clap::Parser)]
+pub struct ConfigArgs {
+    /// TOML file with the settings, `config.toml` when it exists.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Overrides a setting, e.g. `--set postgres.pool_size=40`.
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    File(PathBuf),
+    Env,
+    Flag,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "from {}", path.display()),
+            Self::Env => f.write_str("from the environment"),
+            Self::Flag => f.write_str("from --set"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub key: String,
+    pub origin: Option<Origin>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.origin {
+            Some(origin) => write!(f, "{} ({origin}): {}", self.key, self.message),
+            None => write!(f, "{}: {}", self.key, self.message),
+        }
+    }
+}
+
+/// Every problem found while loading the settings.
+#[derive(Debug, Clone)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl std::error::Error for ConfigErrors {}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `postgres.pool-size` and `POSTGRES_POOL_SIZE` are the same setting.
+fn normalize(key: &str) -> String {
+    key.trim().replace(['.', '-'], "_").to_ascii_uppercase()
+}
+
+/// Flattens the tables of the file, arrays are joined by commas like the lists in the environment.
+fn flatten(
+    prefix: &str,
+    table: toml::Table,
+    values: &mut HashMap<String, String>,
+    errors: &mut Vec<String>,
+) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            normalize(&key)
+        } else {
+            normalize(&format!("{prefix}_{key}"))
+        };
+        let scalar = |value: toml::Value| match value {
+            toml::Value::String(s) => Some(s),
+            toml::Value::Integer(_)
+            | toml::Value::Float(_)
+            | toml::Value::Boolean(_)
+            | toml::Value::Datetime(_) => Some(value.to_string()),
+            toml::Value::Array(_) | toml::Value::Table(_) => None,
+        };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, values, errors),
+            toml::Value::Array(items) => {
+                match items.into_iter().map(scalar).collect::<Option<Vec<_>>>() {
+                    Some(items) => {
+                        values.insert(key, items.join(","));
+                    }
+                    None => errors.push(key),
+                }
+            }
+            value => {
+                values.insert(key, scalar(value).unwrap_or_default());
+            }
+        }
+    }
+}
+
+/// The settings of every layer, read by key and validated as they're read.
+///
+/// Problems are collected instead of returned, so they can all be reported at once.
+#[derive(Debug, Default)]
+pub struct Settings {
+    file: Option<PathBuf>,
+    file_values: HashMap<String, String>,
+    flag_values: HashMap<String, String>,
+    origins: HashMap<String, Origin>,
+    read: HashSet<String>,
+    errors: Vec<ConfigError>,
+}
+
+impl Settings {
+    pub fn load(args: &ConfigArgs) -> Self {
+        let mut settings = Self::default();
+        // `.env` only fills the environment, it's optional
+        let _ = dotenvy::dotenv();
+
+        let (path, explicit) = match &args.config {
+            Some(path) => (path.clone(), true),
+            None => (PathBuf::from(DEFAULT_FILE), false),
+        };
+        if explicit || path.exists() {
+            settings.load_file(&path);
+        }
+        for entry in &args.overrides {
+            match entry.split_once('=') {
+                Some((key, value)) => {
+                    settings
+                        .flag_values
+                        .insert(normalize(key), value.trim().to_owned());
+                }
+                None => settings.errors.push(ConfigError {
+                    key: entry.clone(),
+                    origin: Some(Origin::Flag),
+                    message: "expected KEY=VALUE".into(),
+                }),
+            }
+        }
+        settings
+    }
+
+    fn load_file(&mut self, path: &Path) {
+        let origin = Some(Origin::File(path.to_owned()));
+        let table = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| content.parse::<toml::Table>().map_err(|e| e.to_string()));
+        match table {
+            Ok(table) => {
+                let mut nested = Vec::new();
+                flatten("", table, &mut self.file_values, &mut nested);
+                for key in nested {
+                    self.errors.push(ConfigError {
+                        key,
+                        origin: origin.clone(),
+                        message: "arrays can only hold strings, numbers and booleans".into(),
+                    });
+                }
+                self.file = Some(path.to_owned());
+            }
+            Err(message) => self.errors.push(ConfigError {
+                key: "config".into(),
+                origin,
+                message,
+            }),
+        }
+    }
+
+    /// The value of `key` from the highest layer setting it, empty values count as unset.
+    fn raw(&mut self, key: &str) -> Option<String> {
+        self.read.insert(key.to_owned());
+        let (value, origin) = if let Some(value) = self.flag_values.get(key) {
+            (value.clone(), Origin::Flag)
+        } else if let Ok(value) = std::env::var(key) {
+            (value, Origin::Env)
+        } else if let (Some(value), Some(file)) = (self.file_values.get(key), &self.file) {
+            (value.clone(), Origin::File(file.clone()))
+        } else {
+            return None;
+        };
+        if value.trim().is_empty() {
+            return None;
+        }
+        self.origins.insert(key.to_owned(), origin);
+        Some(value)
+    }
+
+    /// Records a problem with `key`, e.g. a value out of range.
+    pub fn error(&mut self, key: &str, message: impl Into<String>) {
+        self.errors.push(ConfigError {
+            key: key.to_owned(),
+            origin: self.origins.get(key).cloned(),
+            message: message.into(),
+        });
+    }
+
+    /// Records a problem with `key` unless `valid`.
+    pub fn check(&mut self, key: &str, valid: bool, message: &str) {
+        if !valid {
+            self.error(key, message);
+        }
+    }
+
+    /// Parses `key` with `parse`, `None` when it's unset or invalid.
+    pub fn parse_with<T, E: fmt::Display>(
+        &mut self,
+        key: &str,
+        parse: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Option<T> {
+        let value = self.raw(key)?;
+        match parse(value.trim()) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.error(key, format!("invalid value {value:?}: {e}"));
+                None
+            }
+        }
+    }
+
+    /// Parses `key` with `parse`, recording it as missing when unset.
+    pub fn required_with<T, E: fmt::Display>(
+        &mut self,
+        key: &str,
+        parse: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Option<T> {
+        if self.raw(key).is_none() {
+            self.error(key, "missing");
+            return None;
+        }
+        self.parse_with(key, parse)
+    }
+
+    pub fn optional<T: std::str::FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        self.parse_with(key, str::parse)
+    }
+
+    pub fn or<T: std::str::FromStr>(&mut self, key: &str, default: T) -> T
+    where
+        T::Err: fmt::Display,
+    {
+        self.optional(key).unwrap_or(default)
+    }
+
+    /// Parses `key`, recording it as missing when unset. The default only stands in until
+    /// [`Settings::finish`] reports the problem.
+    pub fn required<T: std::str::FromStr + Default>(&mut self, key: &str) -> T
+    where
+        T::Err: fmt::Display,
+    {
+        if self.raw(key).is_none() {
+            self.error(key, "missing");
+            return T::default();
+        }
+        self.or(key, T::default())
+    }
+
+    /// A comma separated list, empty items are dropped.
+    pub fn list(&mut self, key: &str) -> Vec<String> {
+        self.raw(key)
+            .map(|v| {
+                v.split(',')
+                    .map(|item| item.trim().to_owned())
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Fails with every problem found, including the settings of the file and flags
+    /// that were never read, likely typos.
+    pub fn finish(mut self) -> Result<(), ConfigErrors> {
+        let file = self.file.clone();
+        let mut unknown: Vec<ConfigError> = self
+            .file_values
+            .keys()
+            .filter(|key| !self.read.contains(*key))
+            .map(|key| ConfigError {
+                key: key.clone(),
+                origin: file.clone().map(Origin::File),
+                message: "unknown setting".into(),
+            })
+            .chain(
+                self.flag_values
+                    .keys()
+                    .filter(|key| !self.read.contains(*key))
+                    .map(|key| ConfigError {
+                        key: key.clone(),
+                        origin: Some(Origin::Flag),
+                        message: "unknown setting".into(),
+                    }),
+            )
+            .collect();
+        unknown.sort_by(|a, b| a.key.cmp(&b.key));
+        self.errors.extend(unknown);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(self.errors))
+        }
+    }
+}
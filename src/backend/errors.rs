@@ -27,10 +27,20 @@ pub enum BackendError {
     Forbidden,
     #[error("login.required")]
     LoginRequired,
+    #[error("login.invalid")]
+    InvalidCredentials,
+    #[error("login.locked")]
+    AccountLocked,
+    #[error("reauth.required")]
+    ReauthRequired,
     #[error("duplicate")]
     UniqueConstraintViolation,
     #[error("frm-email.duplicate")]
     DuplicateUser,
+    #[error("frm-password.breached")]
+    BreachedPassword,
+    #[error("csrf.rejected")]
+    CsrfRejected,
 }
 
 // Implement `IntoResponse` for `BackendError` to convert it into an Axum response.
@@ -57,6 +67,11 @@ impl IntoResponse for BackendError {
                 (StatusCode::BAD_REQUEST, "duplicate".to_string())
             }
             BackendError::DuplicateUser => (StatusCode::BAD_REQUEST, "duplicate user".to_string()),
+            BackendError::BreachedPassword => {
+                warn!("Rejected a breached password.");
+                (StatusCode::BAD_REQUEST, "frm-password.breached".to_string())
+            }
+            BackendError::CsrfRejected => (StatusCode::FORBIDDEN, "csrf.rejected".to_string()),
             BackendError::Unauthorized => {
                 error!("Unauthorized access attempt.");
                 (StatusCode::UNAUTHORIZED, "unauthorized".to_string())
@@ -65,6 +80,15 @@ impl IntoResponse for BackendError {
                 warn!("Login required.");
                 (StatusCode::UNAUTHORIZED, "unauthorized.login".to_string())
             }
+            BackendError::InvalidCredentials => {
+                warn!("Invalid credentials.");
+                (StatusCode::UNAUTHORIZED, "login.invalid".to_string())
+            }
+            BackendError::AccountLocked => {
+                warn!("Login attempt on a locked account.");
+                (StatusCode::FORBIDDEN, "login.locked".to_string())
+            }
+            BackendError::ReauthRequired => (StatusCode::FORBIDDEN, "reauth.required".to_string()),
             BackendError::Forbidden => {
                 error!("Forbidden access attempt.");
                 (StatusCode::FORBIDDEN, "forbidden".to_string())
@@ -113,6 +137,13 @@ impl From<tokio_postgres::Error> for BackendError {
     }
 }
 
+impl From<axum_login::tower_sessions::session::Error> for BackendError {
+    fn from(error: axum_login::tower_sessions::session::Error) -> Self {
+        error!("Session Error: {:?}", error);
+        BackendError::InternalError
+    }
+}
+
 impl From<deadpool_postgres::PoolError> for BackendError {
     fn from(error: deadpool_postgres::PoolError) -> Self {
         error!("Database Error: {:?}", error);
@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{
+        HeaderValue,
+        header::{
+            CONTENT_LENGTH, CONTENT_SECURITY_POLICY, CONTENT_TYPE, REFERRER_POLICY,
+            STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+        },
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::error;
+use uuid::Uuid;
+
+use super::{SecurityHeadersConfig, errors::BackendError};
+
+/// Nonce of the request `Content-Security-Policy`, inline scripts must carry it to run.
+#[derive(Debug, Clone)]
+pub struct CspNonce(pub String);
+
+/// Script Dioxus renders right inside the app root, before the body, see `dioxus_fullstack::render`.
+const STREAMING_INIT: &str = "<script>window.hydrate_queue=";
+/// Marks where the body rendered by the app ends.
+const HYDRATION_DATA: &str = "<script>window.initial_dioxus_hydration_data=";
+/// Largest HTML response buffered to stamp the nonce, server rendered pages are far smaller.
+const MAX_HTML_BYTES: usize = 4 * 1024 * 1024;
+
+/// Adds the security headers to every response, including a `Content-Security-Policy`
+/// that only allows inline scripts carrying the per-request [`CspNonce`].
+///
+/// Dioxus renders its streaming and hydration scripts outside of the app root without a nonce,
+/// so HTML responses are buffered to stamp it on them. Scripts inside the app root are left as
+/// rendered, components must set the nonce themselves. Dioxus has no hook for the nonce, the
+/// tests render its template to catch markers that stop matching.
+pub async fn security_headers(
+    State(config): State<Arc<SecurityHeadersConfig>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let nonce = Uuid::new_v4().simple().to_string();
+    req.extensions_mut().insert(CspNonce(nonce.clone()));
+    let response = next.run(req).await;
+
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    let mut response = if is_html {
+        match stamp_response(response, &nonce).await {
+            Ok(response) => response,
+            Err(e) => return e.into_response(),
+        }
+    } else {
+        response
+    };
+
+    let headers = response.headers_mut();
+    let csp = format!(
+        "default-src 'self'; \
+        script-src 'self' 'nonce-{nonce}' 'wasm-unsafe-eval'; \
+        style-src 'self' 'unsafe-inline'; \
+        img-src 'self' data:; \
+        object-src 'none'; \
+        base-uri 'self'; \
+        form-action 'self'; \
+        frame-ancestors 'none'"
+    );
+    if let Ok(value) = HeaderValue::from_str(&csp) {
+        headers.insert(CONTENT_SECURITY_POLICY, value);
+    }
+    if config.hsts_max_age > 0 {
+        let hsts = format!("max-age={}; includeSubDomains", config.hsts_max_age);
+        if let Ok(value) = HeaderValue::from_str(&hsts) {
+            headers.insert(STRICT_TRANSPORT_SECURITY, value);
+        }
+    }
+    headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(
+        REFERRER_POLICY,
+        HeaderValue::from_static("strict-origin-when-cross-origin"),
+    );
+    headers.insert(
+        "permissions-policy",
+        HeaderValue::from_static("camera=(), microphone=(), geolocation=(), payment=(), usb=()"),
+    );
+    response
+}
+
+async fn stamp_response(response: Response, nonce: &str) -> Result<Response, BackendError> {
+    let (mut parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_HTML_BYTES)
+        .await
+        .map_err(|e| {
+            error!(
+                "failed to buffer the html response, over {MAX_HTML_BYTES} bytes or broken: {e}"
+            );
+            BackendError::InternalError
+        })?;
+    let Ok(html) = std::str::from_utf8(&bytes) else {
+        return Ok(Response::from_parts(parts, Body::from(bytes)));
+    };
+    let html = stamp_nonce(html, nonce);
+    parts.headers.remove(CONTENT_LENGTH);
+    Ok(Response::from_parts(parts, Body::from(html)))
+}
+
+/// Adds the nonce to the `<script>` tags rendered by Dioxus around the app body.
+fn stamp_nonce(html: &str, nonce: &str) -> String {
+    let root = html
+        .find(r#"id="main""#)
+        .and_then(|start| html[start..].find('>').map(|end| start + end + 1));
+    let (Some(mut root), Some(body_end)) = (root, html.rfind(HYDRATION_DATA)) else {
+        return html.to_owned();
+    };
+    if html[root..].starts_with(STREAMING_INIT) {
+        root += "<script>".len();
+    }
+    if body_end < root {
+        return html.to_owned();
+    }
+    let mut stamped = String::with_capacity(html.len() + 64);
+    stamp_scripts(&mut stamped, &html[..root], nonce);
+    stamped.push_str(&html[root..body_end]);
+    stamp_scripts(&mut stamped, &html[body_end..], nonce);
+    stamped
+}
+
+fn stamp_scripts(to: &mut String, html: &str, nonce: &str) {
+    let mut rest = html;
+    while let Some(start) = rest.find("<script") {
+        let (before, after) = rest.split_at(start + "<script".len());
+        to.push_str(before);
+        if after.starts_with(['>', ' ', '\n', '\t']) && !after.starts_with(" nonce=") {
+            to.push_str(&format!(r#" nonce="{nonce}""#));
+        }
+        rest = after;
+    }
+    to.push_str(rest);
+}
+
+#[cfg(test)]
+mod tests {
+    use dioxus::prelude::*;
+
+    use super::stamp_nonce;
+
+    /// Renders a page with the template Dioxus serves, so the markers break loudly when it changes.
+    fn render_page(body: &str) -> String {
+        let index = r#"<!DOCTYPE html><html><head><title>app</title></head><body><div id="main"></div><script src="/wasm/app.js"></script></body></html>"#;
+        let config = ServeConfigBuilder::default()
+            .index_html(index.to_owned())
+            .build()
+            .expect("the index has a main element");
+        let mut dom = VirtualDom::new(|| rsx! {});
+        dom.rebuild_in_place();
+        let mut html = String::new();
+        FullstackHTMLTemplate::new(&config)
+            .wrap_body(&mut html, &dom, body)
+            .expect("the page renders");
+        html
+    }
+
+    #[test]
+    fn stamps_the_scripts_dioxus_renders() {
+        let html = stamp_nonce(&render_page("<p>hello</p>"), "abc");
+
+        assert!(html.contains(r#"<script nonce="abc">window.hydrate_queue="#));
+        assert!(html.contains(r#"<script nonce="abc">window.initial_dioxus_hydration_data="#));
+        assert!(html.contains(r#"<script nonce="abc" src="/wasm/app.js">"#));
+    }
+
+    #[test]
+    fn leaves_the_scripts_of_the_app_alone() {
+        let html = stamp_nonce(&render_page("<script>alert(1)</script>"), "abc");
+
+        assert!(html.contains("<script>alert(1)</script>"));
+    }
+}
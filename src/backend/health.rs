@@ -0,0 +1,111 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::get};
+use serde::Serialize;
+use tracing::warn;
+
+use super::BackendState;
+
+/// How long a probe waits for the database.
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+struct Health {
+    state: BackendState,
+    draining: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    database: bool,
+    migrations: bool,
+    permissions: bool,
+    draining: bool,
+}
+
+/// The process is up, it says nothing about its dependencies.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Whether the instance should receive traffic: a connection can be checked out,
+/// the migrations are current and the permission groups are loaded.
+async fn readyz(State(health): State<Health>) -> (StatusCode, Json<Readiness>) {
+    let (database, migrations) = match tokio::time::timeout(
+        DATABASE_TIMEOUT,
+        health.state.schema.pending_migrations(),
+    )
+    .await
+    {
+        Ok(Ok(pending)) => (true, pending.is_some_and(|p| p.is_empty())),
+        Ok(Err(e)) => {
+            warn!("readiness: failed to reach the database: {e:?}");
+            (false, false)
+        }
+        Err(_) => {
+            warn!("readiness: timed out reaching the database");
+            (false, false)
+        }
+    };
+    let readiness = Readiness {
+        database,
+        migrations,
+        permissions: !health.state.groups.is_empty(),
+        draining: health.draining.load(Ordering::Relaxed),
+    };
+    let ready =
+        readiness.database && readiness.migrations && readiness.permissions && !readiness.draining;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// `/healthz` and `/readyz`, merged outside of the middlewares so probes aren't rate limited
+/// or redirected to a login. `/readyz` fails once `draining` is set.
+pub fn router(state: BackendState, draining: Arc<AtomicBool>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(Health { state, draining })
+}
+
+/// Resolves on SIGINT or SIGTERM, setting `draining` so the instance leaves the load balancer
+/// while the requests in flight finish.
+pub async fn shutdown_signal(draining: Arc<AtomicBool>) {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("failed to listen for SIGINT: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {},
+        () = terminate => {},
+    }
+    draining.store(true, Ordering::Relaxed);
+    tracing::info!("shutting down, draining the requests in flight");
+}
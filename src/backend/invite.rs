@@ -0,0 +1,46 @@
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::shared::invite::Invite;
+
+use super::{errors::BackendError, repository::InviteRepository};
+
+#[instrument(name = "Invite: create", level = "info", skip(invites))]
+pub async fn create_invite(
+    invites: &dyn InviteRepository,
+    created_by: i64,
+    email: Option<String>,
+    days: u32,
+) -> Result<Invite, BackendError> {
+    let code = Uuid::new_v4().simple().to_string();
+    let invite = invites
+        .create(&code, created_by, email.as_deref(), days)
+        .await?;
+
+    info!("Invite {} created by {created_by}", invite.id);
+    Ok(invite)
+}
+
+#[instrument(name = "Invite: list", level = "info", skip(invites))]
+pub async fn list_invites(invites: &dyn InviteRepository) -> Result<Vec<Invite>, BackendError> {
+    invites.list().await
+}
+
+#[instrument(name = "Invite: get email", level = "info", skip(invites, code))]
+pub async fn get_invite_email(
+    invites: &dyn InviteRepository,
+    code: &str,
+) -> Result<Option<String>, BackendError> {
+    invites.email(code).await
+}
+
+/// Checks if the invite can still be redeemed, by `email` when given.
+///
+/// Only a hint, the invite is redeemed with the registration.
+pub async fn invite_is_valid(
+    invites: &dyn InviteRepository,
+    code: &str,
+    email: Option<&str>,
+) -> Result<bool, BackendError> {
+    invites.is_valid(code, email).await
+}
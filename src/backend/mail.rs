@@ -0,0 +1,77 @@
+use std::sync::OnceLock;
+
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::Mailbox};
+use tracing::{error, info, warn};
+
+use super::MailConfig;
+
+struct Mailer {
+    /// Without an SMTP server the emails are only logged, for development.
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: Mailbox,
+    public_url: String,
+}
+
+static MAILER: OnceLock<Mailer> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    #[error("invalid MAIL_FROM: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("invalid SMTP_URL: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+/// Configures the SMTP transport, must be called before sending emails.
+pub fn init(config: &MailConfig) -> Result<(), MailError> {
+    let mailer = Mailer {
+        transport: match &config.smtp_url {
+            Some(url) => Some(AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build()),
+            None => None,
+        },
+        from: config.from.parse()?,
+        public_url: config.public_url.trim_end_matches('/').to_owned(),
+    };
+    if MAILER.set(mailer).is_err() {
+        warn!("mailer was already initialized");
+    }
+    Ok(())
+}
+
+/// The url the application is reachable at, to build the links sent by email.
+pub fn public_url() -> &'static str {
+    MAILER.get().map_or("", |mailer| mailer.public_url.as_str())
+}
+
+/// Sends a plain text email in the background, failures are only logged.
+pub fn send(to: String, subject: String, body: String) {
+    let Some(mailer) = MAILER.get() else {
+        error!("mailer isn't initialized, dropped email to {to}");
+        return;
+    };
+    let Some(transport) = &mailer.transport else {
+        info!("email to {to}: {subject}\n{body}");
+        return;
+    };
+    let message = to
+        .parse::<Mailbox>()
+        .map_err(|e| e.to_string())
+        .and_then(|to| {
+            Message::builder()
+                .from(mailer.from.clone())
+                .to(to)
+                .subject(subject)
+                .body(body)
+                .map_err(|e| e.to_string())
+        });
+    match message {
+        Ok(message) => {
+            tokio::spawn(async move {
+                if let Err(e) = transport.send(message).await {
+                    error!("failed to send email to {to}: {e}");
+                }
+            });
+        }
+        Err(e) => error!("failed to build email to {to}: {e}"),
+    }
+}
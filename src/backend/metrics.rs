@@ -0,0 +1,297 @@
+//! Prometheus metrics, served on `/metrics` next to the OTLP traces of [`super::otlp`].
+
+use std::{
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
+
+use axum::{
+    Router,
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header::AUTHORIZATION},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use axum_login::tower_sessions::{
+    session::{Id, Record},
+    session_store::{self, SessionStore},
+};
+use dashmap::DashMap;
+use deadpool_postgres::{Object, Pool, PoolError};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+use super::{
+    MetricsConfig, auth::csrf::constant_time_eq, errors::BackendError, server_fns::server_fn_name,
+};
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn register<M: prometheus::core::Collector + Clone + 'static>(metric: M) -> M {
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric registered twice");
+    metric
+}
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status."),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+static HTTP_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency by route, server functions are routed under /api.",
+            ),
+            &["method", "route"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+static DB_POOL_CONNECTIONS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register(
+        IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Database pool connections: max, open, idle and the requests waiting for one.",
+            ),
+            &["state"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+static DB_POOL_WAIT: LazyLock<Histogram> = LazyLock::new(|| {
+    register(
+        Histogram::with_opts(
+            HistogramOpts::new(
+                "db_pool_wait_seconds",
+                "Time waited to check out a database connection.",
+            )
+            .buckets(vec![0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0]),
+        )
+        .expect("valid metric"),
+    )
+});
+
+static LOGINS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("auth_logins_total", "Password logins by result."),
+            &["result"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+static ARGON2_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "argon2_duration_seconds",
+                "Time spent computing Argon2 hashes, without waiting for a permit.",
+            )
+            .buckets(vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+            &["operation"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+static ACTIVE_SESSIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new("active_sessions", "Unexpired web sessions in the store.")
+            .expect("valid metric"),
+    )
+});
+
+/// Counts the requests and their latency, labelled by the server function or else the
+/// matched route so the cardinality stays bounded, the pages rendered by the fallback
+/// share one label.
+pub async fn track(req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = match server_fn_name(req.uri().path()) {
+        Some(name) => format!("/api/{name}"),
+        None => req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| "fallback".to_owned(), |path| path.as_str().to_owned()),
+    };
+    let response = next.run(req).await;
+    HTTP_DURATION
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    response
+}
+
+/// Counts a password login by its outcome.
+pub fn record_login<T>(result: &Result<Option<T>, BackendError>) {
+    let label = match result {
+        Ok(Some(_)) => "success",
+        Ok(None) | Err(BackendError::InvalidCredentials) => "invalid_credentials",
+        Err(BackendError::AccountLocked) => "locked",
+        Err(_) => "error",
+    };
+    LOGINS.with_label_values(&[label]).inc();
+}
+
+/// Records how long an Argon2 `operation`, `hash` or `verify`, took.
+pub fn record_argon2(operation: &str, start: Instant) {
+    ARGON2_DURATION
+        .with_label_values(&[operation])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+/// The database pool, recording how long checking out a connection waits.
+#[derive(Debug, Clone)]
+pub struct Db(Pool);
+
+impl Db {
+    pub fn new(pool: Pool) -> Self {
+        Self(pool)
+    }
+
+    pub async fn get(&self) -> Result<Object, PoolError> {
+        let start = Instant::now();
+        let client = self.0.get().await;
+        DB_POOL_WAIT.observe(start.elapsed().as_secs_f64());
+        client
+    }
+}
+
+impl std::ops::Deref for Db {
+    type Target = Pool;
+
+    fn deref(&self) -> &Pool {
+        &self.0
+    }
+}
+
+/// Wraps a session store to count the unexpired sessions it holds.
+#[derive(Debug, Clone)]
+pub struct CountingStore<S> {
+    inner: S,
+    /// Expiry of every saved session, as a unix timestamp.
+    expiries: Arc<DashMap<Id, i64>>,
+}
+
+impl<S> CountingStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            expiries: Arc::default(),
+        }
+    }
+
+    /// Drops the expired sessions from the count and returns it.
+    fn active(&self) -> usize {
+        let now = chrono::Utc::now().timestamp();
+        self.expiries.retain(|_, expiry| *expiry > now);
+        self.expiries.len()
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: SessionStore> SessionStore for CountingStore<S> {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        self.inner.create(record).await?;
+        self.expiries
+            .insert(record.id, record.expiry_date.unix_timestamp());
+        Ok(())
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        self.inner.save(record).await?;
+        self.expiries
+            .insert(record.id, record.expiry_date.unix_timestamp());
+        Ok(())
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        self.inner.load(id).await
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        self.inner.delete(id).await?;
+        self.expiries.remove(id);
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Scrape {
+    pool: Pool,
+    sessions: Arc<dyn Fn() -> usize + Send + Sync>,
+    token: Option<Arc<str>>,
+}
+
+async fn metrics(State(scrape): State<Scrape>, req: Request) -> Response {
+    if let Some(token) = &scrape.token {
+        let authorized = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+        if !authorized {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    let status = scrape.pool.status();
+    for (state, value) in [
+        ("max", status.max_size),
+        ("open", status.size),
+        ("idle", status.available),
+        ("waiting", status.waiting),
+    ] {
+        DB_POOL_CONNECTIONS
+            .with_label_values(&[state])
+            .set(i64::try_from(value).unwrap_or(i64::MAX));
+    }
+    ACTIVE_SESSIONS.set(i64::try_from((scrape.sessions)()).unwrap_or(i64::MAX));
+
+    let mut body = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut body) {
+        tracing::error!("failed to encode the metrics: {e}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    (
+        [(axum::http::header::CONTENT_TYPE, encoder.format_type())],
+        body,
+    )
+        .into_response()
+}
+
+/// `/metrics`, served outside of the middlewares like the health probes.
+pub fn router<S: Clone + Send + Sync + 'static>(
+    config: &MetricsConfig,
+    pool: Pool,
+    sessions: CountingStore<S>,
+) -> Router {
+    let scrape = Scrape {
+        pool,
+        sessions: Arc::new(move || sessions.active()),
+        token: config.token.as_deref().map(Arc::from),
+    };
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(scrape)
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+
+use deadpool_postgres::GenericClient;
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument, warn};
+
+/// Advisory lock held while migrating, so concurrent instances don't apply the same migration.
+const MIGRATION_LOCK: i64 = 0x7363_6865_6d61_5f6d;
+/// First line of the migrations that can't run in a transaction, every statement commits
+/// on its own so they must be idempotent.
+const NO_TRANSACTION: &str = "-- migrate: no-transaction";
+
+/// A schema change, applied once and in order.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+macro_rules! migration {
+    ($version:literal, $file:literal) => {
+        Migration {
+            version: $version,
+            name: $file,
+            sql: include_str!(concat!("../../migrations/", $file)),
+        }
+    };
+}
+
+/// Every migration, ordered by version. Applied migrations must never be edited,
+/// changes go in a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "01-init.sql"),
+    migration!(2, "02-invites.sql"),
+    migration!(3, "03-api-tokens.sql"),
+    migration!(4, "04-refresh-tokens.sql"),
+    migration!(5, "05-registration-challenges.sql"),
+    migration!(6, "06-security-notifications.sql"),
+    migration!(7, "07-audit-log.sql"),
+    migration!(8, "08-guest-accounts.sql"),
+];
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.sql.as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    fn transactional(&self) -> bool {
+        !self.sql.starts_with(NO_TRANSACTION)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("database error: {}", db_message(.0))]
+    Database(#[from] tokio_postgres::Error),
+    #[error("migration {version} ({name}) was edited after it was applied")]
+    ChecksumMismatch { version: i32, name: &'static str },
+    #[error("migration {0} is applied but unknown to this version, the database is newer")]
+    Unknown(i32),
+    #[error("migration {version} ({name}) is older than the latest applied one")]
+    OutOfOrder { version: i32, name: &'static str },
+}
+
+/// The message of the server, the error alone only says `db error`.
+fn db_message(error: &tokio_postgres::Error) -> String {
+    error
+        .as_db_error()
+        .map_or_else(|| error.to_string(), ToString::to_string)
+}
+
+/// Splits a script into its statements, the `;` in quotes, dollar quotes and comments
+/// don't end a statement.
+fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    let mut closing: Option<&str> = None;
+    while i < sql.len() {
+        let rest = &sql[i..];
+        if let Some(end) = closing {
+            if rest.starts_with(end) {
+                i += end.len();
+                closing = None;
+                continue;
+            }
+        } else if rest.starts_with("--") {
+            i += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        } else if rest.starts_with('\'') {
+            closing = Some("'");
+        } else if let Some(tag) = rest
+            .strip_prefix('$')
+            .and_then(|r| r.find('$'))
+            .map(|end| &rest[..end + 2])
+            .filter(|tag| {
+                tag[1..tag.len() - 1]
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_')
+            })
+        {
+            closing = Some(tag);
+            i += tag.len();
+            continue;
+        } else if rest.starts_with(';') {
+            statements.push(&sql[start..=i]);
+            start = i + 1;
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    statements.push(&sql[start..]);
+    statements.retain(|s| !s.trim().is_empty());
+    statements
+}
+
+async fn record(client: &impl GenericClient, migration: &Migration) -> Result<(), MigrationError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+            &[
+                tokio_postgres::types::Type::INT4,
+                tokio_postgres::types::Type::TEXT,
+                tokio_postgres::types::Type::TEXT,
+            ],
+        )
+        .await?;
+    client
+        .execute(
+            &stmt,
+            &[&migration.version, &migration.name, &migration.checksum()],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn apply_pending(client: &mut deadpool_postgres::Client) -> Result<Vec<i32>, MigrationError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations ( \n
+                version INT PRIMARY KEY, \n
+                name TEXT NOT NULL, \n
+                checksum TEXT NOT NULL, \n
+                applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP \n
+            )",
+        )
+        .await?;
+    let stmt = client
+        .prepare_typed_cached("SELECT version, checksum FROM schema_migrations", &[])
+        .await?;
+    let applied: HashMap<i32, String> = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    if let Some(unknown) = applied
+        .keys()
+        .find(|v| !MIGRATIONS.iter().any(|m| m.version == **v))
+    {
+        return Err(MigrationError::Unknown(*unknown));
+    }
+    let latest = applied.keys().max().copied().unwrap_or_default();
+
+    let mut done = Vec::new();
+    for migration in MIGRATIONS {
+        match applied.get(&migration.version) {
+            Some(checksum) if *checksum == migration.checksum() => continue,
+            Some(_) => {
+                return Err(MigrationError::ChecksumMismatch {
+                    version: migration.version,
+                    name: migration.name,
+                });
+            }
+            None if migration.version < latest => {
+                return Err(MigrationError::OutOfOrder {
+                    version: migration.version,
+                    name: migration.name,
+                });
+            }
+            None => {}
+        }
+        if migration.transactional() {
+            let tx = client.transaction().await?;
+            tx.batch_execute(migration.sql).await?;
+            record(&tx, migration).await?;
+            tx.commit().await?;
+        } else {
+            for statement in split_statements(migration.sql) {
+                client.batch_execute(statement).await?;
+            }
+            record(&*client, migration).await?;
+        }
+        info!("Applied migration {}", migration.name);
+        done.push(migration.version);
+    }
+    Ok(done)
+}
+
+/// Versions of the migrations not applied yet, fails when none ever was.
+pub async fn pending(client: &impl GenericClient) -> Result<Vec<i32>, MigrationError> {
+    let stmt = client
+        .prepare_typed_cached("SELECT version FROM schema_migrations", &[])
+        .await?;
+    let applied: Vec<i32> = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    Ok(MIGRATIONS
+        .iter()
+        .map(|m| m.version)
+        .filter(|v| !applied.contains(v))
+        .collect())
+}
+
+/// Applies the pending migrations, returns their versions.
+#[instrument(name = "Migrate: run", level = "info", skip(client))]
+pub async fn run(client: &mut deadpool_postgres::Client) -> Result<Vec<i32>, MigrationError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "SELECT pg_advisory_lock($1)",
+            &[tokio_postgres::types::Type::INT8],
+        )
+        .await?;
+    client.execute(&stmt, &[&MIGRATION_LOCK]).await?;
+    let result = apply_pending(client).await;
+    // the lock belongs to the connection, it goes back to the pool afterwards
+    if let Err(e) = client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK])
+        .await
+    {
+        warn!("failed to release the migration lock: {e}");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+    use tokio_postgres::NoTls;
+
+    use super::{MIGRATIONS, run, split_statements};
+
+    #[test]
+    fn splits_on_semicolons_outside_quotes_and_comments() {
+        let sql = "CREATE TABLE a (b TEXT DEFAULT 'x;y');\n\
+            -- a comment; not a statement\n\
+            INSERT INTO a VALUES ('it''s; fine');\n\
+            DO $$ BEGIN PERFORM 1; END; $$;\n\
+            CREATE FUNCTION f() RETURNS TEXT AS $body$ SELECT ';' $$ $body$ LANGUAGE sql;";
+        let statements: Vec<&str> = split_statements(sql).into_iter().map(str::trim).collect();
+        assert_eq!(
+            statements,
+            [
+                "CREATE TABLE a (b TEXT DEFAULT 'x;y');",
+                "-- a comment; not a statement\nINSERT INTO a VALUES ('it''s; fine');",
+                "DO $$ BEGIN PERFORM 1; END; $$;",
+                "CREATE FUNCTION f() RETURNS TEXT AS $body$ SELECT ';' $$ $body$ LANGUAGE sql;",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_a_trailing_statement_and_drops_blank_ones() {
+        assert_eq!(
+            split_statements("SELECT 1;\n-- done\n SELECT 2"),
+            ["SELECT 1;", "\n-- done\n SELECT 2"]
+        );
+        assert!(split_statements(" \n\t").is_empty());
+        // `$1` is a parameter, not a dollar quote
+        assert_eq!(
+            split_statements("SELECT $1; SELECT 'é;';\n"),
+            ["SELECT $1;", " SELECT 'é;';"]
+        );
+    }
+
+    #[test]
+    fn every_migration_splits_into_complete_statements() {
+        for migration in MIGRATIONS {
+            for statement in split_statements(migration.sql) {
+                assert!(
+                    statement.trim_end().ends_with(';'),
+                    "{}: {statement}",
+                    migration.name
+                );
+            }
+        }
+    }
+
+    /// The migrations before they were versioned, as the docker init script ran them,
+    /// the others are unchanged.
+    const LEGACY_INIT: &[(&str, &str)] = &[
+        (
+            "01-init.sql",
+            include_str!("../../tests/fixtures/legacy-init/01-init.sql"),
+        ),
+        (
+            "02-invites.sql",
+            include_str!("../../tests/fixtures/legacy-init/02-invites.sql"),
+        ),
+        (
+            "07-audit-log.sql",
+            include_str!("../../tests/fixtures/legacy-init/07-audit-log.sql"),
+        ),
+        (
+            "08-guest-accounts.sql",
+            include_str!("../../tests/fixtures/legacy-init/08-guest-accounts.sql"),
+        ),
+    ];
+
+    /// A throwaway database on the server of `TEST_DATABASE_URL`, dropped by [`Scratch::drop`].
+    struct Scratch {
+        admin: tokio_postgres::Client,
+        name: String,
+        pool: Pool,
+    }
+
+    impl Scratch {
+        /// Fails when `TEST_DATABASE_URL` isn't set, the tests ran without a database
+        /// would prove nothing.
+        async fn create() -> Self {
+            let url = std::env::var("TEST_DATABASE_URL")
+                .expect("TEST_DATABASE_URL must be set to run the database tests");
+            let (admin, connection) = tokio_postgres::connect(&url, NoTls)
+                .await
+                .expect("failed to connect to TEST_DATABASE_URL");
+            tokio::spawn(connection);
+            let name = format!("migrate_test_{}", uuid::Uuid::new_v4().simple());
+            admin
+                .batch_execute(&format!("CREATE DATABASE {name}"))
+                .await
+                .expect("failed to create the test database");
+            let mut config: tokio_postgres::Config =
+                url.parse().expect("invalid TEST_DATABASE_URL");
+            config.dbname(&name);
+            let manager = Manager::from_config(
+                config,
+                NoTls,
+                ManagerConfig {
+                    recycling_method: RecyclingMethod::Fast,
+                },
+            );
+            let pool = Pool::builder(manager).max_size(1).build().unwrap();
+            Self { admin, name, pool }
+        }
+
+        async fn drop(self) {
+            self.pool.close();
+            self.admin
+                .batch_execute(&format!("DROP DATABASE {} WITH (FORCE)", self.name))
+                .await
+                .expect("failed to drop the test database");
+        }
+    }
+
+    /// Everything the migrations define, in a comparable form.
+    async fn schema(client: &deadpool_postgres::Client) -> Vec<String> {
+        let queries = [
+            "SELECT table_name || '.' || column_name || ' ' || data_type || ' ' || is_nullable \n
+                || ' ' || coalesce(column_default, '') \n
+                FROM information_schema.columns WHERE table_schema = 'public'",
+            "SELECT conrelid::regclass || ' ' || conname || ' ' || pg_get_constraintdef(oid) \n
+                FROM pg_constraint WHERE connamespace = 'public'::regnamespace",
+            "SELECT indexdef FROM pg_indexes WHERE schemaname = 'public'",
+            "SELECT t.typname || ' ' || string_agg(e.enumlabel, ',' ORDER BY e.enumsortorder) \n
+                FROM pg_enum e JOIN pg_type t ON t.oid = e.enumtypid GROUP BY t.typname",
+            "SELECT pg_get_triggerdef(oid) FROM pg_trigger WHERE NOT tgisinternal",
+            "SELECT proname || ' ' || prosrc FROM pg_proc \n
+                WHERE pronamespace = 'public'::regnamespace AND prokind = 'f' \n
+                AND proname LIKE 'app_%'",
+            "SELECT extname FROM pg_extension",
+            "SELECT role || ' ' || permission FROM app_groups_permissions",
+            "SELECT version || ' ' || name || ' ' || checksum FROM schema_migrations",
+        ];
+        let mut schema = Vec::new();
+        for query in queries {
+            for row in client.query(query, &[]).await.unwrap() {
+                schema.push(row.get::<_, String>(0));
+            }
+        }
+        schema.sort();
+        schema
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server in TEST_DATABASE_URL"]
+    async fn applies_every_migration_to_an_empty_database() {
+        let db = Scratch::create().await;
+        let mut client = db.pool.get().await.unwrap();
+        let all: Vec<i32> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(run(&mut client).await.unwrap(), all);
+        let migrated = schema(&client).await;
+        // a second run finds nothing to do and changes nothing
+        assert_eq!(run(&mut client).await.unwrap(), Vec::<i32>::new());
+        assert_eq!(schema(&client).await, migrated);
+        drop(client);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server in TEST_DATABASE_URL"]
+    async fn an_upgraded_database_matches_a_fresh_one() {
+        let (fresh, legacy) = (Scratch::create().await, Scratch::create().await);
+        let mut client = fresh.pool.get().await.unwrap();
+        run(&mut client).await.unwrap();
+        let expected = schema(&client).await;
+        drop(client);
+        fresh.drop().await;
+
+        // the docker init script ran every file with psql, one statement at a time
+        let mut client = legacy.pool.get().await.unwrap();
+        for migration in MIGRATIONS {
+            let sql = LEGACY_INIT
+                .iter()
+                .find(|(name, _)| *name == migration.name)
+                .map_or(migration.sql, |(_, sql)| sql);
+            for statement in split_statements(sql) {
+                client
+                    .batch_execute(statement)
+                    .await
+                    .unwrap_or_else(|e| panic!("{}: {e}", migration.name));
+            }
+        }
+        run(&mut client).await.unwrap();
+        assert_eq!(schema(&client).await, expected);
+        drop(client);
+        legacy.drop().await;
+    }
+}
@@ -0,0 +1,336 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::shared::{native_auth::NativeTokens, user::User};
+
+use super::{
+    NativeTokenConfig,
+    errors::BackendError,
+    repository::{Rotation, TokenRepository},
+    token::hash_token,
+};
+
+/// Prefix of the short-lived access tokens issued to the desktop and mobile clients.
+pub const ACCESS_PREFIX: &str = "nat_";
+/// Prefix of the rotating refresh tokens issued to the desktop and mobile clients.
+pub const REFRESH_PREFIX: &str = "nrt_";
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn access_signature(key: &[u8], user: &User, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{expires}.{}", user.id, user.skey).as_bytes());
+    mac
+}
+
+/// Signs a stateless access token, bound to the user `skey` so it stops working when it changes.
+pub fn issue_access_token(key: &[u8], user: &User, expires_at: DateTime<Utc>) -> String {
+    let expires = expires_at.timestamp();
+    let signature: String = access_signature(key, user, expires)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("{ACCESS_PREFIX}{}.{expires}.{signature}", user.id)
+}
+
+/// Splits an access token into the user id, the expiry timestamp and the signature.
+pub fn parse_access_token(token: &str) -> Option<(i64, i64, Vec<u8>)> {
+    let mut parts = token.strip_prefix(ACCESS_PREFIX)?.splitn(3, '.');
+    let user = parts.next()?.parse().ok()?;
+    let expires = parts.next()?.parse().ok()?;
+    let signature = parts.next()?;
+    if signature.len() % 2 != 0 {
+        return None;
+    }
+    let signature = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some((user, expires, signature))
+}
+
+/// Checks the signature and expiry of an access token issued to `user`.
+pub fn verify_access_token(key: &[u8], user: &User, expires: i64, signature: &[u8]) -> bool {
+    expires > Utc::now().timestamp()
+        && access_signature(key, user, expires)
+            .verify_slice(signature)
+            .is_ok()
+}
+
+fn new_refresh_token() -> String {
+    format!(
+        "{REFRESH_PREFIX}{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// Signs an access token to go with the stored `refresh_token`.
+fn token_pair(
+    key: &[u8],
+    config: &NativeTokenConfig,
+    user: &User,
+    refresh_token: String,
+    refresh_expires_at: DateTime<Utc>,
+) -> NativeTokens {
+    let access_expires_at = Utc::now() + Duration::minutes(config.access_minutes);
+    NativeTokens {
+        access_token: issue_access_token(key, user, access_expires_at),
+        access_expires_at,
+        refresh_token,
+        refresh_expires_at,
+    }
+}
+
+/// Issues a new access token and a refresh token in the `family` of the login.
+#[instrument(name = "NativeToken: issue", level = "info", skip(tokens, key, user))]
+pub async fn issue_tokens(
+    tokens: &dyn TokenRepository,
+    key: &[u8],
+    config: &NativeTokenConfig,
+    user: &User,
+    family: Uuid,
+) -> Result<NativeTokens, BackendError> {
+    let refresh_token = new_refresh_token();
+    let refresh_expires_at = Utc::now() + Duration::days(config.refresh_days);
+    tokens
+        .create_refresh_token(
+            user.id,
+            family,
+            &hash_token(&refresh_token),
+            refresh_expires_at,
+        )
+        .await?;
+    Ok(token_pair(
+        key,
+        config,
+        user,
+        refresh_token,
+        refresh_expires_at,
+    ))
+}
+
+/// Exchanges a refresh token for a new pair, the refresh token can only be used once.
+///
+/// Presenting an already rotated token means it leaked, the whole family is revoked.
+#[instrument(name = "NativeToken: refresh", level = "info", skip_all)]
+pub async fn refresh_tokens(
+    tokens: &dyn TokenRepository,
+    key: &[u8],
+    config: &NativeTokenConfig,
+    refresh_token: &str,
+) -> Result<NativeTokens, BackendError> {
+    let next_token = new_refresh_token();
+    let refresh_expires_at = Utc::now() + Duration::days(config.refresh_days);
+    let rotation = tokens
+        .rotate_refresh_token(
+            &hash_token(refresh_token),
+            &hash_token(&next_token),
+            refresh_expires_at,
+        )
+        .await?;
+    match rotation {
+        Rotation::Rotated { user, family } => {
+            info!(
+                "Refresh token rotated for user {} in family {family}",
+                user.id
+            );
+            Ok(token_pair(
+                key,
+                config,
+                &user,
+                next_token,
+                refresh_expires_at,
+            ))
+        }
+        Rotation::Reused => {
+            warn!("refresh token reused, its family is revoked");
+            Err(BackendError::Unauthorized)
+        }
+        Rotation::Invalid => Err(BackendError::Unauthorized),
+    }
+}
+
+/// Revokes the login the refresh token belongs to.
+#[instrument(name = "NativeToken: revoke", level = "info", skip_all)]
+pub async fn revoke_tokens(
+    tokens: &dyn TokenRepository,
+    refresh_token: &str,
+) -> Result<(), BackendError> {
+    if tokens
+        .revoke_refresh_family(&hash_token(refresh_token))
+        .await?
+    {
+        Ok(())
+    } else {
+        Err(BackendError::Unauthorized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use super::{
+        issue_access_token, issue_tokens, parse_access_token, refresh_tokens, verify_access_token,
+    };
+    use crate::backend::{
+        NativeTokenConfig,
+        errors::BackendError,
+        repository::{Repositories, Rotation},
+        token::hash_token,
+    };
+
+    const KEY: &[u8] = &[3; 32];
+    const CONFIG: NativeTokenConfig = NativeTokenConfig {
+        access_minutes: 15,
+        refresh_days: 30,
+    };
+
+    async fn rotate(repositories: &Repositories, refresh_token: &str) -> Rotation {
+        repositories
+            .tokens
+            .rotate_refresh_token(
+                &hash_token(refresh_token),
+                &hash_token(&Uuid::new_v4().to_string()),
+                Utc::now() + Duration::days(1),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rotates_a_refresh_token_once() {
+        let repositories = Repositories::memory();
+        let user = repositories.users.create_guest().await.unwrap();
+        let family = Uuid::new_v4();
+        let issued = issue_tokens(&*repositories.tokens, KEY, &CONFIG, &user, family)
+            .await
+            .unwrap();
+
+        let refreshed = refresh_tokens(&*repositories.tokens, KEY, &CONFIG, &issued.refresh_token)
+            .await
+            .unwrap();
+        assert_ne!(refreshed.refresh_token, issued.refresh_token);
+        let (id, expires, signature) = parse_access_token(&refreshed.access_token).unwrap();
+        assert_eq!(id, user.id);
+        assert!(verify_access_token(KEY, &user, expires, &signature));
+        match rotate(&repositories, &refreshed.refresh_token).await {
+            Rotation::Rotated {
+                user: rotated,
+                family: rotated_family,
+            } => {
+                assert_eq!(rotated.id, user.id);
+                assert_eq!(rotated_family, family);
+            }
+            other => panic!("expected a rotation, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_reused_refresh_token_revokes_its_family() {
+        let repositories = Repositories::memory();
+        let user = repositories.users.create_guest().await.unwrap();
+        let issued = issue_tokens(&*repositories.tokens, KEY, &CONFIG, &user, Uuid::new_v4())
+            .await
+            .unwrap();
+        let refreshed = refresh_tokens(&*repositories.tokens, KEY, &CONFIG, &issued.refresh_token)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            rotate(&repositories, &issued.refresh_token).await,
+            Rotation::Reused
+        ));
+        // the token rotated in its place is revoked with the family
+        assert!(matches!(
+            rotate(&repositories, &refreshed.refresh_token).await,
+            Rotation::Invalid
+        ));
+        assert!(matches!(
+            refresh_tokens(&*repositories.tokens, KEY, &CONFIG, &issued.refresh_token).await,
+            Err(BackendError::Unauthorized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_an_expired_or_unknown_refresh_token() {
+        let repositories = Repositories::memory();
+        let user = repositories.users.create_guest().await.unwrap();
+        repositories
+            .tokens
+            .create_refresh_token(
+                user.id,
+                Uuid::new_v4(),
+                &hash_token("nrt_expired"),
+                Utc::now() - Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            rotate(&repositories, "nrt_expired").await,
+            Rotation::Invalid
+        ));
+        assert!(matches!(
+            rotate(&repositories, "nrt_unknown").await,
+            Rotation::Invalid
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_the_refresh_tokens_of_a_locked_user() {
+        let repositories = Repositories::memory();
+        let user = repositories.users.create_guest().await.unwrap();
+        let issued = issue_tokens(&*repositories.tokens, KEY, &CONFIG, &user, Uuid::new_v4())
+            .await
+            .unwrap();
+        repositories
+            .users
+            .create_lock_token(user.id, "lock", Utc::now() + Duration::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            repositories.users.lock_account("lock").await.unwrap(),
+            Some(user.id)
+        );
+
+        assert!(matches!(
+            rotate(&repositories, &issued.refresh_token).await,
+            Rotation::Invalid
+        ));
+        assert!(matches!(
+            refresh_tokens(&*repositories.tokens, KEY, &CONFIG, &issued.refresh_token).await,
+            Err(BackendError::Unauthorized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn an_access_token_stops_working_when_the_skey_rotates() {
+        let repositories = Repositories::memory();
+        let user = repositories.users.create_guest().await.unwrap();
+        let token = issue_access_token(KEY, &user, Utc::now() + Duration::minutes(5));
+        let (_, expires, signature) = parse_access_token(&token).unwrap();
+        assert!(verify_access_token(KEY, &user, expires, &signature));
+        assert!(!verify_access_token(KEY, &user, expires + 60, &signature));
+        assert!(!verify_access_token(&[4; 32], &user, expires, &signature));
+        let expired = issue_access_token(KEY, &user, Utc::now() - Duration::seconds(1));
+        let (_, expires_before, signature_before) = parse_access_token(&expired).unwrap();
+        assert!(!verify_access_token(
+            KEY,
+            &user,
+            expires_before,
+            &signature_before
+        ));
+
+        repositories.users.revoke_sessions(user.id).await.unwrap();
+        let rotated = repositories.users.get(user.id).await.unwrap().unwrap();
+        assert_ne!(rotated.skey, user.skey);
+        assert!(!verify_access_token(KEY, &rotated, expires, &signature));
+    }
+}
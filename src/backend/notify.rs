@@ -0,0 +1,153 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use chrono::{Duration, Utc};
+use dioxus_i18n::{
+    fluent::{FluentArgs, FluentResource, concurrent::FluentBundle},
+    unic_langid::LanguageIdentifier,
+};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::shared::user::UserRole;
+
+use super::{
+    errors::BackendError,
+    mail,
+    repository::{Contact, UserRepository},
+    token::hash_token,
+};
+
+/// Prefix of the "this wasn't me" secrets sent in the security emails.
+pub const LOCK_PREFIX: &str = "lck_";
+
+static BUNDLES: LazyLock<HashMap<LanguageIdentifier, FluentBundle<FluentResource>>> =
+    LazyLock::new(|| {
+        [
+            (&crate::i18n::EN_US, include_str!("../locales/en-US.ftl")),
+            (&crate::i18n::PT_PT, include_str!("../locales/pt-PT.ftl")),
+        ]
+        .into_iter()
+        .map(|(lang, source)| {
+            let resource =
+                FluentResource::try_new(source.to_owned()).unwrap_or_else(|(resource, _)| resource);
+            let mut bundle = FluentBundle::new_concurrent(vec![lang.clone()]);
+            // plain text emails, no bidi isolation marks around the arguments
+            bundle.set_use_isolating(false);
+            if let Err(e) = bundle.add_resource(resource) {
+                warn!("duplicated messages in {lang} locale: {e:?}");
+            }
+            (lang.clone(), bundle)
+        })
+        .collect()
+    });
+
+/// The bundled locale `locale` names, `None` when the emails aren't translated to it.
+pub fn supported_locale(locale: &str) -> Option<LanguageIdentifier> {
+    let lang: LanguageIdentifier = locale.parse().ok()?;
+    BUNDLES.contains_key(&lang).then_some(lang)
+}
+
+/// Translates `key`, in the `message.attribute` form of the views, falling back to English.
+fn translate(locale: &str, key: &str, args: &FluentArgs) -> String {
+    let lang: LanguageIdentifier = locale.parse().unwrap_or(crate::i18n::EN_US.clone());
+    let bundle = BUNDLES
+        .get(&lang)
+        .or_else(|| BUNDLES.get(&crate::i18n::EN_US))
+        .expect("the English locale is bundled");
+    let (id, attribute) = key
+        .split_once('.')
+        .map_or((key, None), |(m, a)| (m, Some(a)));
+    let Some(message) = bundle.get_message(id) else {
+        return key.to_owned();
+    };
+    let pattern = match attribute {
+        Some(attribute) => message.get_attribute(attribute).map(|a| a.value()),
+        None => message.value(),
+    };
+    let Some(pattern) = pattern else {
+        return key.to_owned();
+    };
+    let mut errors = Vec::new();
+    bundle
+        .format_pattern(pattern, Some(args), &mut errors)
+        .into_owned()
+}
+
+/// Sensitive changes the owner of the account is told about.
+#[derive(Debug, Clone)]
+pub enum SecurityEvent {
+    NewLogin { ip: String, device: String },
+    PasswordChanged,
+    RoleChanged { role: UserRole },
+}
+
+impl SecurityEvent {
+    fn message(&self) -> (&'static str, &'static str, FluentArgs<'static>) {
+        let mut args = FluentArgs::new();
+        let (subject, body) = match self {
+            Self::NewLogin { ip, device } => {
+                args.set("ip", ip.clone());
+                args.set("device", device.clone());
+                ("security-mail.new-login", "security-mail.new-login-body")
+            }
+            Self::PasswordChanged => ("security-mail.password", "security-mail.password-body"),
+            Self::RoleChanged { role } => {
+                args.set("role", format!("{role:?}"));
+                ("security-mail.role", "security-mail.role-body")
+            }
+        };
+        (subject, body, args)
+    }
+}
+
+/// Emails the owner of the account about `event`, in their language, with a link
+/// to lock the account when it wasn't them.
+#[instrument(name = "Notify: security event", level = "info", skip(users))]
+pub async fn notify(
+    users: &dyn UserRepository,
+    user: i64,
+    event: SecurityEvent,
+) -> Result<(), BackendError> {
+    let Contact { email, locale } = users.contact(user).await?;
+    let Some(email) = email else {
+        // guests have no email to be told at
+        return Ok(());
+    };
+
+    let secret = format!(
+        "{LOCK_PREFIX}{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+    users
+        .create_lock_token(user, &hash_token(&secret), Utc::now() + Duration::days(7))
+        .await?;
+
+    let (subject, body, mut args) = event.message();
+    args.set(
+        "link",
+        format!("{}/account/lock?token={secret}", mail::public_url()),
+    );
+    let body = format!(
+        "{}\n\n{}",
+        translate(&locale, body, &args),
+        translate(&locale, "security-mail", &args)
+    );
+    mail::send(email, translate(&locale, subject, &args), body);
+    info!("Security email sent to user {user}");
+    Ok(())
+}
+
+/// Locks the account the "this wasn't me" link belongs to.
+///
+/// Every session and token is revoked, the api tokens too, and locked accounts can't log in.
+#[instrument(name = "Notify: lock account", level = "info", skip_all)]
+pub async fn lock_account(users: &dyn UserRepository, secret: &str) -> Result<(), BackendError> {
+    let Some(user) = users.lock_account(&hash_token(secret)).await? else {
+        warn!("invalid, expired or used lock token");
+        return Err(BackendError::ValidationError("account-lock.invalid".into()));
+    };
+
+    warn!("Account {user} locked by its owner");
+    Ok(())
+}
@@ -1,43 +1,380 @@
-use std::sync::LazyLock;
+//! Traces, metrics and logs exported over OTLP, plus the console output.
+//!
+//! Without an `OTLP_ENDPOINT` nothing is exported, the spans and events only reach the console.
+//!
+//! The requests continue the trace of their W3C `traceparent`, the web client sends it and
+//! its own spans, see `crate::trace`.
 
-use opentelemetry::{KeyValue, trace::TracerProvider};
-use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::{Resource, trace::Sampler, trace::SdkTracerProvider};
-use tracing_opentelemetry::OpenTelemetryLayer;
+use std::{sync::OnceLock, time::SystemTime};
+
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderMap, Request},
+};
+use opentelemetry::{
+    Context, KeyValue, global,
+    propagation::Extractor,
+    trace::{
+        SamplingDecision, Span as _, SpanContext, SpanId, SpanKind, TraceContextExt, TraceFlags,
+        TraceId, TraceState, Tracer as _, TracerProvider,
+    },
+};
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_otlp::{
+    ExporterBuildError, LogExporter, MetricExporter, SpanExporter, WithExportConfig,
+};
+use opentelemetry_sdk::{
+    Resource,
+    logs::SdkLoggerProvider,
+    metrics::SdkMeterProvider,
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracerProvider, ShouldSample},
+};
+use tracing_opentelemetry::{MetricsLayer, OpenTelemetryLayer, OpenTelemetrySpanExt};
 use tracing_subscriber::{
-    EnvFilter,
-    {layer::SubscriberExt, util::SubscriberInitExt},
+    EnvFilter, Layer, Registry, layer::SubscriberExt, util::SubscriberInitExt,
 };
 
-static RESOURCE: LazyLock<Resource> = LazyLock::new(|| {
+use super::{TelemetryConfig, auth::AuthSession, server_fns::server_fn_name};
+use crate::shared::{request_id::REQUEST_ID_HEADER, trace::ClientSpan};
+
+/// Most spans accepted from a browser at once.
+const MAX_CLIENT_SPANS: usize = 64;
+
+/// Sample ratio of the traces, 0 when they aren't exported.
+static TRACE_RATIO: OnceLock<f64> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Grpc,
+    /// Protobuf over HTTP, the signal paths like `/v1/traces` are appended to the endpoint.
+    Http,
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grpc" => Ok(Self::Grpc),
+            "http" | "http/protobuf" => Ok(Self::Http),
+            other => Err(format!("unknown otlp protocol: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Nothing is printed, e.g. when the collector gets the logs.
+    None,
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown log format: {other}")),
+        }
+    }
+}
+
+/// The providers exporting the signals, flushed by [`Telemetry::shutdown`].
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    tracer: Option<SdkTracerProvider>,
+    meter: Option<SdkMeterProvider>,
+    logger: Option<SdkLoggerProvider>,
+}
+
+impl Telemetry {
+    /// Flushes what's still buffered, the collector being unreachable is only reported.
+    pub fn shutdown(self) {
+        if let Some(Err(e)) = self.tracer.map(|provider| provider.shutdown()) {
+            eprintln!("failed to flush the traces: {e}");
+        }
+        if let Some(Err(e)) = self.meter.map(|provider| provider.shutdown()) {
+            eprintln!("failed to flush the metrics: {e}");
+        }
+        if let Some(Err(e)) = self.logger.map(|provider| provider.shutdown()) {
+            eprintln!("failed to flush the logs: {e}");
+        }
+    }
+}
+
+fn resource(config: &TelemetryConfig) -> Resource {
     Resource::builder()
-        .with_attribute(KeyValue::new("service.name", "dioxus-server"))
+        .with_service_name(config.service_name.clone())
+        .with_attributes(
+            [
+                KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+                KeyValue::new("deployment.environment.name", config.environment.clone()),
+            ]
+            .into_iter()
+            .chain(
+                config
+                    .resource_attributes
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+            ),
+        )
         .build()
-});
+}
 
-pub fn init_tracer(endpoint: &str) -> SdkTracerProvider {
-    let exporter = opentelemetry_otlp::SpanExporter::builder()
-        .with_tonic()
-        .with_endpoint(endpoint)
-        .build()
-        .expect("Failed to build span exporter");
+/// The url of `signal` for the HTTP exporters, which take it as is.
+fn signal_url(endpoint: &str, signal: &str) -> String {
+    format!("{}/v1/{signal}", endpoint.trim_end_matches('/'))
+}
+
+fn span_exporter(
+    config: &TelemetryConfig,
+    endpoint: &str,
+) -> Result<SpanExporter, ExporterBuildError> {
+    match config.protocol {
+        Protocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build(),
+        Protocol::Http => SpanExporter::builder()
+            .with_http()
+            .with_endpoint(signal_url(endpoint, "traces"))
+            .build(),
+    }
+}
+
+fn metric_exporter(
+    config: &TelemetryConfig,
+    endpoint: &str,
+) -> Result<MetricExporter, ExporterBuildError> {
+    match config.protocol {
+        Protocol::Grpc => MetricExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build(),
+        Protocol::Http => MetricExporter::builder()
+            .with_http()
+            .with_endpoint(signal_url(endpoint, "metrics"))
+            .build(),
+    }
+}
+
+fn log_exporter(
+    config: &TelemetryConfig,
+    endpoint: &str,
+) -> Result<LogExporter, ExporterBuildError> {
+    match config.protocol {
+        Protocol::Grpc => LogExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build(),
+        Protocol::Http => LogExporter::builder()
+            .with_http()
+            .with_endpoint(signal_url(endpoint, "logs"))
+            .build(),
+    }
+}
+
+/// `RUST_LOG`, or the default filter.
+fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("debug,tower_http=debug,dioxus_fullstack=debug"))
+}
 
-    let provider = SdkTracerProvider::builder()
-        .with_batch_exporter(exporter)
-        .with_resource(RESOURCE.clone())
-        // Sampler::TraceIdRatioBased for production
-        .with_sampler(Sampler::AlwaysOn)
-        .build();
+/// Installs the subscriber and the exporters of the enabled signals.
+pub fn init(config: &TelemetryConfig) -> Result<Telemetry, ExporterBuildError> {
+    let mut telemetry = Telemetry::default();
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
 
-    let otel_layer = OpenTelemetryLayer::new(provider.tracer("dioxus-app"));
+    match config.log_format {
+        LogFormat::None => {}
+        LogFormat::Text => layers.push(tracing_subscriber::fmt::layer().boxed()),
+        LogFormat::Json => layers.push(tracing_subscriber::fmt::layer().json().boxed()),
+    }
 
-    let env_filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("debug,tower_http=debug,dioxus_fullstack=debug")); // Default filter
+    if let Some(endpoint) = &config.otlp_endpoint {
+        let resource = resource(config);
+        if config.traces {
+            let provider = SdkTracerProvider::builder()
+                .with_batch_exporter(span_exporter(config, endpoint)?)
+                .with_resource(resource.clone())
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                    config.sample_ratio,
+                ))))
+                .build();
+            layers.push(OpenTelemetryLayer::new(provider.tracer("dioxus-app")).boxed());
+            global::set_tracer_provider(provider.clone());
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            let _ = TRACE_RATIO.set(config.sample_ratio);
+            telemetry.tracer = Some(provider);
+        }
+        if config.metrics {
+            let provider = SdkMeterProvider::builder()
+                .with_periodic_exporter(metric_exporter(config, endpoint)?)
+                .with_resource(resource.clone())
+                .build();
+            // events with `counter.*`, `monotonic_counter.*` or `histogram.*` fields are recorded
+            layers.push(MetricsLayer::new(provider.clone()).boxed());
+            global::set_meter_provider(provider.clone());
+            telemetry.meter = Some(provider);
+        }
+        if config.logs {
+            let provider = SdkLoggerProvider::builder()
+                .with_batch_exporter(log_exporter(config, endpoint)?)
+                .with_resource(resource)
+                .build();
+            // the exporters log through tracing too, exporting those would loop
+            let exporters = env_filter()
+                .add_directive("hyper=off".parse().expect("valid directive"))
+                .add_directive("h2=off".parse().expect("valid directive"))
+                .add_directive("tonic=off".parse().expect("valid directive"))
+                .add_directive("reqwest=off".parse().expect("valid directive"))
+                .add_directive("opentelemetry=off".parse().expect("valid directive"));
+            layers.push(
+                OpenTelemetryTracingBridge::new(&provider)
+                    .with_filter(exporters)
+                    .boxed(),
+            );
+            telemetry.logger = Some(provider);
+        }
+    }
 
     tracing_subscriber::registry()
-        .with(otel_layer)
-        // .with(tracing_subscriber::fmt::layer()) // Add a formatter for console output
-        .with(env_filter)
+        .with(layers)
+        .with(env_filter())
         .init();
-    provider
+    Ok(telemetry)
+}
+
+/// The ratio the browser samples its traces with, so it only sends the ones the server keeps.
+pub fn browser_sample_ratio() -> f64 {
+    TRACE_RATIO.get().copied().unwrap_or_default()
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// The span of a request, continuing the trace of its `traceparent`.
+///
+/// Server functions are routed as `/api/<name>`, the span is named after the function.
+pub fn make_span<B>(req: &Request<B>) -> tracing::Span {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str);
+    let server_fn = server_fn_name(req.uri().path());
+    // set by the session or a bearer token, a login only knows the user once it's done
+    let user_id = req
+        .extensions()
+        .get::<AuthSession>()
+        .and_then(|session| session.user.as_ref())
+        .map(|user| user.id);
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok());
+    let span = tracing::info_span!(
+        "request",
+        otel.name = format!("{} {}", req.method(), server_fn.or(route).unwrap_or("fallback")),
+        otel.kind = "server",
+        http.request.method = %req.method(),
+        url.path = req.uri().path(),
+        http.route = route,
+        server_fn,
+        user.id = user_id,
+        request.id = request_id,
+    );
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    span.set_parent(parent);
+    span
+}
+
+fn parse_ids(span: &ClientSpan) -> Option<(TraceId, SpanId)> {
+    let trace_id = TraceId::from_hex(&span.trace_id).ok()?;
+    let span_id = SpanId::from_hex(&span.span_id).ok()?;
+    (trace_id != TraceId::INVALID && span_id != SpanId::INVALID).then_some((trace_id, span_id))
+}
+
+/// Whether the sampler of the server keeps the trace, the browser spans don't get to decide.
+fn is_sampled(trace_id: TraceId) -> bool {
+    let sampler = Sampler::TraceIdRatioBased(browser_sample_ratio());
+    let result = sampler.should_sample(None, trace_id, "", &SpanKind::Internal, &[], &[]);
+    result.decision == SamplingDecision::RecordAndSample
+}
+
+/// Exports the spans ended in a browser, of the trace in the `traceparent` of the request
+/// carrying them. The spans of other traces and the invalid ones are dropped.
+pub fn record_client_spans(headers: &HeaderMap, spans: Vec<ClientSpan>) {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    let request_trace = parent.span().span_context().trace_id();
+    if request_trace == TraceId::INVALID || !is_sampled(request_trace) {
+        return;
+    }
+    let tracer = global::tracer("dioxus-web");
+    for span in spans.into_iter().take(MAX_CLIENT_SPANS) {
+        let Some((trace_id, span_id)) = parse_ids(&span) else {
+            continue;
+        };
+        if trace_id != request_trace || span.end < span.start {
+            continue;
+        }
+        let parent = span
+            .parent_span_id
+            .as_deref()
+            .and_then(|id| SpanId::from_hex(id).ok())
+            .map(|parent| {
+                // sampled by the check above, not on the word of the browser
+                Context::new().with_remote_span_context(SpanContext::new(
+                    trace_id,
+                    parent,
+                    TraceFlags::SAMPLED,
+                    true,
+                    TraceState::default(),
+                ))
+            })
+            .unwrap_or_default();
+        let mut attributes = vec![KeyValue::new("url.path", span.route)];
+        if let Some(server_fn) = &span.server_fn {
+            attributes.push(KeyValue::new("server_fn", server_fn.clone()));
+        }
+        if let Some(status) = span.status {
+            attributes.push(KeyValue::new(
+                "http.response.status_code",
+                i64::from(status),
+            ));
+        }
+        let kind = if span.server_fn.is_some() {
+            SpanKind::Client
+        } else {
+            SpanKind::Internal
+        };
+        let mut otel = tracer
+            .span_builder(span.name.chars().take(128).collect::<String>())
+            .with_kind(kind)
+            .with_trace_id(trace_id)
+            .with_span_id(span_id)
+            .with_start_time(SystemTime::from(span.start))
+            .with_attributes(attributes)
+            .start_with_context(&tracer, &parent);
+        otel.end_with_timestamp(SystemTime::from(span.end));
+    }
 }
@@ -0,0 +1,394 @@
+//! TLS for the connections to PostgreSQL, following the `sslmode` of libpq.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject},
+};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+use super::PostgresConfig;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SslMode {
+    /// Plain text only.
+    Disable,
+    /// Encrypts when the server supports it, without checking its certificate.
+    #[default]
+    Prefer,
+    /// Always encrypts, the certificate is only checked when a root certificate is given.
+    Require,
+    /// Checks the certificate is signed by a trusted CA.
+    VerifyCa,
+    /// Also checks the certificate is for the host connected to.
+    VerifyFull,
+}
+
+impl std::str::FromStr for SslMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disable" => Ok(Self::Disable),
+            "prefer" => Ok(Self::Prefer),
+            "require" => Ok(Self::Require),
+            "verify-ca" => Ok(Self::VerifyCa),
+            "verify-full" => Ok(Self::VerifyFull),
+            other => Err(format!("unknown ssl mode: {other}")),
+        }
+    }
+}
+
+impl SslMode {
+    /// Whether TLS is negotiated, the certificate checks are left to [`Verifier`].
+    pub fn negotiation(self) -> deadpool_postgres::SslMode {
+        match self {
+            Self::Disable => deadpool_postgres::SslMode::Disable,
+            Self::Prefer => deadpool_postgres::SslMode::Prefer,
+            Self::Require | Self::VerifyCa | Self::VerifyFull => {
+                deadpool_postgres::SslMode::Require
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("failed to read {path}: {message}")]
+    File { path: PathBuf, message: String },
+    #[error("{0} has no certificates")]
+    NoCertificates(PathBuf),
+    #[error("invalid root certificates: {0}")]
+    Roots(#[from] rustls::client::VerifierBuilderError),
+    #[error("{0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let file_error = |e: rustls::pki_types::pem::Error| TlsError::File {
+        path: path.to_owned(),
+        message: e.to_string(),
+    };
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(file_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(file_error)?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(path.to_owned()));
+    }
+    Ok(certs)
+}
+
+/// Checks the server certificate as the mode asks, the handshake signatures are always checked.
+#[derive(Debug)]
+struct Verifier {
+    mode: SslMode,
+    webpki: Arc<WebPkiServerVerifier>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = || {
+            self.webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )
+        };
+        match self.mode {
+            SslMode::VerifyFull => verified(),
+            SslMode::VerifyCa => match verified() {
+                Err(rustls::Error::InvalidCertificate(
+                    CertificateError::NotValidForName
+                    | CertificateError::NotValidForNameContext { .. },
+                )) => Ok(ServerCertVerified::assertion()),
+                result => result,
+            },
+            SslMode::Disable | SslMode::Prefer | SslMode::Require => {
+                Ok(ServerCertVerified::assertion())
+            }
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// The rustls connector for `config`, `None` when TLS is disabled.
+pub fn connector(config: &PostgresConfig) -> Result<Option<MakeRustlsConnect>, TlsError> {
+    let mode = match (config.ssl_mode, &config.ssl_root_cert) {
+        (SslMode::Disable, _) => return Ok(None),
+        // like libpq, a root certificate makes `require` check the CA
+        (SslMode::Require, Some(_)) => SslMode::VerifyCa,
+        (mode, _) => mode,
+    };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let mut roots = RootCertStore::empty();
+    match &config.ssl_root_cert {
+        Some(path) => {
+            for cert in read_certs(path)? {
+                roots.add(cert)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let verifier = Verifier {
+        mode,
+        webpki: WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()?,
+        provider: provider.clone(),
+    };
+
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let tls = match (&config.ssl_cert, &config.ssl_key) {
+        (Some(cert), Some(key)) => {
+            let key = PrivateKeyDer::from_pem_file(key).map_err(|e| TlsError::File {
+                path: key.clone(),
+                message: e.to_string(),
+            })?;
+            builder.with_client_auth_cert(read_certs(cert)?, key)?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+    Ok(Some(MakeRustlsConnect::new(tls)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use rustls::{
+        ServerConfig,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    };
+    use tokio_postgres::tls::{MakeTlsConnect, TlsConnect};
+    use tokio_rustls::TlsAcceptor;
+
+    use super::{SslMode, TlsError, connector};
+    use crate::backend::PostgresConfig;
+
+    /// A throwaway CA signing the certificates of `localhost` and of the `postgres` client,
+    /// and one signing nothing. `generate.sh` next to them regenerates them.
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/tls/{name}"))
+    }
+
+    fn config(ssl_mode: SslMode, ssl_root_cert: Option<&str>) -> PostgresConfig {
+        PostgresConfig {
+            host: "localhost".into(),
+            port: 5432,
+            user: "postgres".into(),
+            password: "postgres".into(),
+            db: "postgres".into(),
+            pool_size: 1,
+            ssl_mode,
+            ssl_root_cert: ssl_root_cert.map(fixture),
+            ssl_cert: None,
+            ssl_key: None,
+        }
+    }
+
+    /// Handshakes with a server presenting the `localhost` certificate, connecting to `host`.
+    async fn handshake(config: &PostgresConfig, host: &str) -> Result<(), String> {
+        let certs = CertificateDer::pem_file_iter(fixture("server.pem"))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(fixture("server.key")).unwrap();
+        let server =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(certs, key)
+                .unwrap();
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let mut tls = connector(config).unwrap().expect("tls is enabled");
+        let connect = MakeTlsConnect::<tokio::io::DuplexStream>::make_tls_connect(&mut tls, host)
+            .unwrap()
+            .connect(client_io);
+        let accept = TlsAcceptor::from(Arc::new(server)).accept(server_io);
+        let (client, _) = tokio::join!(connect, accept);
+        client.map(drop).map_err(|e| e.to_string())
+    }
+
+    /// The server of `TEST_TLS_DATABASE_URL` in verify-full, trusting `ssl_root_cert`.
+    fn tls_database(ssl_root_cert: &str) -> PostgresConfig {
+        let url = std::env::var("TEST_TLS_DATABASE_URL")
+            .expect("TEST_TLS_DATABASE_URL must be set to run the TLS database tests");
+        let url: tokio_postgres::Config = url.parse().expect("invalid TEST_TLS_DATABASE_URL");
+        let host = match url.get_hosts() {
+            [tokio_postgres::config::Host::Tcp(host)] => host.clone(),
+            _ => panic!("TEST_TLS_DATABASE_URL must have a single tcp host"),
+        };
+        PostgresConfig {
+            host,
+            port: url.get_ports().first().copied().unwrap_or(5432),
+            user: url.get_user().unwrap_or("postgres").into(),
+            password: String::new(),
+            db: url.get_dbname().unwrap_or("postgres").into(),
+            ..config(SslMode::VerifyFull, Some(ssl_root_cert))
+        }
+    }
+
+    fn with_client_certificate(config: PostgresConfig) -> PostgresConfig {
+        PostgresConfig {
+            ssl_cert: Some(fixture("client.pem")),
+            ssl_key: Some(fixture("client.key")),
+            ..config
+        }
+    }
+
+    #[test]
+    fn parses_the_libpq_modes() {
+        for (value, mode) in [
+            ("disable", SslMode::Disable),
+            ("prefer", SslMode::Prefer),
+            ("require", SslMode::Require),
+            ("verify-ca", SslMode::VerifyCa),
+            ("verify-full", SslMode::VerifyFull),
+        ] {
+            assert_eq!(value.parse::<SslMode>(), Ok(mode));
+        }
+        assert!("verify_full".parse::<SslMode>().is_err());
+        assert!("allow".parse::<SslMode>().is_err());
+    }
+
+    #[test]
+    fn negotiates_tls_unless_disabled_or_preferred() {
+        use deadpool_postgres::SslMode as Negotiation;
+        assert_eq!(SslMode::Disable.negotiation(), Negotiation::Disable);
+        assert_eq!(SslMode::Prefer.negotiation(), Negotiation::Prefer);
+        for mode in [SslMode::Require, SslMode::VerifyCa, SslMode::VerifyFull] {
+            assert_eq!(mode.negotiation(), Negotiation::Require);
+        }
+    }
+
+    #[test]
+    fn builds_a_connector_unless_disabled() {
+        assert!(
+            connector(&config(SslMode::Disable, None))
+                .unwrap()
+                .is_none()
+        );
+        for mode in [SslMode::Prefer, SslMode::Require, SslMode::VerifyFull] {
+            assert!(connector(&config(mode, None)).unwrap().is_some());
+        }
+        assert!(matches!(
+            connector(&config(SslMode::VerifyFull, Some("missing.pem"))),
+            Err(TlsError::File { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn verify_full_checks_the_ca_and_the_host() {
+        let verify_full = config(SslMode::VerifyFull, Some("ca.pem"));
+        assert_eq!(handshake(&verify_full, "localhost").await, Ok(()));
+        assert!(handshake(&verify_full, "db.example.com").await.is_err());
+        let untrusted = config(SslMode::VerifyFull, Some("other-ca.pem"));
+        assert!(handshake(&untrusted, "localhost").await.is_err());
+        // without a root certificate only the public CAs are trusted
+        assert!(
+            handshake(&config(SslMode::VerifyFull, None), "localhost")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_ca_ignores_the_host() {
+        let verify_ca = config(SslMode::VerifyCa, Some("ca.pem"));
+        assert_eq!(handshake(&verify_ca, "db.example.com").await, Ok(()));
+        let untrusted = config(SslMode::VerifyCa, Some("other-ca.pem"));
+        assert!(handshake(&untrusted, "localhost").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn require_checks_the_ca_only_with_a_root_certificate() {
+        for mode in [SslMode::Prefer, SslMode::Require] {
+            assert_eq!(handshake(&config(mode, None), "localhost").await, Ok(()));
+        }
+        let untrusted = config(SslMode::Require, Some("other-ca.pem"));
+        assert!(handshake(&untrusted, "db.example.com").await.is_err());
+        let trusted = config(SslMode::Require, Some("ca.pem"));
+        assert_eq!(handshake(&trusted, "db.example.com").await, Ok(()));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a TLS PostgreSQL server in TEST_TLS_DATABASE_URL"]
+    async fn connects_in_verify_full_with_a_client_certificate() {
+        let pool = with_client_certificate(tls_database("ca.pem")).pool();
+        let client = pool.get().await.expect("failed to connect over tls");
+        let row = client
+            .query_one(
+                "SELECT ssl, client_dn FROM pg_stat_ssl WHERE pid = pg_backend_pid()",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert!(row.get::<_, bool>("ssl"));
+        assert_eq!(
+            row.get::<_, Option<&str>>("client_dn"),
+            Some("/CN=postgres")
+        );
+
+        // the server asks for the client certificate
+        assert!(tls_database("ca.pem").pool().get().await.is_err());
+        let untrusted = with_client_certificate(tls_database("other-ca.pem"));
+        assert!(untrusted.pool().get().await.is_err());
+    }
+}
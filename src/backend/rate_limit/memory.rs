@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use super::RateLimitRule;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Time to refill the whole bucket, once elapsed the bucket is the same as a missing one.
+    period: Duration,
+}
+
+/// Token buckets kept in memory, limits are per server instance.
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: DashMap<String, Bucket>,
+}
+
+impl MemoryStore {
+    /// Takes a token from the bucket `key`, returns how long to wait when it's empty.
+    pub fn acquire(&self, key: &str, rule: &RateLimitRule) -> Option<Duration> {
+        self.acquire_at(key, rule, Instant::now())
+    }
+
+    fn acquire_at(&self, key: &str, rule: &RateLimitRule, now: Instant) -> Option<Duration> {
+        let burst = f64::from(rule.burst);
+        let rate = burst / rule.period.as_secs_f64();
+        let mut bucket = self.buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+            period: rule.period,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Drops the buckets that had time to refill.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.buckets
+            .retain(|_, bucket| now.duration_since(bucket.updated) < bucket.period);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::MemoryStore;
+    use crate::backend::rate_limit::{RateLimitRule, RateLimitScope, retry_after_seconds};
+
+    /// Two requests, then one every 5 seconds.
+    fn rule() -> RateLimitRule {
+        RateLimitRule {
+            target: "login_user".into(),
+            scope: RateLimitScope::Ip,
+            burst: 2,
+            period: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn waits_for_the_next_token_once_empty() {
+        let store = MemoryStore::default();
+        let start = Instant::now();
+        assert_eq!(store.acquire_at("ip", &rule(), start), None);
+        assert_eq!(store.acquire_at("ip", &rule(), start), None);
+        assert_eq!(
+            store.acquire_at("ip", &rule(), start),
+            Some(Duration::from_secs(5))
+        );
+        // half a token refilled, a refused request takes nothing
+        let later = start + Duration::from_millis(2500);
+        assert_eq!(
+            store.acquire_at("ip", &rule(), later),
+            Some(Duration::from_millis(2500))
+        );
+        // the other buckets are untouched
+        assert_eq!(store.acquire_at("other", &rule(), start), None);
+    }
+
+    #[test]
+    fn refills_up_to_the_burst() {
+        let store = MemoryStore::default();
+        let start = Instant::now();
+        for _ in 0..2 {
+            assert_eq!(store.acquire_at("ip", &rule(), start), None);
+        }
+        let refilled = start + Duration::from_secs(5);
+        assert_eq!(store.acquire_at("ip", &rule(), refilled), None);
+        assert!(store.acquire_at("ip", &rule(), refilled).is_some());
+
+        // idle for longer than the period still gives only the burst
+        let idle = refilled + Duration::from_secs(60);
+        for _ in 0..2 {
+            assert_eq!(store.acquire_at("ip", &rule(), idle), None);
+        }
+        assert_eq!(
+            store.acquire_at("ip", &rule(), idle),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn retries_after_the_wait_rounded_up() {
+        let store = MemoryStore::default();
+        let start = Instant::now();
+        for _ in 0..2 {
+            store.acquire_at("ip", &rule(), start);
+        }
+        let wait = store.acquire_at("ip", &rule(), start + Duration::from_millis(2500));
+        assert_eq!(wait.map(retry_after_seconds), Some(3));
+        let wait = store.acquire_at("ip", &rule(), start + Duration::from_millis(4999));
+        assert_eq!(wait.map(retry_after_seconds), Some(1));
+    }
+}
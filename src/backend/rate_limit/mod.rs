@@ -0,0 +1,221 @@
+mod memory;
+mod valkey;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dioxus::prelude::server_fn::error::{NoCustomError, ServerFnError, ServerFnErrorSerde};
+use tracing::warn;
+
+use super::{
+    RateLimitConfig, auth::AuthSession, client_info::client_ip, server_fns::server_fn_name,
+};
+
+pub use memory::MemoryStore;
+pub use valkey::ValkeyStore;
+
+/// Limits applied when `RATE_LIMITS` isn't set.
+pub const DEFAULT_RULES: &str = "check_user_is_free=ip:20/60;submit_create_user=ip:5/3600;\
+    login_user=ip:10/300;reauthenticate=user:10/300;start_guest_session=ip:5/3600";
+
+/// What the requests are grouped by to share a bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitScope {
+    /// The client ip address.
+    Ip,
+    /// The logged user, the rule is skipped for anonymous requests.
+    User,
+    /// Every request to the target.
+    Global,
+}
+
+impl std::fmt::Display for RateLimitScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ip => write!(f, "ip"),
+            Self::User => write!(f, "user"),
+            Self::Global => write!(f, "global"),
+        }
+    }
+}
+
+impl std::str::FromStr for RateLimitScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "ip" => Ok(Self::Ip),
+            "user" => Ok(Self::User),
+            "global" => Ok(Self::Global),
+            other => Err(format!("unknown rate limit scope {other}")),
+        }
+    }
+}
+
+/// A token bucket of `burst` requests, refilled completely every `period`.
+#[derive(Debug, Clone)]
+pub struct RateLimitRule {
+    /// A server function name, e.g. `login_user`, or a route prefix starting with `/`.
+    pub target: String,
+    pub scope: RateLimitScope,
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl RateLimitRule {
+    fn applies_to(&self, path: &str, server_fn: Option<&str>) -> bool {
+        if self.target.starts_with('/') {
+            path.starts_with(&self.target)
+        } else {
+            server_fn == Some(self.target.as_str())
+        }
+    }
+}
+
+/// Parses rules in the form `target=scope:burst/seconds[,scope:burst/seconds];...`,
+/// e.g. `login_user=ip:10/300,global:100/60;/admin=user:60/60`.
+pub fn parse_rules(rules: &str) -> Result<Vec<RateLimitRule>, String> {
+    let mut parsed = Vec::new();
+    for entry in rules.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (target, limits) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("missing `=` in rate limit {entry}"))?;
+        for limit in limits.split(',') {
+            let (scope, rate) = limit
+                .split_once(':')
+                .ok_or_else(|| format!("missing `:` in rate limit {limit}"))?;
+            let (burst, seconds) = rate
+                .split_once('/')
+                .ok_or_else(|| format!("missing `/` in rate limit {limit}"))?;
+            let burst: u32 = burst.trim().parse().map_err(|e| format!("{limit}: {e}"))?;
+            let seconds: u64 = seconds
+                .trim()
+                .parse()
+                .map_err(|e| format!("{limit}: {e}"))?;
+            if burst == 0 || seconds == 0 {
+                return Err(format!("{limit}: burst and period must be positive"));
+            }
+            parsed.push(RateLimitRule {
+                target: target.trim().to_owned(),
+                scope: scope.parse()?,
+                burst,
+                period: Duration::from_secs(seconds),
+            });
+        }
+    }
+    Ok(parsed)
+}
+
+pub enum RateLimitStore {
+    Memory(Arc<MemoryStore>),
+    Valkey(ValkeyStore),
+}
+
+impl RateLimitStore {
+    async fn acquire(&self, key: &str, rule: &RateLimitRule) -> Option<Duration> {
+        match self {
+            Self::Memory(store) => store.acquire(key, rule),
+            Self::Valkey(store) => store.acquire(key, rule).await,
+        }
+    }
+}
+
+pub struct RateLimiter {
+    store: RateLimitStore,
+    rules: Vec<RateLimitRule>,
+    trusted_proxies: usize,
+}
+
+impl RateLimiter {
+    /// Connects to Valkey when configured, otherwise keeps the buckets in memory.
+    pub async fn new(config: RateLimitConfig, trusted_proxies: usize) -> redis::RedisResult<Self> {
+        let store = match &config.valkey_url {
+            Some(url) => RateLimitStore::Valkey(ValkeyStore::connect(url).await?),
+            None => {
+                let store = Arc::new(MemoryStore::default());
+                let pruned = store.clone();
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(Duration::from_secs(60));
+                    loop {
+                        interval.tick().await;
+                        pruned.prune();
+                    }
+                });
+                RateLimitStore::Memory(store)
+            }
+        };
+        Ok(Self {
+            store,
+            rules: config.rules,
+            trusted_proxies,
+        })
+    }
+}
+
+/// The `Retry-After` of a wait, rounded up to whole seconds and never 0.
+fn retry_after_seconds(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+/// Throttles the requests matching the configured rules, rejecting them with
+/// `429 Too Many Requests` and `Retry-After` once their bucket is empty.
+///
+/// Must run inside the `AuthManagerLayer` to apply the rules scoped by user.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let path = req.uri().path();
+    let server_fn = server_fn_name(path);
+    let mut retry_after: Option<Duration> = None;
+    for rule in limiter
+        .rules
+        .iter()
+        .filter(|rule| rule.applies_to(path, server_fn))
+    {
+        let subject = match rule.scope {
+            RateLimitScope::Ip => {
+                client_ip(req.headers(), req.extensions(), limiter.trusted_proxies)
+                    .map(|ip| ip.to_string())
+            }
+            RateLimitScope::User => req
+                .extensions()
+                .get::<AuthSession>()
+                .and_then(|session| session.user.as_ref())
+                .map(|user| user.id.to_string()),
+            RateLimitScope::Global => Some(String::new()),
+        };
+        let Some(subject) = subject else {
+            continue;
+        };
+        let key = format!("rate-limit:{}:{}:{subject}", rule.target, rule.scope);
+        if let Some(wait) = limiter.store.acquire(&key, rule).await {
+            retry_after = retry_after.max(Some(wait));
+        }
+    }
+
+    let Some(wait) = retry_after else {
+        return next.run(req).await;
+    };
+    warn!("rate limited a request to {path}");
+    let seconds = retry_after_seconds(wait);
+    // server functions decode the error from the body, routes get the plain message
+    let body = match server_fn {
+        Some(_) => ServerFnError::<NoCustomError>::ServerError("rate-limited".into())
+            .ser()
+            .unwrap_or_default(),
+        None => "rate-limited".to_owned(),
+    };
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, seconds.to_string())],
+        body,
+    )
+        .into_response()
+}
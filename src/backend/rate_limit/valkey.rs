@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use redis::{Script, aio::ConnectionManager};
+use tracing::error;
+
+use super::RateLimitRule;
+
+/// Refills and takes a token atomically, using the Valkey clock so every instance agrees.
+/// Returns the milliseconds to wait, `0` when a token was taken.
+const ACQUIRE: &str = r"
+local burst = tonumber(ARGV[1])
+local period = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(bucket[1]) or burst
+local updated = tonumber(bucket[2]) or now
+tokens = math.min(burst, tokens + (now - updated) * burst / period)
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) * period / burst)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], period)
+return wait
+";
+
+/// Token buckets kept in Valkey, limits are shared by every server instance.
+pub struct ValkeyStore {
+    connection: ConnectionManager,
+    script: Script,
+}
+
+impl ValkeyStore {
+    pub async fn connect(url: &str) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            connection: ConnectionManager::new(client).await?,
+            script: Script::new(ACQUIRE),
+        })
+    }
+
+    /// Takes a token from the bucket `key`, returns how long to wait when it's empty.
+    ///
+    /// When Valkey can't be reached the request is let through, an outage
+    /// of the limiter shouldn't take the application down with it.
+    pub async fn acquire(&self, key: &str, rule: &RateLimitRule) -> Option<Duration> {
+        let result: redis::RedisResult<u64> = self
+            .script
+            .key(key)
+            .arg(rule.burst)
+            .arg(rule.period.as_millis() as u64)
+            .invoke_async(&mut self.connection.clone())
+            .await;
+        match result {
+            Ok(0) => None,
+            Ok(wait) => Some(Duration::from_millis(wait)),
+            Err(e) => {
+                error!("failed to acquire rate limit token from valkey: {e}");
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,783 @@
+//! The repositories kept in memory, for running the server functions without a database.
+//!
+//! They follow the constraints of the migrations, e.g. the unique emails, and the audit log
+//! is chained with the same key as in Postgres.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    backend::{
+        audit::{self, AuditContext, AuditEntry, ChainVerifier},
+        errors::BackendError,
+    },
+    shared::{
+        audit::{AuditFilter, AuditRecord, AuditVerification},
+        invite::Invite,
+        token::ApiToken,
+        user::{User, UserPermission, UserRole},
+    },
+};
+
+use super::{
+    AuditRepository, ChallengeNonce, ChallengeRepository, Contact, InviteRepository, NewAccount,
+    PermissionRepository, Rotation, SchemaRepository, StoredCredentials, TokenRepository,
+    UserRepository,
+};
+
+/// The permissions the migrations seed `app_groups_permissions` with.
+pub fn default_groups() -> HashMap<UserRole, HashSet<UserPermission>> {
+    use UserPermission::*;
+    [
+        (UserRole::Guest, vec![Read]),
+        (UserRole::User, vec![Read]),
+        (UserRole::Staff, vec![Read, MarkAsNaughty]),
+        (
+            UserRole::Admin,
+            vec![
+                Read,
+                DeleteUser,
+                MarkAsNaughty,
+                ProDemoteUser,
+                EditUserPermissions,
+                InviteUser,
+                ViewAuditLog,
+            ],
+        ),
+    ]
+    .into_iter()
+    .map(|(role, permissions)| (role, permissions.into_iter().collect()))
+    .collect()
+}
+
+#[derive(Debug, Clone)]
+struct StoredUser {
+    user: User,
+    password_hash: Option<String>,
+    locale: String,
+    locked: bool,
+}
+
+#[derive(Debug, Clone)]
+struct LockToken {
+    user: i64,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+/// An invite, `redeemed_by` is the id of the user and not its email.
+#[derive(Debug, Clone)]
+struct StoredInvite {
+    invite: Invite,
+    redeemed_by: Option<i64>,
+}
+
+impl StoredInvite {
+    fn is_valid(&self, email: Option<&str>) -> bool {
+        self.invite.redeemed_at.is_none()
+            && self.invite.expires_at > Utc::now()
+            && match (self.invite.email.as_deref(), email) {
+                (Some(bound), Some(email)) => bound.eq_ignore_ascii_case(email),
+                _ => true,
+            }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredApiToken {
+    user: i64,
+    token_hash: String,
+    token: ApiToken,
+}
+
+#[derive(Debug, Clone)]
+struct RefreshToken {
+    user: i64,
+    family: Uuid,
+    expires_at: DateTime<Utc>,
+    used: bool,
+    revoked: bool,
+}
+
+#[derive(Debug, Clone)]
+struct StoredRecord {
+    record: AuditRecord,
+    prev_hash: String,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: i64,
+    users: HashMap<i64, StoredUser>,
+    login_sources: HashSet<(i64, String, String)>,
+    lock_tokens: HashMap<String, LockToken>,
+    invites: Vec<StoredInvite>,
+    challenges: HashMap<String, DateTime<Utc>>,
+    api_tokens: Vec<StoredApiToken>,
+    refresh_tokens: HashMap<String, RefreshToken>,
+    audit: Vec<StoredRecord>,
+}
+
+impl State {
+    fn email_taken(&self, email: &str) -> bool {
+        self.users.values().any(|u| u.user.email == email)
+    }
+
+    fn user_mut(&mut self, id: i64) -> Result<&mut StoredUser, BackendError> {
+        self.users
+            .get_mut(&id)
+            .ok_or_else(|| BackendError::NotFound("user".into()))
+    }
+
+    fn next_id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn email_of(&self, id: i64) -> Option<String> {
+        self.users
+            .get(&id)
+            .map(|u| u.user.email.clone())
+            .filter(|e| !e.is_empty())
+    }
+
+    fn revoke_refresh_tokens(&mut self, user: i64) {
+        for token in self.refresh_tokens.values_mut().filter(|t| t.user == user) {
+            token.revoked = true;
+        }
+    }
+
+    /// Appends to the chain, under the lock of the state like the advisory lock in Postgres.
+    fn append_audit(&mut self, entry: &AuditEntry<'_>) -> Result<(), BackendError> {
+        let prev_hash = self
+            .audit
+            .last()
+            .map_or_else(|| audit::GENESIS.to_owned(), |r| r.record.hash.clone());
+        let hash = audit::chain(&prev_hash, entry)?;
+        let record = AuditRecord {
+            id: self.next_id(),
+            c_at: entry.c_at,
+            actor_id: entry.actor,
+            actor_email: None,
+            permission: entry.permission,
+            action: entry.action.to_owned(),
+            target_id: entry.target,
+            target_email: None,
+            before: entry.before.to_owned(),
+            after: entry.after.to_owned(),
+            request_id: entry.request_id.map(ToOwned::to_owned),
+            hash,
+        };
+        self.audit.push(StoredRecord { record, prev_hash });
+        Ok(())
+    }
+
+    fn insert(&mut self, email: Option<&str>, password_hash: Option<&str>, role: UserRole) -> User {
+        let now = Utc::now();
+        let user = User {
+            id: self.next_id(),
+            email: email.unwrap_or_default().to_owned(),
+            c_at: now,
+            u_at: now,
+            role,
+            skey: Uuid::new_v4(),
+        };
+        self.users.insert(
+            user.id,
+            StoredUser {
+                user: user.clone(),
+                password_hash: password_hash.map(ToOwned::to_owned),
+                locale: crate::i18n::EN_US.to_string(),
+                locked: false,
+            },
+        );
+        user
+    }
+}
+
+/// Every repository behind a mutex, every call is a short critical section.
+#[derive(Debug)]
+pub struct MemoryRepository {
+    state: Mutex<State>,
+    groups: HashMap<UserRole, HashSet<UserPermission>>,
+}
+
+impl Default for MemoryRepository {
+    fn default() -> Self {
+        Self::with_groups(default_groups())
+    }
+}
+
+impl MemoryRepository {
+    pub fn with_groups(groups: HashMap<UserRole, HashSet<UserPermission>>) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            groups,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // every change is applied at once, a panic can't leave it half done
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepository for MemoryRepository {
+    async fn register(
+        &self,
+        account: &NewAccount<'_>,
+        password_hash: &str,
+    ) -> Result<User, BackendError> {
+        let mut state = self.state();
+        // everything is checked before the first change, the registration is all or nothing
+        let now = Utc::now();
+        state.challenges.retain(|_, expires_at| *expires_at > now);
+        if account
+            .challenge
+            .is_some_and(|c| state.challenges.contains_key(&c.nonce))
+        {
+            return Err(BackendError::ValidationError("challenge.invalid".into()));
+        }
+        if state.email_taken(account.email) {
+            return Err(BackendError::DuplicateUser);
+        }
+        if let Some(guest) = account.guest
+            && !state
+                .users
+                .get(&guest)
+                .is_some_and(|u| u.user.role == UserRole::Guest)
+        {
+            return Err(BackendError::NotFound("user".into()));
+        }
+        let invite = match account.invite {
+            Some(code) => Some(
+                state
+                    .invites
+                    .iter()
+                    .position(|i| i.invite.code == code && i.is_valid(Some(account.email)))
+                    .ok_or_else(|| BackendError::ValidationError("invite.invalid".into()))?,
+            ),
+            None => None,
+        };
+
+        if let Some(challenge) = account.challenge {
+            state
+                .challenges
+                .insert(challenge.nonce.clone(), challenge.expires_at);
+        }
+        let user = match account.guest {
+            Some(guest) => {
+                let stored = state.user_mut(guest)?;
+                account.email.clone_into(&mut stored.user.email);
+                stored.user.role = UserRole::User;
+                stored.user.u_at = now;
+                stored.password_hash = Some(password_hash.to_owned());
+                stored.user.clone()
+            }
+            None => state.insert(Some(account.email), Some(password_hash), account.role),
+        };
+        if let Some(index) = invite {
+            let invite = &mut state.invites[index];
+            invite.invite.redeemed_at = Some(now);
+            invite.redeemed_by = Some(user.id);
+        }
+        if let Some(context) = account.created_by {
+            let action = if user.role == UserRole::Admin {
+                "create_admin"
+            } else {
+                "create_user"
+            };
+            let after = serde_json::json!({ "role": format!("{:?}", user.role) }).to_string();
+            let entry = AuditEntry::now(
+                context,
+                UserPermission::ProDemoteUser,
+                action,
+                Some(user.id),
+                "null",
+                &after,
+            );
+            state.append_audit(&entry)?;
+        }
+        Ok(user)
+    }
+
+    async fn create_guest(&self) -> Result<User, BackendError> {
+        Ok(self.state().insert(None, None, UserRole::Guest))
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<User>, BackendError> {
+        Ok(self
+            .state()
+            .users
+            .get(&id)
+            .filter(|u| !u.locked)
+            .map(|u| u.user.clone()))
+    }
+
+    async fn find(&self, id_or_email: &str) -> Result<Option<User>, BackendError> {
+        let id = id_or_email.parse::<i64>().ok();
+        Ok(self
+            .state()
+            .users
+            .values()
+            .find(|u| Some(u.user.id) == id || u.user.email == id_or_email)
+            .map(|u| u.user.clone()))
+    }
+
+    async fn list(&self, search: Option<&str>, limit: i64) -> Result<Vec<User>, BackendError> {
+        let search = search.map(str::to_lowercase);
+        let state = self.state();
+        let mut users: Vec<User> = state
+            .users
+            .values()
+            .filter(|u| {
+                search
+                    .as_deref()
+                    .is_none_or(|s| u.user.email.to_lowercase().contains(s))
+            })
+            .map(|u| u.user.clone())
+            .collect();
+        users.sort_by_key(|u| u.id);
+        users.truncate(usize::try_from(limit).unwrap_or(0));
+        Ok(users)
+    }
+
+    async fn credentials(&self, email: &str) -> Result<Option<StoredCredentials>, BackendError> {
+        Ok(self
+            .state()
+            .users
+            .values()
+            .find(|u| u.user.email == email)
+            .map(|u| StoredCredentials {
+                user: u.user.clone(),
+                password_hash: u.password_hash.clone(),
+                locked: u.locked,
+            }))
+    }
+
+    async fn password_hash(&self, id: i64) -> Result<Option<String>, BackendError> {
+        Ok(self.state().user_mut(id)?.password_hash.clone())
+    }
+
+    async fn set_password_hash(&self, id: i64, password_hash: &str) -> Result<(), BackendError> {
+        self.state().user_mut(id)?.password_hash = Some(password_hash.to_owned());
+        Ok(())
+    }
+
+    async fn set_role(
+        &self,
+        context: &AuditContext,
+        permission: UserPermission,
+        id: i64,
+        role: UserRole,
+    ) -> Result<User, BackendError> {
+        let mut state = self.state();
+        let stored = state.user_mut(id)?;
+        let before = stored.user.role;
+        stored.user.role = role;
+        stored.user.u_at = Utc::now();
+        let user = stored.user.clone();
+        let before = serde_json::json!({ "role": format!("{before:?}") }).to_string();
+        let after = serde_json::json!({ "role": format!("{role:?}") }).to_string();
+        let entry = AuditEntry::now(context, permission, "set_role", Some(id), &before, &after);
+        state.append_audit(&entry)?;
+        Ok(user)
+    }
+
+    async fn set_locale(&self, id: i64, locale: &str) -> Result<(), BackendError> {
+        if let Some(stored) = self.state().users.get_mut(&id) {
+            locale.clone_into(&mut stored.locale);
+        }
+        Ok(())
+    }
+
+    async fn email_taken(&self, email: &str) -> Result<bool, BackendError> {
+        Ok(self.state().email_taken(email))
+    }
+
+    async fn revoke_sessions(&self, id: i64) -> Result<(), BackendError> {
+        let mut state = self.state();
+        let stored = state.user_mut(id)?;
+        stored.user.skey = Uuid::new_v4();
+        stored.user.u_at = Utc::now();
+        state.revoke_refresh_tokens(id);
+        Ok(())
+    }
+
+    async fn purge_guests(&self, max_age_hours: i64) -> Result<u64, BackendError> {
+        let cutoff = Utc::now() - Duration::hours(max_age_hours);
+        let mut state = self.state();
+        let before = state.users.len();
+        state
+            .users
+            .retain(|_, u| u.user.role != UserRole::Guest || u.user.c_at >= cutoff);
+        Ok((before - state.users.len()) as u64)
+    }
+
+    async fn record_login_source(
+        &self,
+        id: i64,
+        ip: Option<IpAddr>,
+        user_agent: Option<&str>,
+    ) -> Result<bool, BackendError> {
+        let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+        let user_agent = user_agent.unwrap_or_default().to_owned();
+        let mut state = self.state();
+        let known = state.login_sources.iter().any(|(user, ..)| *user == id);
+        Ok(state.login_sources.insert((id, ip, user_agent)) && known)
+    }
+
+    async fn contact(&self, id: i64) -> Result<Contact, BackendError> {
+        let mut state = self.state();
+        let stored = state.user_mut(id)?;
+        Ok(Contact {
+            email: Some(stored.user.email.clone()).filter(|e| !e.is_empty()),
+            locale: stored.locale.clone(),
+        })
+    }
+
+    async fn create_lock_token(
+        &self,
+        id: i64,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BackendError> {
+        let mut state = self.state();
+        state.user_mut(id)?;
+        state.lock_tokens.insert(
+            token_hash.to_owned(),
+            LockToken {
+                user: id,
+                expires_at,
+                used: false,
+            },
+        );
+        Ok(())
+    }
+
+    async fn lock_account(&self, token_hash: &str) -> Result<Option<i64>, BackendError> {
+        let mut state = self.state();
+        let Some(token) = state
+            .lock_tokens
+            .get_mut(token_hash)
+            .filter(|t| !t.used && t.expires_at > Utc::now())
+        else {
+            return Ok(None);
+        };
+        token.used = true;
+        let user = token.user;
+        let stored = state.user_mut(user)?;
+        stored.locked = true;
+        stored.user.skey = Uuid::new_v4();
+        stored.user.u_at = Utc::now();
+        state.revoke_refresh_tokens(user);
+        let now = Utc::now();
+        for token in state.api_tokens.iter_mut().filter(|t| t.user == user) {
+            token.token.revoked_at.get_or_insert(now);
+        }
+        Ok(Some(user))
+    }
+}
+
+#[async_trait::async_trait]
+impl PermissionRepository for MemoryRepository {
+    async fn groups(&self) -> Result<HashMap<UserRole, HashSet<UserPermission>>, BackendError> {
+        Ok(self.groups.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl InviteRepository for MemoryRepository {
+    async fn create(
+        &self,
+        code: &str,
+        _created_by: i64,
+        email: Option<&str>,
+        days: u32,
+    ) -> Result<Invite, BackendError> {
+        let mut state = self.state();
+        let now = Utc::now();
+        let invite = Invite {
+            id: state.next_id(),
+            code: code.to_owned(),
+            email: email.map(ToOwned::to_owned),
+            c_at: now,
+            expires_at: now + Duration::days(i64::from(days)),
+            redeemed_at: None,
+            redeemed_by: None,
+        };
+        state.invites.push(StoredInvite {
+            invite: invite.clone(),
+            redeemed_by: None,
+        });
+        Ok(invite)
+    }
+
+    async fn list(&self) -> Result<Vec<Invite>, BackendError> {
+        let state = self.state();
+        Ok(state
+            .invites
+            .iter()
+            .rev()
+            .map(|i| Invite {
+                redeemed_by: i.redeemed_by.and_then(|u| state.email_of(u)),
+                ..i.invite.clone()
+            })
+            .collect())
+    }
+
+    async fn email(&self, code: &str) -> Result<Option<String>, BackendError> {
+        Ok(self
+            .state()
+            .invites
+            .iter()
+            .find(|i| i.invite.code == code && i.is_valid(None))
+            .and_then(|i| i.invite.email.clone()))
+    }
+
+    async fn is_valid(&self, code: &str, email: Option<&str>) -> Result<bool, BackendError> {
+        Ok(self
+            .state()
+            .invites
+            .iter()
+            .any(|i| i.invite.code == code && i.is_valid(email)))
+    }
+}
+
+#[async_trait::async_trait]
+impl ChallengeRepository for MemoryRepository {
+    async fn recent_signups(&self) -> Result<i64, BackendError> {
+        let cutoff = Utc::now() - Duration::hours(1);
+        Ok(self
+            .state()
+            .users
+            .values()
+            .filter(|u| u.user.role != UserRole::Guest && u.user.c_at > cutoff)
+            .count() as i64)
+    }
+
+    async fn redeem(&self, challenge: &ChallengeNonce) -> Result<bool, BackendError> {
+        let mut state = self.state();
+        let now = Utc::now();
+        state.challenges.retain(|_, expires_at| *expires_at > now);
+        if state.challenges.contains_key(&challenge.nonce) {
+            return Ok(false);
+        }
+        state
+            .challenges
+            .insert(challenge.nonce.clone(), challenge.expires_at);
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenRepository for MemoryRepository {
+    async fn create_api_token(
+        &self,
+        user: i64,
+        name: &str,
+        token_hash: &str,
+        scopes: &[UserPermission],
+        days: u32,
+    ) -> Result<ApiToken, BackendError> {
+        let mut state = self.state();
+        state.user_mut(user)?;
+        let now = Utc::now();
+        let token = ApiToken {
+            id: state.next_id(),
+            name: name.to_owned(),
+            scopes: scopes.to_vec(),
+            c_at: now,
+            expires_at: now + Duration::days(i64::from(days)),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        state.api_tokens.push(StoredApiToken {
+            user,
+            token_hash: token_hash.to_owned(),
+            token: token.clone(),
+        });
+        Ok(token)
+    }
+
+    async fn list_api_tokens(&self, user: i64) -> Result<Vec<ApiToken>, BackendError> {
+        Ok(self
+            .state()
+            .api_tokens
+            .iter()
+            .rev()
+            .filter(|t| t.user == user)
+            .map(|t| t.token.clone())
+            .collect())
+    }
+
+    async fn revoke_api_token(&self, user: i64, id: i64) -> Result<(), BackendError> {
+        let mut state = self.state();
+        let token = state
+            .api_tokens
+            .iter_mut()
+            .find(|t| t.user == user && t.token.id == id && t.token.revoked_at.is_none())
+            .ok_or_else(|| BackendError::NotFound("token".into()))?;
+        token.token.revoked_at = Some(Utc::now());
+        Ok(())
+    }
+
+    async fn use_api_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<(User, HashSet<UserPermission>)>, BackendError> {
+        let mut state = self.state();
+        let now = Utc::now();
+        let Some(index) = state.api_tokens.iter().position(|t| {
+            t.token_hash == token_hash && t.token.revoked_at.is_none() && t.token.expires_at > now
+        }) else {
+            return Ok(None);
+        };
+        let user = state.api_tokens[index].user;
+        let Some(user) = state
+            .users
+            .get(&user)
+            .filter(|u| !u.locked)
+            .map(|u| u.user.clone())
+        else {
+            return Ok(None);
+        };
+        let token = &mut state.api_tokens[index].token;
+        token.last_used_at = Some(now);
+        Ok(Some((user, token.scopes.iter().copied().collect())))
+    }
+
+    async fn create_refresh_token(
+        &self,
+        user: i64,
+        family: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BackendError> {
+        self.state().refresh_tokens.insert(
+            token_hash.to_owned(),
+            RefreshToken {
+                user,
+                family,
+                expires_at,
+                used: false,
+                revoked: false,
+            },
+        );
+        Ok(())
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        next_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Rotation, BackendError> {
+        let mut state = self.state();
+        let Some(token) = state.refresh_tokens.get(token_hash).cloned() else {
+            return Ok(Rotation::Invalid);
+        };
+        if token.used {
+            for t in state
+                .refresh_tokens
+                .values_mut()
+                .filter(|t| t.family == token.family)
+            {
+                t.revoked = true;
+            }
+            return Ok(Rotation::Reused);
+        }
+        let user = state
+            .users
+            .get(&token.user)
+            .filter(|u| !u.locked)
+            .map(|u| u.user.clone());
+        let Some(user) = user.filter(|_| !token.revoked && token.expires_at > Utc::now()) else {
+            return Ok(Rotation::Invalid);
+        };
+        if let Some(t) = state.refresh_tokens.get_mut(token_hash) {
+            t.used = true;
+        }
+        state.refresh_tokens.insert(
+            next_hash.to_owned(),
+            RefreshToken {
+                user: user.id,
+                family: token.family,
+                expires_at,
+                used: false,
+                revoked: false,
+            },
+        );
+        Ok(Rotation::Rotated {
+            user,
+            family: token.family,
+        })
+    }
+
+    async fn revoke_refresh_family(&self, token_hash: &str) -> Result<bool, BackendError> {
+        let mut state = self.state();
+        let Some(family) = state.refresh_tokens.get(token_hash).map(|t| t.family) else {
+            return Ok(false);
+        };
+        for t in state
+            .refresh_tokens
+            .values_mut()
+            .filter(|t| t.family == family)
+        {
+            t.revoked = true;
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditRepository for MemoryRepository {
+    async fn list(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, BackendError> {
+        let state = self.state();
+        Ok(state
+            .audit
+            .iter()
+            .rev()
+            .map(|r| &r.record)
+            .filter(|r| {
+                filter.actor_id.is_none_or(|a| r.actor_id == a)
+                    && filter.target_id.is_none_or(|t| r.target_id == Some(t))
+                    && filter.permission.is_none_or(|p| r.permission == p)
+                    && filter.since.is_none_or(|s| r.c_at >= s)
+                    && filter.until.is_none_or(|u| r.c_at < u)
+            })
+            .take(1000)
+            .map(|r| AuditRecord {
+                actor_email: state.email_of(r.actor_id),
+                target_email: r.target_id.and_then(|t| state.email_of(t)),
+                ..r.clone()
+            })
+            .collect())
+    }
+
+    async fn verify(&self) -> Result<AuditVerification, BackendError> {
+        let mut verifier = ChainVerifier::new()?;
+        for stored in &self.state().audit {
+            let record = &stored.record;
+            if !verifier.check(&AuditEntry::of(record), &stored.prev_hash, &record.hash) {
+                return Ok(verifier.finish(Some(record.id)));
+            }
+        }
+        Ok(verifier.finish(None))
+    }
+}
+
+#[async_trait::async_trait]
+impl SchemaRepository for MemoryRepository {
+    /// There is no schema, nothing is ever pending.
+    async fn pending_migrations(&self) -> Result<Option<Vec<i32>>, BackendError> {
+        Ok(Some(Vec::new()))
+    }
+}
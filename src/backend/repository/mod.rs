@@ -0,0 +1,295 @@
+//! Storage of the application, behind traits so the server functions can run against
+//! [`postgres`] in production and [`memory`] without a database.
+//!
+//! Both implement every trait over the same data, a registration creates the user and
+//! redeems its invite and challenge at once, see [`UserRepository::register`].
+
+pub mod memory;
+pub mod postgres;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    net::IpAddr,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::shared::{
+    audit::{AuditFilter, AuditRecord, AuditVerification},
+    invite::Invite,
+    token::ApiToken,
+    user::{User, UserPermission, UserRole},
+};
+
+use super::{audit::AuditContext, errors::BackendError};
+
+/// A user with what the login checks.
+#[derive(Debug, Clone)]
+pub struct StoredCredentials {
+    pub user: User,
+    /// `None` for the guests, they have no password.
+    pub password_hash: Option<String>,
+    pub locked: bool,
+}
+
+/// Where the security emails of a user are sent.
+#[derive(Debug, Clone)]
+pub struct Contact {
+    /// `None` for the guests, they have no email to be told at.
+    pub email: Option<String>,
+    pub locale: String,
+}
+
+/// A registration challenge, whose solution was already checked.
+#[derive(Debug, Clone)]
+pub struct ChallengeNonce {
+    pub nonce: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// An account to create, with what its registration redeems.
+#[derive(Debug, Clone)]
+pub struct NewAccount<'a> {
+    pub email: &'a str,
+    pub role: UserRole,
+    /// The guest registering, it keeps its id and everything tied to it.
+    pub guest: Option<i64>,
+    /// The invite redeemed by the account.
+    pub invite: Option<&'a str>,
+    /// The challenge solved to register, it can't be used again.
+    pub challenge: Option<&'a ChallengeNonce>,
+    /// Who created the account, recorded in the audit log when set.
+    pub created_by: Option<&'a AuditContext>,
+}
+
+#[async_trait::async_trait]
+pub trait UserRepository: Debug + Send + Sync {
+    /// Creates the account and redeems its invite and challenge, all or nothing.
+    ///
+    /// Fails with [`BackendError::DuplicateUser`] when the email is taken, `invite.invalid`
+    /// or `challenge.invalid` when they can't be redeemed, and [`BackendError::NotFound`]
+    /// when the guest doesn't exist or isn't a guest anymore.
+    async fn register(
+        &self,
+        account: &NewAccount<'_>,
+        password_hash: &str,
+    ) -> Result<User, BackendError>;
+
+    async fn create_guest(&self) -> Result<User, BackendError>;
+
+    /// The user `id`, `None` when it doesn't exist or is locked.
+    async fn get(&self, id: i64) -> Result<Option<User>, BackendError>;
+
+    /// Finds a user by id or email, locked or not.
+    async fn find(&self, id_or_email: &str) -> Result<Option<User>, BackendError>;
+
+    /// The users whose email contains `search`, case-insensitive, oldest first.
+    async fn list(&self, search: Option<&str>, limit: i64) -> Result<Vec<User>, BackendError>;
+
+    async fn credentials(&self, email: &str) -> Result<Option<StoredCredentials>, BackendError>;
+
+    /// Fails with [`BackendError::NotFound`] when the user doesn't exist.
+    async fn password_hash(&self, id: i64) -> Result<Option<String>, BackendError>;
+
+    /// Fails with [`BackendError::NotFound`] when the user doesn't exist.
+    async fn set_password_hash(&self, id: i64, password_hash: &str) -> Result<(), BackendError>;
+
+    /// Changes the role of `id` and records it in the audit log under `permission`.
+    async fn set_role(
+        &self,
+        context: &AuditContext,
+        permission: UserPermission,
+        id: i64,
+        role: UserRole,
+    ) -> Result<User, BackendError>;
+
+    async fn set_locale(&self, id: i64, locale: &str) -> Result<(), BackendError>;
+
+    async fn email_taken(&self, email: &str) -> Result<bool, BackendError>;
+
+    /// Rotates the `skey` of `id` and revokes its refresh tokens.
+    async fn revoke_sessions(&self, id: i64) -> Result<(), BackendError>;
+
+    /// Deletes the guests created more than `max_age_hours` ago, returns how many.
+    async fn purge_guests(&self, max_age_hours: i64) -> Result<u64, BackendError>;
+
+    /// Remembers the login source, returns `true` for a new one of a user that logged in before.
+    async fn record_login_source(
+        &self,
+        id: i64,
+        ip: Option<IpAddr>,
+        user_agent: Option<&str>,
+    ) -> Result<bool, BackendError>;
+
+    /// Fails with [`BackendError::NotFound`] when the user doesn't exist.
+    async fn contact(&self, id: i64) -> Result<Contact, BackendError>;
+
+    async fn create_lock_token(
+        &self,
+        id: i64,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BackendError>;
+
+    /// Uses the lock token, locks its account and revokes every session and token of it.
+    /// Returns the user, `None` when the token is invalid, expired or used.
+    async fn lock_account(&self, token_hash: &str) -> Result<Option<i64>, BackendError>;
+}
+
+#[async_trait::async_trait]
+pub trait PermissionRepository: Debug + Send + Sync {
+    /// The permissions of every role.
+    async fn groups(&self) -> Result<HashMap<UserRole, HashSet<UserPermission>>, BackendError>;
+}
+
+#[async_trait::async_trait]
+pub trait InviteRepository: Debug + Send + Sync {
+    async fn create(
+        &self,
+        code: &str,
+        created_by: i64,
+        email: Option<&str>,
+        days: u32,
+    ) -> Result<Invite, BackendError>;
+
+    /// Every invite, newest first.
+    async fn list(&self) -> Result<Vec<Invite>, BackendError>;
+
+    /// The email the invite is bound to, `None` when it isn't or can't be redeemed anymore.
+    async fn email(&self, code: &str) -> Result<Option<String>, BackendError>;
+
+    /// Whether the invite can still be redeemed, by `email` when given.
+    async fn is_valid(&self, code: &str, email: Option<&str>) -> Result<bool, BackendError>;
+}
+
+#[async_trait::async_trait]
+pub trait ChallengeRepository: Debug + Send + Sync {
+    /// Accounts created in the last hour, the guests aside.
+    async fn recent_signups(&self) -> Result<i64, BackendError>;
+
+    /// Marks the challenge used, `false` when it already was.
+    async fn redeem(&self, challenge: &ChallengeNonce) -> Result<bool, BackendError>;
+}
+
+/// How a refresh token was exchanged, see [`TokenRepository::rotate_refresh_token`].
+#[derive(Debug, Clone)]
+pub enum Rotation {
+    Rotated {
+        user: User,
+        family: Uuid,
+    },
+    /// Used before, it leaked and its family is revoked.
+    Reused,
+    /// Unknown, expired, revoked, or its user is locked.
+    Invalid,
+}
+
+#[async_trait::async_trait]
+pub trait TokenRepository: Debug + Send + Sync {
+    async fn create_api_token(
+        &self,
+        user: i64,
+        name: &str,
+        token_hash: &str,
+        scopes: &[UserPermission],
+        days: u32,
+    ) -> Result<ApiToken, BackendError>;
+
+    /// The api tokens of `user`, newest first.
+    async fn list_api_tokens(&self, user: i64) -> Result<Vec<ApiToken>, BackendError>;
+
+    /// Fails with [`BackendError::NotFound`] when `user` has no such active token.
+    async fn revoke_api_token(&self, user: i64, id: i64) -> Result<(), BackendError>;
+
+    /// The user of an active api token and its scopes, marking it used.
+    async fn use_api_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<(User, HashSet<UserPermission>)>, BackendError>;
+
+    async fn create_refresh_token(
+        &self,
+        user: i64,
+        family: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BackendError>;
+
+    /// Marks the refresh token used and stores `next_hash` in its family, at once.
+    async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        next_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Rotation, BackendError>;
+
+    /// Revokes the family of the refresh token, `false` when it's unknown.
+    async fn revoke_refresh_family(&self, token_hash: &str) -> Result<bool, BackendError>;
+}
+
+/// The audit log, its records are appended by the audited changes, e.g.
+/// [`UserRepository::set_role`].
+#[async_trait::async_trait]
+pub trait AuditRepository: Debug + Send + Sync {
+    /// The latest records matching `filter`, newest first.
+    async fn list(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, BackendError>;
+
+    /// Recomputes the chain from the first record, stopping at the first broken link.
+    async fn verify(&self) -> Result<AuditVerification, BackendError>;
+}
+
+#[async_trait::async_trait]
+pub trait SchemaRepository: Debug + Send + Sync {
+    /// Versions of the migrations not applied yet, `None` when they can't be read.
+    ///
+    /// Fails when the storage can't be reached.
+    async fn pending_migrations(&self) -> Result<Option<Vec<i32>>, BackendError>;
+}
+
+/// The repositories [`crate::backend::BackendState`] is built with.
+#[derive(Debug, Clone)]
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub permissions: Arc<dyn PermissionRepository>,
+    pub invites: Arc<dyn InviteRepository>,
+    pub challenges: Arc<dyn ChallengeRepository>,
+    pub tokens: Arc<dyn TokenRepository>,
+    pub audit: Arc<dyn AuditRepository>,
+    pub schema: Arc<dyn SchemaRepository>,
+}
+
+impl Repositories {
+    fn from_shared<R>(repository: Arc<R>) -> Self
+    where
+        R: UserRepository
+            + PermissionRepository
+            + InviteRepository
+            + ChallengeRepository
+            + TokenRepository
+            + AuditRepository
+            + SchemaRepository
+            + 'static,
+    {
+        Self {
+            users: repository.clone(),
+            permissions: repository.clone(),
+            invites: repository.clone(),
+            challenges: repository.clone(),
+            tokens: repository.clone(),
+            audit: repository.clone(),
+            schema: repository,
+        }
+    }
+
+    pub fn postgres(db: super::metrics::Db) -> Self {
+        Self::from_shared(Arc::new(postgres::PgRepository::new(db)))
+    }
+
+    /// Empty, with the permissions of the migrations.
+    pub fn memory() -> Self {
+        Self::from_shared(Arc::new(memory::MemoryRepository::default()))
+    }
+}
@@ -0,0 +1,1118 @@
+//! The repositories over the tables of the migrations.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+};
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::GenericClient;
+use futures_util::{TryStreamExt, pin_mut};
+use tokio_postgres::types::ToSql;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::{
+    backend::{
+        audit::{self, AuditContext, AuditEntry, ChainVerifier},
+        errors::BackendError,
+        metrics::Db,
+        migrate,
+    },
+    shared::{
+        audit::{AuditFilter, AuditRecord, AuditVerification},
+        invite::Invite,
+        token::ApiToken,
+        user::{User, UserPermission, UserRole},
+    },
+};
+
+use super::{
+    AuditRepository, ChallengeNonce, ChallengeRepository, Contact, InviteRepository, NewAccount,
+    PermissionRepository, Rotation, SchemaRepository, StoredCredentials, TokenRepository,
+    UserRepository,
+};
+
+/// Advisory lock serializing the audit appends, so two records never share a predecessor.
+const AUDIT_APPEND_LOCK: i64 = 0x6175_6469_745f_6c6f;
+
+impl From<tokio_postgres::Row> for User {
+    #[inline]
+    fn from(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            c_at: row.get(1),
+            u_at: row.get(2),
+            skey: row.get(3),
+            // guests have no email until they register
+            email: row.get::<_, Option<String>>(4).unwrap_or_default(),
+            role: row.get(5),
+        }
+    }
+}
+
+impl From<tokio_postgres::Row> for Invite {
+    #[inline]
+    fn from(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            code: row.get(1),
+            email: row.get(2),
+            c_at: row.get(3),
+            expires_at: row.get(4),
+            redeemed_at: row.get(5),
+            redeemed_by: row.get(6),
+        }
+    }
+}
+
+impl From<tokio_postgres::Row> for ApiToken {
+    #[inline]
+    fn from(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            name: row.get(1),
+            scopes: row.get(2),
+            c_at: row.get(3),
+            expires_at: row.get(4),
+            last_used_at: row.get(5),
+            revoked_at: row.get(6),
+        }
+    }
+}
+
+impl From<tokio_postgres::Row> for AuditRecord {
+    #[inline]
+    fn from(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            c_at: row.get(1),
+            actor_id: row.get(2),
+            actor_email: row.get(3),
+            permission: row.get(4),
+            action: row.get(5),
+            target_id: row.get(6),
+            target_email: row.get(7),
+            before: row.get(8),
+            after: row.get(9),
+            request_id: row.get(10),
+            hash: row.get(11),
+        }
+    }
+}
+
+async fn insert_user(
+    client: &impl GenericClient,
+    email: &str,
+    password_hash: &str,
+    role: UserRole,
+) -> Result<User, BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "INSERT INTO app_user (email, password_hash, role) \n
+                                            VALUES ($1, $2, $3) \n
+                                            RETURNING id, c_at, m_at, skey, email, role",
+            &[
+                tokio_postgres::types::Type::TEXT,
+                tokio_postgres::types::Type::TEXT,
+            ],
+        )
+        .await?;
+    let row = client
+        .query_one(&stmt, &[&email, &password_hash, &role])
+        .await?;
+    Ok(User::from(row))
+}
+
+async fn upgrade_guest(
+    client: &impl GenericClient,
+    user: i64,
+    email: &str,
+    password_hash: &str,
+) -> Result<Option<User>, BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "UPDATE app_user \n
+            SET email = $2, password_hash = $3, role = $4, m_at = CURRENT_TIMESTAMP \n
+            WHERE id = $1 AND role = $5 \n
+            RETURNING id, c_at, m_at, skey, email, role",
+            &[
+                tokio_postgres::types::Type::INT8,
+                tokio_postgres::types::Type::TEXT,
+                tokio_postgres::types::Type::TEXT,
+            ],
+        )
+        .await?;
+    let row = client
+        .query_opt(
+            &stmt,
+            &[
+                &user,
+                &email,
+                &password_hash,
+                &UserRole::User,
+                &UserRole::Guest,
+            ],
+        )
+        .await?;
+    Ok(row.map(User::from))
+}
+
+async fn revoke_sessions(client: &impl GenericClient, user: i64) -> Result<(), BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "UPDATE app_user \n
+            SET skey = $2, m_at = CURRENT_TIMESTAMP \n
+            WHERE id = $1",
+            &[
+                tokio_postgres::types::Type::INT8,
+                tokio_postgres::types::Type::UUID,
+            ],
+        )
+        .await?;
+    if client.execute(&stmt, &[&user, &Uuid::new_v4()]).await? == 0 {
+        return Err(BackendError::NotFound("user".into()));
+    }
+    let stmt = client
+        .prepare_typed_cached(
+            "UPDATE app_refresh_token SET revoked_at = CURRENT_TIMESTAMP \n
+            WHERE user_id = $1 AND revoked_at IS NULL",
+            &[tokio_postgres::types::Type::INT8],
+        )
+        .await?;
+    client.execute(&stmt, &[&user]).await?;
+    Ok(())
+}
+
+/// Appends a record to the audit log, in the transaction of the audited change.
+async fn append_audit(
+    client: &impl GenericClient,
+    entry: &AuditEntry<'_>,
+) -> Result<(), BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "SELECT pg_advisory_xact_lock($1)",
+            &[tokio_postgres::types::Type::INT8],
+        )
+        .await?;
+    client.execute(&stmt, &[&AUDIT_APPEND_LOCK]).await?;
+    let stmt = client
+        .prepare_typed_cached(
+            "SELECT hash FROM app_audit_log ORDER BY id DESC LIMIT 1",
+            &[],
+        )
+        .await?;
+    let prev_hash: String = client
+        .query_opt(&stmt, &[])
+        .await?
+        .map_or_else(|| audit::GENESIS.to_owned(), |row| row.get(0));
+    let hash = audit::chain(&prev_hash, entry)?;
+    let stmt = client
+        .prepare_typed_cached(
+            "INSERT INTO app_audit_log \n
+            (c_at, actor_id, permission, action, target_id, before, after, request_id, prev_hash, hash) \n
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            &[
+                tokio_postgres::types::Type::TIMESTAMPTZ,
+                tokio_postgres::types::Type::INT8,
+            ],
+        )
+        .await?;
+    client
+        .execute(
+            &stmt,
+            &[
+                &entry.c_at,
+                &entry.actor,
+                &entry.permission,
+                &entry.action,
+                &entry.target,
+                &entry.before,
+                &entry.after,
+                &entry.request_id,
+                &prev_hash,
+                &hash,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Marks the challenge used, `false` when it already was.
+async fn redeem_challenge(
+    client: &impl GenericClient,
+    challenge: &ChallengeNonce,
+) -> Result<bool, BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "DELETE FROM app_registration_challenge WHERE expires_at <= CURRENT_TIMESTAMP",
+            &[],
+        )
+        .await?;
+    client.execute(&stmt, &[]).await?;
+    let stmt = client
+        .prepare_typed_cached(
+            "INSERT INTO app_registration_challenge (nonce, expires_at) \n
+            VALUES ($1, $2) \n
+            ON CONFLICT DO NOTHING",
+            &[
+                tokio_postgres::types::Type::TEXT,
+                tokio_postgres::types::Type::TIMESTAMPTZ,
+            ],
+        )
+        .await?;
+    Ok(client
+        .execute(&stmt, &[&challenge.nonce, &challenge.expires_at])
+        .await?
+        == 1)
+}
+
+/// Marks the invite as redeemed by `user`, fails with `invite.invalid` when it doesn't exist,
+/// was already used, expired, or is bound to another email.
+async fn redeem_invite(
+    client: &impl GenericClient,
+    code: &str,
+    user: i64,
+    email: &str,
+) -> Result<(), BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "UPDATE app_invite \n
+            SET redeemed_at = CURRENT_TIMESTAMP, redeemed_by = $2 \n
+            WHERE code = $1 AND redeemed_at IS NULL AND expires_at > CURRENT_TIMESTAMP \n
+            AND (email IS NULL OR lower(email) = lower($3)) \n
+            RETURNING id",
+            &[
+                tokio_postgres::types::Type::TEXT,
+                tokio_postgres::types::Type::INT8,
+                tokio_postgres::types::Type::TEXT,
+            ],
+        )
+        .await?;
+    match client.query_opt(&stmt, &[&code, &user, &email]).await? {
+        Some(row) => {
+            info!("Invite {} redeemed by {user}", row.get::<_, i64>(0));
+            Ok(())
+        }
+        None => {
+            warn!("tried to register with an invalid invite");
+            Err(BackendError::ValidationError("invite.invalid".into()))
+        }
+    }
+}
+
+async fn insert_refresh_token(
+    client: &impl GenericClient,
+    user: i64,
+    family: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "INSERT INTO app_refresh_token (user_id, family, token_hash, expires_at) \n
+            VALUES ($1, $2, $3, $4)",
+            &[
+                tokio_postgres::types::Type::INT8,
+                tokio_postgres::types::Type::UUID,
+                tokio_postgres::types::Type::TEXT,
+                tokio_postgres::types::Type::TIMESTAMPTZ,
+            ],
+        )
+        .await?;
+    client
+        .execute(&stmt, &[&user, &family, &token_hash, &expires_at])
+        .await?;
+    Ok(())
+}
+
+async fn revoke_family(client: &impl GenericClient, family: Uuid) -> Result<(), BackendError> {
+    let stmt = client
+        .prepare_typed_cached(
+            "UPDATE app_refresh_token \n
+            SET revoked_at = CURRENT_TIMESTAMP \n
+            WHERE family = $1 AND revoked_at IS NULL",
+            &[tokio_postgres::types::Type::UUID],
+        )
+        .await?;
+    client.execute(&stmt, &[&family]).await?;
+    info!("Refresh token family {family} revoked");
+    Ok(())
+}
+
+/// Every repository over the connection pool.
+#[derive(Debug, Clone)]
+pub struct PgRepository {
+    db: Db,
+}
+
+impl PgRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepository for PgRepository {
+    #[instrument(
+        name = "Postgres: register",
+        level = "debug",
+        skip(self, password_hash)
+    )]
+    async fn register(
+        &self,
+        account: &NewAccount<'_>,
+        password_hash: &str,
+    ) -> Result<User, BackendError> {
+        let mut client = self.db.get().await?;
+        let tx = client.transaction().await?;
+        if let Some(challenge) = account.challenge
+            && !redeem_challenge(&tx, challenge).await?
+        {
+            warn!("rejected a replayed challenge");
+            return Err(BackendError::ValidationError("challenge.invalid".into()));
+        }
+        let user = match account.guest {
+            Some(guest) => upgrade_guest(&tx, guest, account.email, password_hash)
+                .await?
+                .ok_or_else(|| BackendError::NotFound("user".into()))?,
+            None => insert_user(&tx, account.email, password_hash, account.role).await?,
+        };
+        if let Some(code) = account.invite {
+            redeem_invite(&tx, code, user.id, account.email).await?;
+        }
+        if let Some(context) = account.created_by {
+            let action = if user.role == UserRole::Admin {
+                "create_admin"
+            } else {
+                "create_user"
+            };
+            let after = serde_json::json!({ "role": format!("{:?}", user.role) }).to_string();
+            let entry = AuditEntry::now(
+                context,
+                UserPermission::ProDemoteUser,
+                action,
+                Some(user.id),
+                "null",
+                &after,
+            );
+            append_audit(&tx, &entry).await?;
+        }
+        tx.commit().await?;
+        Ok(user)
+    }
+
+    #[instrument(name = "Postgres: create guest", level = "debug", skip(self))]
+    async fn create_guest(&self) -> Result<User, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "INSERT INTO app_user (role) \n
+                VALUES ($1) \n
+                RETURNING id, c_at, m_at, skey, email, role",
+                &[],
+            )
+            .await?;
+        Ok(User::from(
+            client.query_one(&stmt, &[&UserRole::Guest]).await?,
+        ))
+    }
+
+    #[instrument(name = "Postgres: get user", level = "debug", skip(self))]
+    async fn get(&self, id: i64) -> Result<Option<User>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT id, c_at, m_at, skey, email, role \n
+         FROM app_user \n
+         WHERE id = $1 AND locked_at IS NULL",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        Ok(client.query_opt(&stmt, &[&id]).await?.map(User::from))
+    }
+
+    #[instrument(name = "Postgres: find user", level = "debug", skip(self))]
+    async fn find(&self, id_or_email: &str) -> Result<Option<User>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT id, c_at, m_at, skey, email, role \n
+                FROM app_user \n
+                WHERE id = $1 OR email = $2",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::TEXT,
+                ],
+            )
+            .await?;
+        let id = id_or_email.parse::<i64>().ok();
+        Ok(client
+            .query_opt(&stmt, &[&id, &id_or_email])
+            .await?
+            .map(User::from))
+    }
+
+    #[instrument(name = "Postgres: list users", level = "debug", skip(self))]
+    async fn list(&self, search: Option<&str>, limit: i64) -> Result<Vec<User>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT id, c_at, m_at, skey, email, role \n
+                FROM app_user \n
+                WHERE $1::TEXT IS NULL OR email ILIKE '%' || $1 || '%' \n
+                ORDER BY id \n
+                LIMIT $2",
+                &[
+                    tokio_postgres::types::Type::TEXT,
+                    tokio_postgres::types::Type::INT8,
+                ],
+            )
+            .await?;
+        let rows = client.query(&stmt, &[&search, &limit]).await?;
+        Ok(rows.into_iter().map(User::from).collect())
+    }
+
+    #[instrument(name = "Postgres: credentials", level = "debug", skip(self))]
+    async fn credentials(&self, email: &str) -> Result<Option<StoredCredentials>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT id, c_at, m_at, skey, email, role, password_hash, locked_at IS NOT NULL \n
+         FROM app_user \n
+         WHERE email = $1",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        Ok(client
+            .query_opt(&stmt, &[&email])
+            .await?
+            .map(|row| StoredCredentials {
+                password_hash: row.get(6),
+                locked: row.get(7),
+                user: User::from(row),
+            }))
+    }
+
+    #[instrument(name = "Postgres: password hash", level = "debug", skip(self))]
+    async fn password_hash(&self, id: i64) -> Result<Option<String>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT password_hash \n
+     FROM app_user \n
+     WHERE id = $1",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        match client.query_opt(&stmt, &[&id]).await? {
+            Some(row) => Ok(row.get(0)),
+            None => Err(BackendError::NotFound("user".into())),
+        }
+    }
+
+    #[instrument(
+        name = "Postgres: set password hash",
+        level = "debug",
+        skip(self, password_hash)
+    )]
+    async fn set_password_hash(&self, id: i64, password_hash: &str) -> Result<(), BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "UPDATE app_user \n
+                SET password_hash = $2 \n
+                WHERE id = $1",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::TEXT,
+                ],
+            )
+            .await?;
+        if client.execute(&stmt, &[&id, &password_hash]).await? == 0 {
+            return Err(BackendError::NotFound("user".into()));
+        }
+        Ok(())
+    }
+
+    #[instrument(name = "Postgres: set role", level = "debug", skip(self))]
+    async fn set_role(
+        &self,
+        context: &AuditContext,
+        permission: UserPermission,
+        id: i64,
+        role: UserRole,
+    ) -> Result<User, BackendError> {
+        let mut client = self.db.get().await?;
+        let tx = client.transaction().await?;
+        let stmt = tx
+            .prepare_typed_cached(
+                "SELECT role FROM app_user WHERE id = $1 FOR UPDATE",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        let Some(row) = tx.query_opt(&stmt, &[&id]).await? else {
+            return Err(BackendError::NotFound("user".into()));
+        };
+        let before: UserRole = row.get(0);
+        let stmt = tx
+            .prepare_typed_cached(
+                "UPDATE app_user \n
+                SET role = $2, m_at = CURRENT_TIMESTAMP \n
+                WHERE id = $1 \n
+                RETURNING id, c_at, m_at, skey, email, role",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        let row = tx.query_one(&stmt, &[&id, &role]).await?;
+        let before = serde_json::json!({ "role": format!("{before:?}") }).to_string();
+        let after = serde_json::json!({ "role": format!("{role:?}") }).to_string();
+        let entry = AuditEntry::now(context, permission, "set_role", Some(id), &before, &after);
+        append_audit(&tx, &entry).await?;
+        tx.commit().await?;
+        Ok(User::from(row))
+    }
+
+    #[instrument(name = "Postgres: set locale", level = "debug", skip(self))]
+    async fn set_locale(&self, id: i64, locale: &str) -> Result<(), BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "UPDATE app_user SET locale = $2 WHERE id = $1 AND locale <> $2",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::TEXT,
+                ],
+            )
+            .await?;
+        client.execute(&stmt, &[&id, &locale]).await?;
+        Ok(())
+    }
+
+    #[instrument(name = "Postgres: email taken", level = "debug", skip(self))]
+    async fn email_taken(&self, email: &str) -> Result<bool, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT true FROM app_user where email = $1",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        Ok(client.query_opt(&stmt, &[&email]).await?.is_some())
+    }
+
+    #[instrument(name = "Postgres: revoke sessions", level = "debug", skip(self))]
+    async fn revoke_sessions(&self, id: i64) -> Result<(), BackendError> {
+        let client = self.db.get().await?;
+        revoke_sessions(&client, id).await
+    }
+
+    #[instrument(name = "Postgres: purge guests", level = "debug", skip(self))]
+    async fn purge_guests(&self, max_age_hours: i64) -> Result<u64, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "DELETE FROM app_user \n
+                WHERE role = $1 AND c_at < CURRENT_TIMESTAMP - make_interval(hours => $2::INT)",
+                &[],
+            )
+            .await?;
+        let hours = i32::try_from(max_age_hours).unwrap_or(i32::MAX);
+        Ok(client.execute(&stmt, &[&UserRole::Guest, &hours]).await?)
+    }
+
+    #[instrument(
+        name = "Postgres: login source",
+        level = "debug",
+        skip(self, user_agent)
+    )]
+    async fn record_login_source(
+        &self,
+        id: i64,
+        ip: Option<IpAddr>,
+        user_agent: Option<&str>,
+    ) -> Result<bool, BackendError> {
+        let client = self.db.get().await?;
+        let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+        let user_agent = user_agent.unwrap_or_default();
+        let stmt = client
+            .prepare_typed_cached(
+                "WITH known AS (SELECT count(*) AS n FROM app_login_source WHERE user_id = $1) \n
+                INSERT INTO app_login_source (user_id, ip, user_agent) \n
+                VALUES ($1, $2, $3) \n
+                ON CONFLICT (user_id, ip, user_agent) DO UPDATE SET last_seen = CURRENT_TIMESTAMP \n
+                RETURNING xmax = 0, (SELECT n FROM known)",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::TEXT,
+                    tokio_postgres::types::Type::TEXT,
+                ],
+            )
+            .await?;
+        let row = client.query_one(&stmt, &[&id, &ip, &user_agent]).await?;
+        let (inserted, known): (bool, i64) = (row.get(0), row.get(1));
+        Ok(inserted && known > 0)
+    }
+
+    #[instrument(name = "Postgres: contact", level = "debug", skip(self))]
+    async fn contact(&self, id: i64) -> Result<Contact, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT email, locale FROM app_user WHERE id = $1",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        match client.query_opt(&stmt, &[&id]).await? {
+            Some(row) => Ok(Contact {
+                email: row.get(0),
+                locale: row.get(1),
+            }),
+            None => Err(BackendError::NotFound("user".into())),
+        }
+    }
+
+    #[instrument(name = "Postgres: create lock token", level = "debug", skip_all)]
+    async fn create_lock_token(
+        &self,
+        id: i64,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "INSERT INTO app_lock_token (user_id, token_hash, expires_at) \n
+                VALUES ($1, $2, $3)",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::TEXT,
+                    tokio_postgres::types::Type::TIMESTAMPTZ,
+                ],
+            )
+            .await?;
+        client
+            .execute(&stmt, &[&id, &token_hash, &expires_at])
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(name = "Postgres: lock account", level = "debug", skip_all)]
+    async fn lock_account(&self, token_hash: &str) -> Result<Option<i64>, BackendError> {
+        let mut client = self.db.get().await?;
+        let tx = client.transaction().await?;
+        let stmt = tx
+            .prepare_typed_cached(
+                "UPDATE app_lock_token \n
+                SET used_at = CURRENT_TIMESTAMP \n
+                WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP \n
+                RETURNING user_id",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        let Some(row) = tx.query_opt(&stmt, &[&token_hash]).await? else {
+            return Ok(None);
+        };
+        let user: i64 = row.get(0);
+
+        let stmt = tx
+            .prepare_typed_cached(
+                "UPDATE app_user SET locked_at = CURRENT_TIMESTAMP WHERE id = $1",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        tx.execute(&stmt, &[&user]).await?;
+        revoke_sessions(&tx, user).await?;
+        let stmt = tx
+            .prepare_typed_cached(
+                "UPDATE app_api_token SET revoked_at = CURRENT_TIMESTAMP \n
+                WHERE user_id = $1 AND revoked_at IS NULL",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        tx.execute(&stmt, &[&user]).await?;
+        tx.commit().await?;
+        Ok(Some(user))
+    }
+}
+
+#[async_trait::async_trait]
+impl PermissionRepository for PgRepository {
+    async fn groups(&self) -> Result<HashMap<UserRole, HashSet<UserPermission>>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached("SELECT role, permission FROM app_groups_permissions", &[])
+            .await?;
+        let mut groups: HashMap<UserRole, HashSet<UserPermission>> = HashMap::new();
+        for r in client.query(&stmt, &[]).await? {
+            let (role, permission): (UserRole, UserPermission) = (r.get(0), r.get(1));
+            groups.entry(role).or_default().insert(permission);
+        }
+        Ok(groups)
+    }
+}
+
+#[async_trait::async_trait]
+impl InviteRepository for PgRepository {
+    #[instrument(name = "Postgres: create invite", level = "debug", skip(self, code))]
+    async fn create(
+        &self,
+        code: &str,
+        created_by: i64,
+        email: Option<&str>,
+        days: u32,
+    ) -> Result<Invite, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "INSERT INTO app_invite (code, email, created_by, expires_at) \n
+                VALUES ($1, $2, $3, CURRENT_TIMESTAMP + make_interval(days => $4)) \n
+                RETURNING id, code, email, c_at, expires_at, redeemed_at, NULL::text",
+                &[
+                    tokio_postgres::types::Type::TEXT,
+                    tokio_postgres::types::Type::TEXT,
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::INT4,
+                ],
+            )
+            .await?;
+        let row = client
+            .query_one(&stmt, &[&code, &email, &created_by, &(days as i32)])
+            .await?;
+        Ok(Invite::from(row))
+    }
+
+    #[instrument(name = "Postgres: list invites", level = "debug", skip(self))]
+    async fn list(&self) -> Result<Vec<Invite>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT i.id, i.code, i.email, i.c_at, i.expires_at, i.redeemed_at, u.email \n
+                FROM app_invite i \n
+                LEFT JOIN app_user u ON u.id = i.redeemed_by \n
+                ORDER BY i.c_at DESC",
+                &[],
+            )
+            .await?;
+        let rows = client.query(&stmt, &[]).await?;
+        Ok(rows.into_iter().map(Invite::from).collect())
+    }
+
+    #[instrument(name = "Postgres: invite email", level = "debug", skip(self, code))]
+    async fn email(&self, code: &str) -> Result<Option<String>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT email FROM app_invite \n
+                WHERE code = $1 AND redeemed_at IS NULL AND expires_at > CURRENT_TIMESTAMP",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        let row = client.query_opt(&stmt, &[&code]).await?;
+        Ok(row.and_then(|r| r.get(0)))
+    }
+
+    #[instrument(name = "Postgres: invite valid", level = "debug", skip(self, code))]
+    async fn is_valid(&self, code: &str, email: Option<&str>) -> Result<bool, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT true FROM app_invite \n
+                WHERE code = $1 AND redeemed_at IS NULL AND expires_at > CURRENT_TIMESTAMP \n
+                AND ($2::text IS NULL OR email IS NULL OR lower(email) = lower($2))",
+                &[
+                    tokio_postgres::types::Type::TEXT,
+                    tokio_postgres::types::Type::TEXT,
+                ],
+            )
+            .await?;
+        Ok(client.query_opt(&stmt, &[&code, &email]).await?.is_some())
+    }
+}
+
+#[async_trait::async_trait]
+impl ChallengeRepository for PgRepository {
+    #[instrument(name = "Postgres: recent signups", level = "debug", skip(self))]
+    async fn recent_signups(&self) -> Result<i64, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT count(*) FROM app_user \n
+                WHERE c_at > CURRENT_TIMESTAMP - interval '1 hour' AND role <> 'guest'",
+                &[],
+            )
+            .await?;
+        Ok(client.query_one(&stmt, &[]).await?.get(0))
+    }
+
+    #[instrument(name = "Postgres: redeem challenge", level = "debug", skip_all)]
+    async fn redeem(&self, challenge: &ChallengeNonce) -> Result<bool, BackendError> {
+        let client = self.db.get().await?;
+        redeem_challenge(&client, challenge).await
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenRepository for PgRepository {
+    #[instrument(
+        name = "Postgres: create api token",
+        level = "debug",
+        skip(self, token_hash)
+    )]
+    async fn create_api_token(
+        &self,
+        user: i64,
+        name: &str,
+        token_hash: &str,
+        scopes: &[UserPermission],
+        days: u32,
+    ) -> Result<ApiToken, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "INSERT INTO app_api_token (user_id, name, token_hash, scopes, expires_at) \n
+                VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP + make_interval(days => $5)) \n
+                RETURNING id, name, scopes, c_at, expires_at, last_used_at, revoked_at",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::TEXT,
+                    tokio_postgres::types::Type::TEXT,
+                ],
+            )
+            .await?;
+        let row = client
+            .query_one(&stmt, &[&user, &name, &token_hash, &scopes, &(days as i32)])
+            .await?;
+        Ok(ApiToken::from(row))
+    }
+
+    #[instrument(name = "Postgres: list api tokens", level = "debug", skip(self))]
+    async fn list_api_tokens(&self, user: i64) -> Result<Vec<ApiToken>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT id, name, scopes, c_at, expires_at, last_used_at, revoked_at \n
+                FROM app_api_token \n
+                WHERE user_id = $1 \n
+                ORDER BY c_at DESC",
+                &[tokio_postgres::types::Type::INT8],
+            )
+            .await?;
+        let rows = client.query(&stmt, &[&user]).await?;
+        Ok(rows.into_iter().map(ApiToken::from).collect())
+    }
+
+    #[instrument(name = "Postgres: revoke api token", level = "debug", skip(self))]
+    async fn revoke_api_token(&self, user: i64, id: i64) -> Result<(), BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "UPDATE app_api_token \n
+                SET revoked_at = CURRENT_TIMESTAMP \n
+                WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::INT8,
+                ],
+            )
+            .await?;
+        if client.execute(&stmt, &[&id, &user]).await? == 0 {
+            return Err(BackendError::NotFound("token".into()));
+        }
+        Ok(())
+    }
+
+    #[instrument(name = "Postgres: use api token", level = "debug", skip_all)]
+    async fn use_api_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<(User, HashSet<UserPermission>)>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "UPDATE app_api_token t \n
+                SET last_used_at = CURRENT_TIMESTAMP \n
+                FROM app_user u \n
+                WHERE t.token_hash = $1 AND u.id = t.user_id AND u.locked_at IS NULL \n
+                AND t.revoked_at IS NULL AND t.expires_at > CURRENT_TIMESTAMP \n
+                RETURNING u.id, u.c_at, u.m_at, u.skey, u.email, u.role, t.scopes",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        Ok(client.query_opt(&stmt, &[&token_hash]).await?.map(|row| {
+            let scopes: Vec<UserPermission> = row.get(6);
+            (User::from(row), scopes.into_iter().collect())
+        }))
+    }
+
+    #[instrument(
+        name = "Postgres: create refresh token",
+        level = "debug",
+        skip(self, token_hash)
+    )]
+    async fn create_refresh_token(
+        &self,
+        user: i64,
+        family: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BackendError> {
+        let client = self.db.get().await?;
+        insert_refresh_token(&client, user, family, token_hash, expires_at).await
+    }
+
+    #[instrument(name = "Postgres: rotate refresh token", level = "debug", skip_all)]
+    async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        next_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Rotation, BackendError> {
+        let mut client = self.db.get().await?;
+        let tx = client.transaction().await?;
+        let stmt = tx
+            .prepare_typed_cached(
+                "SELECT u.id, u.c_at, u.m_at, u.skey, u.email, u.role, t.family, \n
+                t.used_at IS NOT NULL, \n
+                t.revoked_at IS NULL AND t.expires_at > CURRENT_TIMESTAMP AND u.locked_at IS NULL \n
+                FROM app_refresh_token t \n
+                JOIN app_user u ON u.id = t.user_id \n
+                WHERE t.token_hash = $1 \n
+                FOR UPDATE OF t",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        let Some(row) = tx.query_opt(&stmt, &[&token_hash]).await? else {
+            return Ok(Rotation::Invalid);
+        };
+        let (family, used, valid): (Uuid, bool, bool) = (row.get(6), row.get(7), row.get(8));
+        if used {
+            revoke_family(&tx, family).await?;
+            tx.commit().await?;
+            return Ok(Rotation::Reused);
+        }
+        if !valid {
+            return Ok(Rotation::Invalid);
+        }
+        let user = User::from(row);
+
+        let stmt = tx
+            .prepare_typed_cached(
+                "UPDATE app_refresh_token SET used_at = CURRENT_TIMESTAMP WHERE token_hash = $1",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        tx.execute(&stmt, &[&token_hash]).await?;
+        insert_refresh_token(&tx, user.id, family, next_hash, expires_at).await?;
+        tx.commit().await?;
+        Ok(Rotation::Rotated { user, family })
+    }
+
+    #[instrument(name = "Postgres: revoke refresh family", level = "debug", skip_all)]
+    async fn revoke_refresh_family(&self, token_hash: &str) -> Result<bool, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT family FROM app_refresh_token WHERE token_hash = $1",
+                &[tokio_postgres::types::Type::TEXT],
+            )
+            .await?;
+        match client.query_opt(&stmt, &[&token_hash]).await? {
+            Some(row) => {
+                revoke_family(&client, row.get(0)).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditRepository for PgRepository {
+    #[instrument(name = "Postgres: list audit log", level = "debug", skip(self))]
+    async fn list(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, BackendError> {
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT l.id, l.c_at, l.actor_id, a.email, l.permission, l.action, \n
+                l.target_id, t.email, l.before, l.after, l.request_id, l.hash \n
+                FROM app_audit_log l \n
+                LEFT JOIN app_user a ON a.id = l.actor_id \n
+                LEFT JOIN app_user t ON t.id = l.target_id \n
+                WHERE ($1::BIGINT IS NULL OR l.actor_id = $1) \n
+                AND ($2::BIGINT IS NULL OR l.target_id = $2) \n
+                AND ($3::app_user_permission IS NULL OR l.permission = $3) \n
+                AND ($4::TIMESTAMPTZ IS NULL OR l.c_at >= $4) \n
+                AND ($5::TIMESTAMPTZ IS NULL OR l.c_at < $5) \n
+                ORDER BY l.id DESC \n
+                LIMIT 1000",
+                &[
+                    tokio_postgres::types::Type::INT8,
+                    tokio_postgres::types::Type::INT8,
+                ],
+            )
+            .await?;
+        let rows = client
+            .query(
+                &stmt,
+                &[
+                    &filter.actor_id,
+                    &filter.target_id,
+                    &filter.permission,
+                    &filter.since,
+                    &filter.until,
+                ],
+            )
+            .await?;
+        Ok(rows.into_iter().map(AuditRecord::from).collect())
+    }
+
+    /// The records are streamed, the log isn't loaded at once.
+    #[instrument(name = "Postgres: verify audit log", level = "debug", skip(self))]
+    async fn verify(&self) -> Result<AuditVerification, BackendError> {
+        let mut verifier = ChainVerifier::new()?;
+        let client = self.db.get().await?;
+        let stmt = client
+            .prepare_typed_cached(
+                "SELECT id, c_at, actor_id, permission, action, target_id, before, after, \n
+                request_id, prev_hash, hash \n
+                FROM app_audit_log ORDER BY id",
+                &[],
+            )
+            .await?;
+        let rows = client
+            .query_raw(&stmt, std::iter::empty::<&(dyn ToSql + Sync)>())
+            .await?;
+        pin_mut!(rows);
+        while let Some(row) = rows.try_next().await? {
+            let entry = AuditEntry {
+                c_at: row.get(1),
+                actor: row.get(2),
+                permission: row.get(3),
+                action: row.get(4),
+                target: row.get(5),
+                before: row.get(6),
+                after: row.get(7),
+                request_id: row.get(8),
+            };
+            if !verifier.check(&entry, row.get(9), row.get(10)) {
+                return Ok(verifier.finish(Some(row.get(0))));
+            }
+        }
+        Ok(verifier.finish(None))
+    }
+}
+
+#[async_trait::async_trait]
+impl SchemaRepository for PgRepository {
+    async fn pending_migrations(&self) -> Result<Option<Vec<i32>>, BackendError> {
+        let client = self.db.get().await?;
+        match migrate::pending(&client).await {
+            Ok(pending) => Ok(Some(pending)),
+            Err(e) => {
+                warn!("failed to read the applied migrations: {e}");
+                Ok(None)
+            }
+        }
+    }
+}
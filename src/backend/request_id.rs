@@ -0,0 +1,84 @@
+//! Gives every request an id, taken from `X-Request-Id` or generated, and returns it.
+//!
+//! The error responses also carry it in their message, the failed server functions and the plain
+//! text errors like the rate limit, so the alert shown to the user has something to report,
+//! see [`crate::shared::request_id`].
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        HeaderValue, StatusCode,
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dioxus::prelude::server_fn::error::SERVER_FN_ERROR_HEADER;
+
+use crate::shared::request_id::{REQUEST_ID_HEADER, REQUEST_ID_SEPARATOR};
+
+/// Longest id accepted from the client or a proxy.
+const MAX_LEN: usize = 64;
+
+/// Error messages are short, a longer body is left as it is.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+fn valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+async fn append_to_error(response: Response, id: &str) -> Response {
+    let (mut parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, MAX_ERROR_BODY).await {
+        Ok(bytes) => {
+            let mut message = String::from_utf8_lossy(&bytes).into_owned();
+            message.push_str(REQUEST_ID_SEPARATOR);
+            message.push_str(id);
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(message))
+        }
+        Err(e) => {
+            tracing::error!("failed to read the server function error: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// A failed server function, or an error answered with a plain text message.
+fn is_error_message(response: &Response) -> bool {
+    let plain_text = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/plain"));
+    response.headers().contains_key(SERVER_FN_ERROR_HEADER)
+        || (plain_text
+            && (response.status().is_client_error() || response.status().is_server_error()))
+}
+
+/// Replaces an invalid id, so the audit log and the traces only record the valid ones.
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| valid(id))
+        .map_or_else(
+            || uuid::Uuid::new_v4().simple().to_string(),
+            ToOwned::to_owned,
+        );
+    let value = HeaderValue::from_str(&id).expect("request ids are visible ascii");
+    req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+
+    let mut response = next.run(req).await;
+    if is_error_message(&response) {
+        response = append_to_error(response, &id).await;
+    }
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
@@ -0,0 +1,51 @@
+//! The server functions registered by Dioxus, routed as `/api/<name>` by their `endpoint`.
+
+use std::{collections::HashSet, sync::LazyLock};
+
+use dioxus::prelude::server_fn;
+
+/// The name of the server function registered at `path`, `None` for any other path.
+pub fn server_fn_name(path: &str) -> Option<&'static str> {
+    static PATHS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+        server_fn::axum::server_fn_paths()
+            .map(|(path, _)| path)
+            .collect()
+    });
+    PATHS.get(path)?.strip_prefix("/api/")
+}
+
+#[cfg(test)]
+mod tests {
+    use dioxus::prelude::server_fn::{ServerFn, axum::server_fn_paths};
+
+    use super::server_fn_name;
+    use crate::shared::user::{ChangeUserPassword, CheckUserIsFree, LoginUser};
+
+    #[test]
+    fn names_the_registered_server_functions() {
+        assert_eq!(server_fn_name(LoginUser::PATH), Some("login_user"));
+        assert_eq!(
+            server_fn_name(CheckUserIsFree::PATH),
+            Some("check_user_is_free")
+        );
+        assert_eq!(
+            server_fn_name(ChangeUserPassword::PATH),
+            Some("change_password")
+        );
+        assert_eq!(server_fn_name("/api/login_user1"), None);
+        assert_eq!(server_fn_name("/api/"), None);
+        assert_eq!(server_fn_name("/login"), None);
+    }
+
+    #[test]
+    fn every_server_function_has_an_endpoint() {
+        // without one the path ends with the hash of the location of the function
+        for (path, _) in server_fn_paths() {
+            assert!(server_fn_name(path).is_some(), "{path} isn't under /api");
+            assert!(
+                !path.ends_with(|c: char| c.is_ascii_digit()),
+                "{path} has no endpoint"
+            );
+        }
+    }
+}
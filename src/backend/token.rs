@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::shared::{
+    token::{ApiToken, CreatedApiToken},
+    user::{User, UserPermission},
+};
+
+use super::{errors::BackendError, repository::TokenRepository};
+
+/// Prefix of the personal access tokens secrets, makes them easy to spot by secret scanners.
+pub const TOKEN_PREFIX: &str = "dat_";
+
+/// The secrets are random, a fast hash is enough to not store them in plain text.
+pub fn hash_token(secret: &str) -> String {
+    let digest = Sha256::digest(secret.as_bytes());
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[instrument(name = "Token: create", level = "info", skip(tokens))]
+pub async fn create_token(
+    tokens: &dyn TokenRepository,
+    user: i64,
+    name: String,
+    scopes: Vec<UserPermission>,
+    days: u32,
+) -> Result<CreatedApiToken, BackendError> {
+    let secret = format!(
+        "{TOKEN_PREFIX}{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+    let token = tokens
+        .create_api_token(user, &name, &hash_token(&secret), &scopes, days)
+        .await?;
+
+    info!("Token {} created for user {user}", token.id);
+    Ok(CreatedApiToken { token, secret })
+}
+
+#[instrument(name = "Token: list", level = "info", skip(tokens))]
+pub async fn list_tokens(
+    tokens: &dyn TokenRepository,
+    user: i64,
+) -> Result<Vec<ApiToken>, BackendError> {
+    tokens.list_api_tokens(user).await
+}
+
+#[instrument(name = "Token: revoke", level = "info", skip(tokens))]
+pub async fn revoke_token(
+    tokens: &dyn TokenRepository,
+    user: i64,
+    token: i64,
+) -> Result<(), BackendError> {
+    tokens.revoke_api_token(user, token).await?;
+    info!("Token {token} revoked by user {user}");
+    Ok(())
+}
+
+/// Resolves a bearer secret to its user and the scopes granted to the token.
+#[instrument(name = "Token: authenticate", level = "debug", skip(tokens, secret))]
+pub async fn authenticate_token(
+    tokens: &dyn TokenRepository,
+    secret: &str,
+) -> Result<Option<(User, HashSet<UserPermission>)>, BackendError> {
+    if !secret.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let found = tokens.use_api_token(&hash_token(secret)).await?;
+    if found.is_none() {
+        warn!("invalid, expired or revoked api token");
+    }
+    Ok(found)
+}
@@ -1,116 +1,585 @@
-use tracing::{info, instrument};
+use tracing::{error, info, instrument};
 
 use crate::{
-    backend::auth::hash_password,
-    shared::user::{User, UserRole},
+    backend::auth::{breached, hash_password},
+    shared::{
+        invite::{EmailCheckMode, RegistrationMode},
+        user::{CheckEmail, RegisterPayload, User, UserPermission, UserRole},
+    },
 };
 
-use super::{auth::verify_password, errors::BackendError};
-
-impl From<tokio_postgres::Row> for User {
-    #[inline]
-    fn from(row: tokio_postgres::Row) -> Self {
-        Self {
-            id: row.get(0),
-            c_at: row.get(1),
-            u_at: row.get(2),
-            skey: row.get(3),
-            email: row.get(4),
-            role: row.get(5),
-        }
-    }
-}
+use super::{
+    BackendState,
+    audit::AuditContext,
+    auth::verify_password,
+    challenge::verify_solution,
+    errors::BackendError,
+    invite::invite_is_valid,
+    notify::{SecurityEvent, notify, supported_locale},
+    repository::{NewAccount, UserRepository},
+};
 
-#[instrument(name = "User: create", level = "info", skip(client, password))]
+/// Creates the account, or upgrades the guest, with the invite and challenge it redeems.
+#[instrument(name = "User: create", level = "info", skip(users, password))]
 pub async fn create_user(
-    client: &deadpool_postgres::Client,
-    email: String,
-    password: String,
+    users: &dyn UserRepository,
+    account: &NewAccount<'_>,
+    password: &str,
 ) -> Result<User, BackendError> {
-    info!("Attempting to create user with email: {}", email);
-    let hashed_password = hash_password(&password)?;
-    let stmt = client
-        .prepare_typed_cached(
-            "INSERT INTO app_user (email, password_hash, role) \n
-                                            VALUES ($1, $2, $3) \n
-                                            RETURNING id, c_at, m_at, skey, email, role",
-            &[
-                tokio_postgres::types::Type::TEXT,
-                tokio_postgres::types::Type::TEXT,
-            ],
-        )
-        .await?;
-    let row = client
-        .query_one(&stmt, &[&email, &hashed_password, &UserRole::User])
-        .await?;
-    let user = User::from(row);
+    info!("Attempting to create user with email: {}", account.email);
+    breached::check_password(password)?;
+    let hashed_password = hash_password(password).await?;
+    let user = users.register(account, &hashed_password).await?;
 
-    info!("User created successfully: {}", user.email);
+    match account.guest {
+        Some(guest) => info!("Guest {guest} registered as {}", user.email),
+        None => info!("User created successfully: {}", user.email),
+    }
     Ok(user)
 }
 
-#[instrument(name = "User: set_password", level = "info", skip(client, password))]
+/// Registers the visitor, or the `guest` they're browsing as, from a validated payload.
+///
+/// With the email check disabled a taken email returns `None` instead of failing, the
+/// caller mustn't return the created user either then.
+#[instrument(name = "User: register", level = "info", skip(state, payload))]
+pub async fn register_user(
+    state: &BackendState,
+    guest: Option<i64>,
+    payload: RegisterPayload,
+) -> Result<Option<User>, BackendError> {
+    let registration = &state.registration;
+    match registration.mode {
+        RegistrationMode::Closed => {
+            return Err(BackendError::ValidationError("register.closed".into()));
+        }
+        RegistrationMode::Domains if !registration.allows_email(&payload.email) => {
+            return Err(BackendError::ValidationError(
+                "register.domain-not-allowed".into(),
+            ));
+        }
+        _ => {}
+    }
+    let challenge = verify_solution(state.key.signing(), &payload.challenge)?;
+    // without the email check the answer must not reveal whether the email was taken
+    let hide_taken = registration.email_check == EmailCheckMode::Disabled;
+    let invite =
+        (registration.mode == RegistrationMode::Invite).then(|| payload.invite.unwrap_or_default());
+    // checked before the email so a bad invite fails the same for taken emails
+    if let Some(code) = &invite
+        && hide_taken
+        && !invite_is_valid(&*state.invites, code, Some(&payload.email)).await?
+    {
+        return Err(BackendError::ValidationError("invite.invalid".into()));
+    }
+    let account = NewAccount {
+        email: &payload.email,
+        role: UserRole::User,
+        guest,
+        invite: invite.as_deref(),
+        challenge: Some(&challenge),
+        created_by: None,
+    };
+    match create_user(&*state.users, &account, &payload.password).await {
+        Err(BackendError::DuplicateUser) if hide_taken => {
+            // the challenge is spent all the same, probing costs as much as registering
+            if !state.challenges.redeem(&challenge).await? {
+                return Err(BackendError::ValidationError("challenge.invalid".into()));
+            }
+            Ok(None)
+        }
+        created => created.map(Some),
+    }
+}
+
+/// Whether the email of a validated payload is free, when the email check allows asking.
+#[instrument(
+    name = "User: check email is free",
+    level = "info",
+    skip(state, payload)
+)]
+pub async fn check_email_is_free(
+    state: &BackendState,
+    payload: CheckEmail,
+) -> Result<bool, BackendError> {
+    match state.registration.email_check {
+        EmailCheckMode::Open => {}
+        EmailCheckMode::Disabled => return Err(BackendError::Forbidden),
+        EmailCheckMode::Invite => {
+            let code = payload.invite.as_deref().unwrap_or_default();
+            if !invite_is_valid(&*state.invites, code, None).await? {
+                return Err(BackendError::Forbidden);
+            }
+        }
+    }
+    check_email(&*state.users, payload.email).await
+}
+
+#[instrument(name = "User: set_password", level = "info", skip(users, password))]
 pub async fn set_user_password(
-    client: &deadpool_postgres::Client,
+    users: &dyn UserRepository,
     user: i64,
     password: &str,
 ) -> Result<(), BackendError> {
     info!("Attempting to set user password: {}", &user);
-    let hashed_password = hash_password(password)?;
-    let stmt = client
-        .prepare_typed_cached(
-            "UPDATE app_user \n
-            SET password_hash = $2 \n
-            WHERE id = $1 \n
-            RETURNING true",
-            &[
-                tokio_postgres::types::Type::INT8,
-                tokio_postgres::types::Type::TEXT,
-            ],
-        )
-        .await?;
-    let _row = client.query_one(&stmt, &[&user, &hashed_password]).await?;
+    breached::check_password(password)?;
+    let hashed_password = hash_password(password).await?;
+    users.set_password_hash(user, &hashed_password).await?;
+    // the password is changed already, a failed email mustn't report otherwise
+    if let Err(e) = notify(users, user, SecurityEvent::PasswordChanged).await {
+        error!("failed to notify user {user} of the password change: {e:?}");
+    }
 
     info!("User updated successfully: {user}");
     Ok(())
 }
 
-#[instrument(name = "User: check email", level = "info", skip(client))]
-pub async fn check_email(
-    client: &deadpool_postgres::Client,
-    email: String,
-) -> Result<bool, BackendError> {
+/// Changes the role of `user`, audited under `permission`, and tells the user about it.
+#[instrument(name = "User: set role", level = "info", skip(users))]
+pub async fn set_user_role(
+    users: &dyn UserRepository,
+    context: &AuditContext,
+    permission: UserPermission,
+    user: i64,
+    role: UserRole,
+) -> Result<User, BackendError> {
+    let updated = users.set_role(context, permission, user, role).await?;
+    if let Err(e) = notify(users, user, SecurityEvent::RoleChanged { role }).await {
+        error!("failed to notify user {user} of the role change: {e:?}");
+    }
+
+    info!("User {user} role set to {role:?}");
+    Ok(updated)
+}
+
+/// Finds a user by id or email.
+#[instrument(name = "User: find", level = "info", skip(users))]
+pub async fn find_user(
+    users: &dyn UserRepository,
+    id_or_email: &str,
+) -> Result<User, BackendError> {
+    users
+        .find(id_or_email)
+        .await?
+        .ok_or_else(|| BackendError::NotFound("user".into()))
+}
+
+/// The users whose email contains `search`, oldest first.
+#[instrument(name = "User: list", level = "info", skip(users))]
+pub async fn list_users(
+    users: &dyn UserRepository,
+    search: Option<&str>,
+    limit: i64,
+) -> Result<Vec<User>, BackendError> {
+    users.list(search, limit).await
+}
+
+/// Signs `user` out everywhere: rotating the `skey` ends every session and access token,
+/// and the refresh tokens are revoked. The api tokens are left alone.
+#[instrument(name = "User: revoke sessions", level = "info", skip(users))]
+pub async fn revoke_sessions(users: &dyn UserRepository, user: i64) -> Result<(), BackendError> {
+    users.revoke_sessions(user).await?;
+
+    info!("Sessions of user {user} revoked");
+    Ok(())
+}
+
+/// Stores the language the security emails are sent in, one of the bundled locales.
+#[instrument(name = "User: set locale", level = "debug", skip(users))]
+pub async fn set_user_locale(
+    users: &dyn UserRepository,
+    user: i64,
+    locale: &str,
+) -> Result<(), BackendError> {
+    let Some(locale) = supported_locale(locale) else {
+        return Err(BackendError::ValidationError("locale.unsupported".into()));
+    };
+    users.set_locale(user, &locale.to_string()).await
+}
+
+/// Records where the user logged in from and emails them when it's a new ip or device.
+///
+/// The login succeeded already, failures are only logged.
+pub async fn track_login(
+    users: &dyn UserRepository,
+    user: i64,
+    info: &super::client_info::ClientInfo,
+) {
+    let new_source = match users
+        .record_login_source(user, info.ip, info.user_agent.as_deref())
+        .await
+    {
+        Ok(new_source) => new_source,
+        Err(e) => {
+            error!("failed to record the login source of user {user}: {e:?}");
+            return;
+        }
+    };
+    if new_source {
+        let event = SecurityEvent::NewLogin {
+            ip: info.ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            device: info.user_agent.clone().unwrap_or_default(),
+        };
+        if let Err(e) = notify(users, user, event).await {
+            error!("failed to notify user {user} of the new login: {e:?}");
+        }
+    }
+}
+
+/// Replaces the stored hash with one computed with the current Argon2 parameters.
+///
+/// The password was already verified, so it isn't checked against the breached list.
+#[instrument(name = "User: rehash password", level = "info", skip(users, password))]
+pub async fn rehash_user_password(
+    users: &dyn UserRepository,
+    user: i64,
+    password: &str,
+) -> Result<(), BackendError> {
+    let hashed_password = hash_password(password).await?;
+    users.set_password_hash(user, &hashed_password).await?;
+
+    info!("User password rehashed: {user}");
+    Ok(())
+}
+
+#[instrument(name = "User: check email", level = "info", skip(users))]
+pub async fn check_email(users: &dyn UserRepository, email: String) -> Result<bool, BackendError> {
     info!("Attempting to check if email it's free: {email}");
-    let stmt = client
-        .prepare_typed_cached(
-            "SELECT true FROM app_user where email = $1",
-            &[tokio_postgres::types::Type::TEXT],
-        )
-        .await?;
-    // check if the user email in db
-    let row = client.query_opt(&stmt, &[&email]).await?;
-    if row.is_some() { Ok(false) } else { Ok(true) }
+    Ok(!users.email_taken(&email).await?)
 }
 
 #[instrument(
     name = "User: validate password",
     level = "info",
-    skip(client, password)
+    skip(users, password)
 )]
 pub async fn validate_password(
-    client: &deadpool_postgres::Client,
+    users: &dyn UserRepository,
     user: i64,
     password: &str,
 ) -> Result<(), BackendError> {
-    let stmt = client
-        .prepare_typed_cached(
-            "SELECT password_hash \n
-     FROM app_user \n
-     WHERE id = $1",
-            &[tokio_postgres::types::Type::INT8],
+    match users.password_hash(user).await? {
+        Some(hash) => verify_password(password, &hash).await,
+        // guests have no password to confirm
+        None => Err(BackendError::ValidationError("frm-password.invalid".into())),
+    }
+}
+
+#[instrument(name = "User: create guest", level = "info", skip(users))]
+pub async fn create_guest(users: &dyn UserRepository) -> Result<User, BackendError> {
+    let user = users.create_guest().await?;
+
+    info!("Guest created: {}", user.id);
+    Ok(user)
+}
+
+/// Deletes the guests older than `max_age_hours`, their session already expired and
+/// without credentials nobody can log in to them again.
+#[instrument(name = "User: purge guests", level = "info", skip(users))]
+pub async fn purge_guests(
+    users: &dyn UserRepository,
+    max_age_hours: i64,
+) -> Result<u64, BackendError> {
+    let purged = users.purge_guests(max_age_hours).await?;
+    if purged > 0 {
+        info!("Purged {purged} abandoned guests");
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_extra::extract::cookie::Key;
+
+    use super::{check_email_is_free, create_guest, register_user, set_user_role};
+    use crate::backend::{
+        Argon2Config, BackendState, ChallengeConfig, NativeTokenConfig, RegistrationConfig,
+        SessionConfig,
+        audit::{self, AuditContext, AuditKey},
+        auth::init_password_hashing,
+        challenge::issue_challenge,
+        errors::BackendError,
+        invite::{create_invite, list_invites},
+        repository::Repositories,
+    };
+    use crate::shared::{
+        audit::AuditFilter,
+        challenge::{ChallengeSolution, is_solution},
+        invite::{EmailCheckMode, RegistrationMode},
+        user::{CheckEmail, RegisterPayload, UserPermission, UserRole},
+    };
+
+    const PASSWORD: &str = "c0rrect-h0rse";
+
+    async fn state(mode: RegistrationMode, email_check: EmailCheckMode) -> BackendState {
+        audit::set_key(AuditKey(vec![7; 32]));
+        init_password_hashing(&Argon2Config {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+            max_blocking: 4,
+        })
+        .expect("valid argon2 parameters");
+        BackendState::new(
+            Repositories::memory(),
+            Key::generate(),
+            RegistrationConfig {
+                mode,
+                domains: vec!["example.com".into()],
+                email_check,
+            },
+            NativeTokenConfig {
+                access_minutes: 15,
+                refresh_days: 30,
+            },
+            SessionConfig {
+                idle_minutes: 60,
+                remember_days: 30,
+                max_age_hours: 24,
+                reauth_minutes: 10,
+                secure_cookie: false,
+            },
+            ChallengeConfig {
+                base_difficulty: 4,
+                max_difficulty: 4,
+                signups_per_step: 100,
+                minutes: 5,
+            },
+            0,
+        )
+        .await
+    }
+
+    async fn solved(state: &BackendState) -> ChallengeSolution {
+        let challenge = issue_challenge(state).await.expect("issued");
+        let counter = (0..)
+            .find(|&c| is_solution(&challenge.token, c, challenge.difficulty))
+            .expect("a solution");
+        ChallengeSolution {
+            token: challenge.token,
+            counter,
+        }
+    }
+
+    fn payload(
+        email: &str,
+        invite: Option<&str>,
+        challenge: &ChallengeSolution,
+    ) -> RegisterPayload {
+        RegisterPayload {
+            email: email.into(),
+            password: PASSWORD.into(),
+            invite: invite.map(Into::into),
+            challenge: challenge.clone(),
+        }
+    }
+
+    fn is_validation(result: Result<impl std::fmt::Debug, BackendError>, key: &str) -> bool {
+        matches!(result, Err(BackendError::ValidationError(k)) if k == key)
+    }
+
+    #[tokio::test]
+    async fn a_challenge_registers_once() {
+        let state = state(RegistrationMode::Open, EmailCheckMode::Open).await;
+        let challenge = solved(&state).await;
+        let user = register_user(&state, None, payload("a@example.com", None, &challenge))
+            .await
+            .expect("registered")
+            .expect("revealed");
+        assert_eq!(user.role, UserRole::User);
+        let replayed =
+            register_user(&state, None, payload("b@example.com", None, &challenge)).await;
+        assert!(is_validation(replayed, "challenge.invalid"));
+        let taken = register_user(
+            &state,
+            None,
+            payload("a@example.com", None, &solved(&state).await),
         )
-        .await?;
+        .await;
+        assert!(matches!(taken, Err(BackendError::DuplicateUser)));
+    }
 
-    let resp = client.query_one(&stmt, &[&user]).await?;
-    verify_password(password, resp.get::<_, &str>(0))
+    #[tokio::test]
+    async fn hides_a_taken_email_but_spends_the_challenge() {
+        let state = state(RegistrationMode::Open, EmailCheckMode::Disabled).await;
+        register_user(
+            &state,
+            None,
+            payload("a@example.com", None, &solved(&state).await),
+        )
+        .await
+        .expect("registered");
+        let challenge = solved(&state).await;
+        let taken = register_user(&state, None, payload("a@example.com", None, &challenge)).await;
+        assert!(matches!(taken, Ok(None)));
+        let replayed =
+            register_user(&state, None, payload("b@example.com", None, &challenge)).await;
+        assert!(is_validation(replayed, "challenge.invalid"));
+    }
+
+    #[tokio::test]
+    async fn follows_the_registration_mode() {
+        let closed = state(RegistrationMode::Closed, EmailCheckMode::Open).await;
+        let result = register_user(
+            &closed,
+            None,
+            payload("a@example.com", None, &solved(&closed).await),
+        )
+        .await;
+        assert!(is_validation(result, "register.closed"));
+
+        let domains = state(RegistrationMode::Domains, EmailCheckMode::Open).await;
+        let challenge = solved(&domains).await;
+        let result =
+            register_user(&domains, None, payload("a@example.org", None, &challenge)).await;
+        assert!(is_validation(result, "register.domain-not-allowed"));
+        // a refused registration doesn't spend the challenge
+        register_user(&domains, None, payload("a@example.com", None, &challenge))
+            .await
+            .expect("registered");
+    }
+
+    #[tokio::test]
+    async fn redeems_the_invite_with_the_account() {
+        let state = state(RegistrationMode::Invite, EmailCheckMode::Open).await;
+        let invite = create_invite(&*state.invites, 1, Some("a@example.com".into()), 7)
+            .await
+            .expect("created");
+        let challenge = solved(&state).await;
+        let other = register_user(
+            &state,
+            None,
+            payload("b@example.com", Some(&invite.code), &challenge),
+        )
+        .await;
+        assert!(is_validation(other, "invite.invalid"));
+        // all or nothing, the failed registration spent neither the challenge nor the email
+        assert!(state.users.find("b@example.com").await.unwrap().is_none());
+        let user = register_user(
+            &state,
+            None,
+            payload("A@example.com", Some(&invite.code), &challenge),
+        )
+        .await
+        .expect("registered")
+        .expect("revealed");
+
+        let invites = list_invites(&*state.invites).await.expect("listed");
+        assert!(invites[0].redeemed_at.is_some());
+        assert_eq!(invites[0].redeemed_by.as_deref(), Some(user.email.as_str()));
+        let again = register_user(
+            &state,
+            None,
+            payload("a@example.net", Some(&invite.code), &solved(&state).await),
+        )
+        .await;
+        assert!(is_validation(again, "invite.invalid"));
+    }
+
+    #[tokio::test]
+    async fn a_guest_keeps_its_id() {
+        let state = state(RegistrationMode::Open, EmailCheckMode::Open).await;
+        let guest = create_guest(&*state.users).await.expect("guest");
+        let user = register_user(
+            &state,
+            Some(guest.id),
+            payload("a@example.com", None, &solved(&state).await),
+        )
+        .await
+        .expect("registered")
+        .expect("revealed");
+        assert_eq!((user.id, user.role), (guest.id, UserRole::User));
+        let again = register_user(
+            &state,
+            Some(guest.id),
+            payload("b@example.com", None, &solved(&state).await),
+        )
+        .await;
+        assert!(matches!(again, Err(BackendError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn gates_the_email_check() {
+        let check = |email: &str, invite: Option<&str>| CheckEmail {
+            email: email.into(),
+            invite: invite.map(Into::into),
+        };
+        let open = state(RegistrationMode::Open, EmailCheckMode::Open).await;
+        assert!(
+            check_email_is_free(&open, check("a@example.com", None))
+                .await
+                .unwrap()
+        );
+        register_user(
+            &open,
+            None,
+            payload("a@example.com", None, &solved(&open).await),
+        )
+        .await
+        .expect("registered");
+        assert!(
+            !check_email_is_free(&open, check("a@example.com", None))
+                .await
+                .unwrap()
+        );
+
+        let disabled = state(RegistrationMode::Open, EmailCheckMode::Disabled).await;
+        let result = check_email_is_free(&disabled, check("a@example.com", None)).await;
+        assert!(matches!(result, Err(BackendError::Forbidden)));
+
+        let invite_only = state(RegistrationMode::Invite, EmailCheckMode::Invite).await;
+        let result = check_email_is_free(&invite_only, check("a@example.com", Some("nope"))).await;
+        assert!(matches!(result, Err(BackendError::Forbidden)));
+        let invite = create_invite(&*invite_only.invites, 1, None, 7)
+            .await
+            .expect("created");
+        let result =
+            check_email_is_free(&invite_only, check("a@example.com", Some(&invite.code))).await;
+        assert!(result.unwrap());
+    }
+
+    #[tokio::test]
+    async fn audits_the_role_changes() {
+        let state = state(RegistrationMode::Open, EmailCheckMode::Open).await;
+        let user = register_user(
+            &state,
+            None,
+            payload("a@example.com", None, &solved(&state).await),
+        )
+        .await
+        .expect("registered")
+        .expect("revealed");
+        let context = AuditContext::cli();
+        let permission = UserPermission::MarkAsNaughty;
+        set_user_role(
+            &*state.users,
+            &context,
+            permission,
+            user.id,
+            UserRole::Naughty,
+        )
+        .await
+        .expect("role set");
+        set_user_role(
+            &*state.users,
+            &context,
+            UserPermission::ProDemoteUser,
+            user.id,
+            UserRole::Staff,
+        )
+        .await
+        .expect("role set");
+
+        let filter = AuditFilter {
+            permission: Some(permission),
+            ..AuditFilter::default()
+        };
+        let records = state.audit.list(&filter).await.expect("listed");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].action, "set_role");
+        assert_eq!(records[0].target_email.as_deref(), Some("a@example.com"));
+        assert_eq!(records[0].before, r#"{"role":"User"}"#);
+        let verification = state.audit.verify().await.expect("verified");
+        assert_eq!((verification.checked, verification.broken_at), (2, None));
+    }
 }
@@ -0,0 +1,226 @@
+//! Administrative tasks run against the database of the server, e.g. creating the first admin.
+//!
+//! Reads the same configuration as the server, run it with `cargo run --bin manage -- --help`.
+
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use validator::Validate;
+
+use dioxus_daisy_auth_i18n_start::backend::{
+    self, AppConfig, audit,
+    config::ConfigArgs,
+    errors::BackendError,
+    migrate,
+    repository::{NewAccount, Repositories},
+    user,
+};
+use dioxus_daisy_auth_i18n_start::shared::user::{Credentials, UserPermission, UserRole};
+
+#[derive(Debug, Parser)]
+#[command(about = "Administrative tasks for the server database")]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Applies the pending migrations.
+    Migrate,
+    /// Creates an admin, the password is read from stdin.
+    CreateAdmin { email: String },
+    /// Changes the role of a user, recorded in the audit log.
+    SetRole {
+        /// Id or email of the user.
+        user: String,
+        role: UserRole,
+    },
+    /// Sets a new password, read from stdin, and signs the user out everywhere.
+    ResetPassword {
+        /// Id or email of the user.
+        user: String,
+    },
+    /// Lists the users, optionally only those whose email contains `search`.
+    ListUsers {
+        search: Option<String>,
+        #[arg(long, default_value_t = 100)]
+        limit: i64,
+    },
+    /// Signs the user out of every session and device.
+    RevokeSessions {
+        /// Id or email of the user.
+        user: String,
+    },
+    /// Checks the configuration, and that the database is reachable and up to date.
+    CheckConfig,
+    /// Checks the hash chain of the audit log.
+    VerifyAuditLog,
+}
+
+/// Reads a line from stdin, so the password can be piped instead of typed.
+fn read_password(email: &str) -> Result<String, String> {
+    eprint!("password: ");
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .map_err(|e| format!("failed to read the password: {e}"))?;
+    let password = password.trim_end_matches(['\r', '\n']).to_owned();
+    // the same rules as the login, or the user couldn't log in with it
+    Credentials {
+        email: email.to_owned(),
+        password: password.clone(),
+        locale: None,
+        remember: false,
+    }
+    .validate()
+    .map_err(|e| format!("invalid credentials: {e}"))?;
+    Ok(password)
+}
+
+async fn run(command: Command, config: AppConfig) -> Result<String, String> {
+    // the password rules and the security emails are the same as the server's
+    backend::auth::init_password_hashing(&config.argon2)
+        .map_err(|e| format!("invalid argon2 parameters: {e}"))?;
+    backend::auth::breached::init(config.breached_passwords.as_deref())
+        .map_err(|e| format!("failed to load breached passwords file: {e}"))?;
+    backend::mail::init(&config.mail).map_err(|e| format!("invalid mail configuration: {e}"))?;
+    let pool = config.postgres.pool();
+    let repositories = Repositories::postgres(backend::metrics::Db::new(pool.clone()));
+    let users = &*repositories.users;
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|e| format!("failed to connect to the database: {e}"))?;
+    let backend_error = |e: BackendError| format!("{e:?}");
+    match command {
+        Command::Migrate => match migrate::run(&mut client).await {
+            Ok(applied) if applied.is_empty() => Ok("database is up to date".into()),
+            Ok(applied) => Ok(format!("applied migrations {applied:?}")),
+            Err(e) => Err(format!("failed to migrate the database: {e}")),
+        },
+        Command::CreateAdmin { email } => {
+            let password = read_password(&email)?;
+            let account = NewAccount {
+                email: &email,
+                role: UserRole::Admin,
+                guest: None,
+                invite: None,
+                challenge: None,
+                created_by: Some(&audit::AuditContext::cli()),
+            };
+            let admin = user::create_user(users, &account, &password)
+                .await
+                .map_err(backend_error)?;
+            Ok(format!("created admin {} (#{})", admin.email, admin.id))
+        }
+        Command::SetRole { user, role } => {
+            let target = user::find_user(users, &user).await.map_err(backend_error)?;
+            let permission = if role == UserRole::Naughty {
+                UserPermission::MarkAsNaughty
+            } else {
+                UserPermission::ProDemoteUser
+            };
+            let target = user::set_user_role(
+                users,
+                &audit::AuditContext::cli(),
+                permission,
+                target.id,
+                role,
+            )
+            .await
+            .map_err(backend_error)?;
+            Ok(format!(
+                "{} (#{}) is now {:?}",
+                target.email, target.id, target.role
+            ))
+        }
+        Command::ResetPassword { user } => {
+            let target = user::find_user(users, &user).await.map_err(backend_error)?;
+            let password = read_password(&target.email)?;
+            user::set_user_password(users, target.id, &password)
+                .await
+                .map_err(backend_error)?;
+            user::revoke_sessions(users, target.id)
+                .await
+                .map_err(backend_error)?;
+            Ok(format!(
+                "password of {} (#{}) reset",
+                target.email, target.id
+            ))
+        }
+        Command::ListUsers { search, limit } => {
+            let listed = user::list_users(users, search.as_deref(), limit)
+                .await
+                .map_err(backend_error)?;
+            Ok(listed
+                .iter()
+                .map(|u| {
+                    let c_at = u.c_at.format("%Y-%m-%d %H:%M");
+                    let role = format!("{:?}", u.role);
+                    format!("{:>8}  {role:<8} {c_at}  {}", u.id, u.email)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        Command::RevokeSessions { user } => {
+            let target = user::find_user(users, &user).await.map_err(backend_error)?;
+            user::revoke_sessions(users, target.id)
+                .await
+                .map_err(backend_error)?;
+            Ok(format!(
+                "signed {} (#{}) out everywhere",
+                target.email, target.id
+            ))
+        }
+        Command::CheckConfig => match migrate::pending(&client).await {
+            Ok(pending) if pending.is_empty() => {
+                Ok("configuration is valid, database is up to date".into())
+            }
+            Ok(pending) => Err(format!(
+                "configuration is valid, but migrations {pending:?} are pending, run `manage migrate`"
+            )),
+            Err(e) => Err(format!(
+                "failed to check the migrations, run `manage migrate` if none was applied: {e}"
+            )),
+        },
+        Command::VerifyAuditLog => {
+            let result = repositories.audit.verify().await.map_err(backend_error)?;
+            match result.broken_at {
+                None => Ok(format!(
+                    "audit log intact, {} records checked, head {}",
+                    result.checked, result.head
+                )),
+                Some(id) => Err(format!(
+                    "audit log broken at record {id}, {} records before it are intact",
+                    result.checked
+                )),
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match AppConfig::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    audit::set_key(config.audit_key.clone());
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start the runtime");
+    match runtime.block_on(run(cli.command, config)) {
+        Ok(message) => {
+            println!("{message}");
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use dioxus::prelude::*;
 use dioxus_i18n::tid;
 
-use crate::app::AppGlobalState;
+use crate::{app::AppGlobalState, shared::request_id::split_request_id};
 
 pub enum Alert {
     Info,
@@ -31,9 +31,27 @@ impl Alert {
     }
 }
 
+/// Copies `text` to the clipboard.
+fn copy(text: &str) {
+    #[cfg(feature = "web")]
+    if let Some(window) = web_sys::window() {
+        // a failure only means the id has to be selected by hand
+        let _ = window.navigator().clipboard().write_text(text);
+    }
+    #[cfg(any(feature = "desktop", feature = "mobile"))]
+    document::eval(&format!("navigator.clipboard.writeText({text:?})"));
+    #[cfg(not(any(feature = "web", feature = "desktop", feature = "mobile")))]
+    let _ = text;
+}
+
 #[component]
 pub fn AlertDisplay() -> Element {
     let mut alert_msg = use_context::<AppGlobalState>().alert;
+    let mut copied = use_signal(|| false);
+    use_effect(move || {
+        alert_msg.read();
+        copied.set(false);
+    });
 
     let run_timeout = dioxus_time::use_timeout(std::time::Duration::from_secs(10), move |()| {
         tracing::debug!("timer closed alert message");
@@ -42,12 +60,17 @@ pub fn AlertDisplay() -> Element {
     let close = move |_: Event<_>| alert_msg.set(None);
     if let Some((alert, msg)) = &*alert_msg.read() {
         tracing::debug!("alert display with message: {msg}");
+        let (msg, request_id) = split_request_id(msg);
+        let request_id = request_id.map(ToOwned::to_owned);
         let message = if let Some(m) = msg.strip_prefix("error running server function: ") {
             tid!(m)
         } else {
-            msg.clone()
+            msg.to_owned()
         };
-        run_timeout.action(());
+        // stays open until closed when there's a request id to report
+        if request_id.is_none() {
+            run_timeout.action(());
+        }
         rsx! {
             div {
                 role: "alert",
@@ -64,7 +87,27 @@ pub fn AlertDisplay() -> Element {
                         d: "{alert.path()}"
                     }
                 }
-                span { {message} }
+                div {
+                    span { {message} }
+                    if let Some(id) = request_id {
+                        div { class: "flex items-center gap-2 text-xs",
+                            span { {tid!("request-id")} }
+                            code { "{id}" }
+                            button {
+                                class: "btn btn-xs",
+                                onclick: move |_| {
+                                    copy(&id);
+                                    copied.set(true);
+                                },
+                                if copied() {
+                                    {tid!("request-id.copied")}
+                                } else {
+                                    {tid!("bu.copy")}
+                                }
+                            }
+                        }
+                    }
+                }
                 div {
                     button {
                         class: "btn btn-sm btn-primary",
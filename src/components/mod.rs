@@ -10,5 +10,11 @@ pub use echo::Echo;
 mod alert;
 pub use alert::{Alert, AlertDisplay};
 
+mod reauth;
+pub use reauth::{ReauthDialog, show_error};
+
+mod session_timeout;
+pub use session_timeout::SessionTimeout;
+
 mod theme_control;
 pub use theme_control::ThemeControl;
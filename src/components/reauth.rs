@@ -0,0 +1,79 @@
+use dioxus::prelude::*;
+use dioxus_i18n::tid;
+
+use crate::{app::AppGlobalState, components::Alert, shared::request_id::error_key};
+
+/// Error of the sensitive server functions when the password wasn't entered recently.
+const REAUTH_REQUIRED: &str = "reauth.required";
+
+/// Shows a server function error, asking for the password instead when the action
+/// needs a recent login.
+pub fn show_error(mut state: AppGlobalState, e: ServerFnError) {
+    if error_key(&e) == Some(REAUTH_REQUIRED) {
+        state.reauth.set(true);
+    } else {
+        state.alert.set(Some((Alert::Error, e.to_string())));
+    }
+}
+
+/// Asks the logged user for the password, the rejected action can be repeated once confirmed.
+#[component]
+pub fn ReauthDialog() -> Element {
+    let mut state = use_context::<AppGlobalState>();
+
+    let form_submit = move |evt: Event<FormData>| {
+        evt.prevent_default();
+        let password = evt
+            .values()
+            .get("password")
+            .and_then(|v| v.first())
+            .cloned()
+            .unwrap_or_default();
+        async move {
+            match crate::shared::session::reauthenticate(password).await {
+                Ok(()) => {
+                    state.reauth.set(false);
+                    state
+                        .alert
+                        .set(Some((Alert::Info, tid!("reauth.confirmed"))));
+                }
+                Err(e) => state.alert.set(Some((Alert::Error, e.to_string()))),
+            }
+        }
+    };
+
+    if !(state.reauth)() {
+        return rsx! {};
+    }
+    rsx! {
+        dialog { class: "modal modal-open",
+            div { class: "modal-box",
+                h3 { class: "text-lg font-bold", {tid!("reauth")} }
+                p { class: "py-4", {tid!("reauth.description")} }
+                form {
+                    // a fix for bug [prevent_default()](https://github.com/DioxusLabs/dioxus/issues/4303)
+                    action: "#",
+                    method: "dialog",
+                    onsubmit: form_submit,
+                    input {
+                        class: "input w-full",
+                        r#type: "password",
+                        name: "password",
+                        required: true,
+                        autocomplete: "current-password",
+                        placeholder: tid!("frm-password"),
+                    }
+                    div { class: "modal-action",
+                        button {
+                            class: "btn",
+                            r#type: "button",
+                            onclick: move |_| state.reauth.set(false),
+                            {tid!("bu.cancel")}
+                        }
+                        button { class: "btn btn-primary", r#type: "submit", {tid!("reauth.confirm")} }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use dioxus::prelude::*;
+use dioxus_i18n::tid;
+
+use crate::{
+    app::{AppGlobalState, Route},
+    components::Alert,
+    shared::user::LoggedUser,
+};
+
+/// Seconds before the session expires the warning is shown.
+const WARNING_SECS: i64 = 120;
+
+/// Warns the logged user before the web session expires and lets them extend it.
+///
+/// The desktop and mobile clients refresh their tokens instead, so it renders nothing there.
+#[component]
+pub fn SessionTimeout() -> Element {
+    let mut logged = use_context::<Signal<Option<LoggedUser>>>();
+    let mut alert = use_context::<AppGlobalState>();
+    let nav = use_navigator();
+    let mut round = use_signal(|| 0_u32);
+    let mut warning = use_signal(|| false);
+
+    let status = use_resource(move || async move {
+        round();
+        let logged_in = logged.read().is_some();
+        if cfg!(feature = "server") || cfg!(any(feature = "desktop", feature = "mobile")) {
+            return None;
+        }
+        if !logged_in {
+            return None;
+        }
+        crate::shared::session::extend_session().await.ok()
+    });
+
+    // restarted, and the previous countdown dropped, whenever the status is fetched again
+    let _countdown = use_resource(move || async move {
+        let Some(Some(status)) = status.read().as_ref().copied() else {
+            return;
+        };
+        let warn_in = (status.expires_in - WARNING_SECS).max(0);
+        dioxus_time::sleep(Duration::from_secs(warn_in.unsigned_abs())).await;
+        warning.set(true);
+        let left = status.expires_in.clamp(0, WARNING_SECS);
+        dioxus_time::sleep(Duration::from_secs(left.unsigned_abs())).await;
+        warning.set(false);
+        logged.set(None);
+        alert
+            .alert
+            .set(Some((Alert::Warning, tid!("session-timeout.expired"))));
+        nav.push(Route::Login {});
+    });
+
+    let extend = move |_| {
+        warning.set(false);
+        round += 1;
+    };
+    let logout = move |_| async move {
+        let _ = crate::shared::user::user_session_logout().await;
+        warning.set(false);
+        logged.set(None);
+        alert.alert.set(Some((Alert::Info, tid!("logout.suc"))));
+        nav.push(Route::Home {});
+    };
+
+    rsx! {
+        dialog { class: if warning() { "modal modal-open" } else { "modal" },
+            div { class: "modal-box",
+                h3 { class: "text-lg font-bold", {tid!("session-timeout")} }
+                p { class: "py-4", {tid!("session-timeout.description")} }
+                div { class: "modal-action",
+                    button { class: "btn", onclick: logout, {tid!("logout")} }
+                    button { class: "btn btn-primary", onclick: extend, {tid!("session-timeout.extend")} }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! CSRF token of the web build.
+//!
+//! The server renders the token in the SSR document, [`CsrfClient`] echoes it back in
+//! [`CSRF_HEADER`] on every server function call, see `crate::backend::auth::csrf`.
+//! It also sends the trace of the call, see [`crate::trace`].
+
+use std::sync::RwLock;
+
+use dioxus::prelude::server_fn::{
+    self,
+    client::{Client, browser::BrowserClient},
+    response::ClientRes,
+};
+
+/// Header the client echoes the token in.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+static TOKEN: RwLock<String> = RwLock::new(String::new());
+
+/// Keeps the token rendered by the server.
+pub fn set_token(token: &str) {
+    if let Ok(mut current) = TOKEN.write() {
+        token.clone_into(&mut current);
+    }
+}
+
+/// Server function client that sends the CSRF token and the trace context.
+pub struct CsrfClient;
+
+impl<CustErr> Client<CustErr> for CsrfClient {
+    type Request = <BrowserClient as Client<CustErr>>::Request;
+    type Response = <BrowserClient as Client<CustErr>>::Response;
+
+    async fn send(req: Self::Request) -> Result<Self::Response, server_fn::ServerFnError<CustErr>> {
+        if let Ok(token) = TOKEN.read()
+            && !token.is_empty()
+        {
+            req.headers().set(CSRF_HEADER, &token);
+        }
+        let call = crate::trace::start_call(&req.url());
+        let traceparent = match &call {
+            Some(call) => Some(call.traceparent()),
+            None => crate::trace::batch_traceparent(&req.url()),
+        };
+        if let Some(traceparent) = traceparent {
+            req.headers().set(crate::trace::TRACEPARENT, &traceparent);
+        }
+        let res = <BrowserClient as Client<CustErr>>::send(req).await;
+        if let Some(call) = call {
+            let status = res.as_ref().ok().map(ClientRes::<CustErr>::status);
+            crate::trace::end_call(call, status);
+        }
+        res
+    }
+}
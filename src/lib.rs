@@ -0,0 +1,16 @@
+//! The app, shared by the server and client binary in `main.rs` and the `manage` cli.
+
+pub mod app;
+mod components;
+#[cfg(not(any(feature = "desktop", feature = "mobile")))]
+mod csrf;
+mod i18n;
+#[cfg(any(feature = "desktop", feature = "mobile"))]
+pub mod native;
+pub mod shared;
+#[cfg(not(any(feature = "desktop", feature = "mobile")))]
+mod trace;
+mod views;
+
+#[cfg(feature = "server")]
+pub mod backend;
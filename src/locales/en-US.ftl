@@ -23,20 +23,45 @@ bu = Button
     .prev = Previous
     .next = Next
     .close = Close
+    .cancel = Cancel
+    .copy = Copy
 
 user = User
     .not-found = User not found
 
 register = Create Account
     .suc = Account with email { $username } was created with success.
+    .submitted = Unless { $username } was already registered, you can now log in with it.
+    .closed = Registration is closed.
+    .domain-not-allowed = Registration is restricted to allowed email domains.
+
+guest = Guest
+    .start = Continue as guest
+    .started = You are browsing as a guest, register to keep your account.
+    .register = Register to keep your account
+    .upgraded = Your guest account is now registered as { $username }.
 
 login = Login
     .suc = Welcome back { $username }.
     .required = Login required
+    .remember = Remember me
+    .invalid = Invalid email or password.
+    .locked = This account is locked, contact support to recover it.
 
 logout = Logout
     .suc = Your session was terminated.
 
+reauth = Confirm your password
+    .description = This action needs your password, confirm it to continue.
+    .confirm = Confirm
+    .confirmed = Password confirmed, repeat the action to continue.
+    .required = Confirm your password to continue.
+
+session-timeout = Your session is about to expire
+    .description = You will be signed out in a couple of minutes due to inactivity.
+    .extend = Stay signed in
+    .expired = Your session expired, sign in again.
+
 frm-password = Password
     .err = Must be more than 8 characters, including number, lowercase letter, uppercase letter
     .invalid = Invalid password
@@ -44,6 +69,7 @@ frm-password = Password
     .old = Old Password
     .new = New Password
     .suc-change = Your password was changed.
+    .breached = This password appears in a list of known breached or common passwords, choose another one.
 
 frm-email = Email
     .err = Must enter a valid email address.
@@ -52,6 +78,15 @@ frm-email = Email
 
 unexpected = Oops, we encountered an error. Please report this to the developer of this application.
 
+std-err = Error
+    .internal = Something went wrong on our side. If it keeps happening, report it with the request id.
+
+request-id = Request id
+    .copied = Copied
+
+locale = Language
+    .unsupported = This language isn't available.
+
 unauthorized = Unauthorized
 forbidden = Forbidden: You do not have permission to access this resource.
 
@@ -62,3 +97,74 @@ date = Date
 resume = Resume
 
 duplicate = An entry already exists.
+
+rate-limited = Too many attempts, wait a moment and try again.
+
+csrf = Security check
+    .rejected = The request failed a security check, reload the page and try again.
+
+security-mail = If this wasn't you, lock your account and sign out everywhere: { $link }
+    .new-login = New sign-in to your account
+    .new-login-body = Your account was signed in from a new device or location.{"\u000A"}IP: { $ip }{"\u000A"}Device: { $device }
+    .password = Your password was changed
+    .password-body = The password of your account was changed.
+    .role = Your role was changed
+    .role-body = The role of your account was changed to { $role }.
+
+account-lock = Lock account
+    .description = Locking the account signs it out of every device and revokes its tokens, it can't be used until support recovers it.
+    .confirm = Lock my account
+    .done = The account was locked and signed out everywhere.
+    .invalid = This link is invalid, expired or was already used.
+
+challenge = Verification
+    .pending = Still verifying this browser, try again in a few seconds.
+    .solving = Verifying this browser…
+    .invalid = The verification failed, try again.
+    .expired = The verification expired, try again.
+
+invite = Invite
+    .admin = Invites
+    .create = Create Invite
+    .code = Invite code
+    .email = Email (optional)
+    .days = Days until it expires
+    .link = Share this link:
+    .expires-at = Expires at
+    .status = Status
+    .pending = Pending
+    .expired = Expired
+    .redeemed = Redeemed at { $date } by { $email }
+    .invalid = The invite code is invalid, expired or was already used.
+
+audit = Audit
+    .admin = Audit Log
+    .actor = Actor id
+    .target = Target id
+    .permission = Permission
+    .any-permission = Any permission
+    .action = Action
+    .before = Before
+    .after = After
+    .request-id = Request id
+    .since = From
+    .until = To
+    .filter = Filter
+    .export = Export CSV
+    .download = Download the CSV export
+    .verify = Verify chain
+    .intact = The audit log is intact, { $count } records checked.
+    .broken = The audit log was tampered with at record { $id }.
+
+token = Token
+    .admin = API Tokens
+    .create = Create Token
+    .name = Name
+    .days = Days until it expires
+    .scopes = Scopes
+    .expires-at = Expires at
+    .last-used = Last used
+    .secret-once = Copy this token now, it won't be shown again:
+    .revoke = Revoke
+    .revoked = Revoked
+    .not-found = Token not found
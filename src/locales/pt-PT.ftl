@@ -25,20 +25,45 @@ bu = button
     .prev = Anterior
     .next = Seguinte
     .close = Fechar
+    .cancel = Cancelar
+    .copy = Copiar
 
 user = User
     .not-found = Utilizador não encontrado.
 
 register = Criar Conta
     .suc = Conta com o email { $username } foi criada com sucesso.
+    .submitted = A menos que { $username } já estivesse registado, já pode entrar com ele.
+    .closed = O registo está fechado.
+    .domain-not-allowed = O registo está limitado a domínios de e-mail autorizados.
+
+guest = Convidado
+    .start = Continuar como convidado
+    .started = Está a navegar como convidado, registe-se para manter a sua conta.
+    .register = Registe-se para manter a sua conta
+    .upgraded = A sua conta de convidado está agora registada como { $username }.
 
 login = Entrar
     .suc = Bem vindo de novo { $username }.
     .required = Login necessário.
+    .remember = Lembrar-me
+    .invalid = E-mail ou palavra-passe inválidos.
+    .locked = Esta conta está bloqueada, contacte o suporte para a recuperar.
 
 logout = Sair
     .suc = A sessão foi terminda com sucesso.
 
+reauth = Confirme a sua palavra-passe
+    .description = Esta ação precisa da sua palavra-passe, confirme-a para continuar.
+    .confirm = Confirmar
+    .confirmed = Palavra-passe confirmada, repita a ação para continuar.
+    .required = Confirme a sua palavra-passe para continuar.
+
+session-timeout = A sua sessão está prestes a expirar
+    .description = A sessão vai terminar dentro de alguns minutos por inatividade.
+    .extend = Manter sessão
+    .expired = A sua sessão expirou, entre novamente.
+
 frm-password = Palavra-passe
     .err = Deve ter mais de 8 caracteres, incluindo número, letra minúscula e letra maiúscula
     .invalid = Palavra-passe invalida
@@ -46,6 +71,7 @@ frm-password = Palavra-passe
     .old = Palavra-passe antiga
     .new = Nova Palavra-passe
     .suc-change = Palavra-passe foi alterada.
+    .breached = Esta palavra-passe aparece numa lista de palavras-passe comprometidas ou comuns, escolha outra.
 
 frm-email = E-mail
     .err = Deve introduzir um endereço de e-mail válido.
@@ -54,6 +80,15 @@ frm-email = E-mail
 
 unexpected = Oops, encontrámos um erro. Por favor, relate isto ao programador desta aplicação.
 
+std-err = Erro
+    .internal = Algo correu mal do nosso lado. Se voltar a acontecer, relate-o com o id do pedido.
+
+request-id = Id do pedido
+    .copied = Copiado
+
+locale = Idioma
+    .unsupported = Este idioma não está disponível.
+
 date = Data
     .c-at = Criado em
     .u-at = Modificado em
@@ -61,3 +96,74 @@ date = Data
 resume = Resumo
 
 duplicate = Já existe uma entrada.
+
+rate-limited = Demasiadas tentativas, aguarde um momento e tente novamente.
+
+csrf = Verificação de segurança
+    .rejected = O pedido falhou uma verificação de segurança, recarregue a página e tente novamente.
+
+security-mail = Se não foi você, bloqueie a sua conta e termine todas as sessões: { $link }
+    .new-login = Novo início de sessão na sua conta
+    .new-login-body = A sua conta iniciou sessão a partir de um novo dispositivo ou local.{"\u000A"}IP: { $ip }{"\u000A"}Dispositivo: { $device }
+    .password = A sua palavra-passe foi alterada
+    .password-body = A palavra-passe da sua conta foi alterada.
+    .role = O seu papel foi alterado
+    .role-body = O papel da sua conta foi alterado para { $role }.
+
+account-lock = Bloquear conta
+    .description = Bloquear a conta termina todas as sessões e revoga os tokens, não pode ser usada até o suporte a recuperar.
+    .confirm = Bloquear a minha conta
+    .done = A conta foi bloqueada e todas as sessões terminadas.
+    .invalid = Esta ligação é inválida, expirou ou já foi usada.
+
+challenge = Verificação
+    .pending = Ainda a verificar este navegador, tente novamente dentro de alguns segundos.
+    .solving = A verificar este navegador…
+    .invalid = A verificação falhou, tente novamente.
+    .expired = A verificação expirou, tente novamente.
+
+invite = Convite
+    .admin = Convites
+    .create = Criar Convite
+    .code = Código do convite
+    .email = E-mail (opcional)
+    .days = Dias até expirar
+    .link = Partilhe este link:
+    .expires-at = Expira em
+    .status = Estado
+    .pending = Pendente
+    .expired = Expirado
+    .redeemed = Usado em { $date } por { $email }
+    .invalid = O código do convite é inválido, expirou ou já foi usado.
+
+audit = Auditoria
+    .admin = Registo de auditoria
+    .actor = Id do autor
+    .target = Id do alvo
+    .permission = Permissão
+    .any-permission = Qualquer permissão
+    .action = Ação
+    .before = Antes
+    .after = Depois
+    .request-id = Id do pedido
+    .since = De
+    .until = Até
+    .filter = Filtrar
+    .export = Exportar CSV
+    .download = Descarregar a exportação CSV
+    .verify = Verificar cadeia
+    .intact = O registo de auditoria está íntegro, { $count } registos verificados.
+    .broken = O registo de auditoria foi adulterado no registo { $id }.
+
+token = Token
+    .admin = Tokens de API
+    .create = Criar Token
+    .name = Nome
+    .days = Dias até expirar
+    .scopes = Permissões
+    .expires-at = Expira em
+    .last-used = Último uso
+    .secret-once = Copie este token agora, não voltará a ser mostrado:
+    .revoke = Revogar
+    .revoked = Revogado
+    .not-found = Token não encontrado
@@ -1,24 +1,26 @@
-mod app;
-mod components;
-mod i18n;
-mod shared;
-mod views;
-
+use dioxus_daisy_auth_i18n_start::app;
 #[cfg(feature = "server")]
-mod backend;
+use dioxus_daisy_auth_i18n_start::backend;
+#[cfg(any(feature = "desktop", feature = "mobile"))]
+use dioxus_daisy_auth_i18n_start::native;
 
 fn main() {
     #[cfg(feature = "web")]
     // Hydrate the application on the client
     dioxus::launch(app::App);
 
+    #[cfg(any(feature = "desktop", feature = "mobile"))]
+    {
+        native::init();
+        dioxus::launch(app::App);
+    }
+
     // Launch axum on the server
     #[cfg(feature = "server")]
     {
-        tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(async move {
-                backend::launch_server(app::App).await;
-            });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            backend::launch_server(app::App).await;
+        });
     }
 }
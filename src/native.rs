@@ -0,0 +1,184 @@
+//! Authentication of the desktop and mobile builds.
+//!
+//! Native webviews don't keep the session cookie reliably across restarts, so these builds
+//! log in with an access and refresh token pair, persisted in the platform data directory,
+//! and [`NativeClient`] attaches the access token to every server function call.
+
+use std::{
+    fs,
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use chrono::{Duration, Utc};
+use dioxus::prelude::{
+    ServerFnError,
+    server_fn::{
+        self, ServerFn as _,
+        client::{Client, reqwest::ReqwestClient},
+    },
+};
+use reqwest::header::{AUTHORIZATION, HeaderValue};
+use tokio::sync::Mutex;
+
+use crate::shared::{
+    native_auth::{
+        LoginNative, NativeTokens, RefreshNativeTokens, login_native, refresh_native_tokens,
+        revoke_native_tokens,
+    },
+    user::{Credentials, LoggedUser},
+};
+
+/// The current tokens, locked while refreshing so concurrent calls don't race
+/// to rotate the same refresh token.
+static TOKENS: Mutex<Option<NativeTokens>> = Mutex::const_new(None);
+
+fn tokens_path() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(env!("CARGO_PKG_NAME"))
+        .join("tokens.json")
+}
+
+/// Loads the tokens persisted by a previous run, must be called before launching the app.
+pub fn init() {
+    let tokens = fs::read(tokens_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+    *TOKENS.try_lock().expect("tokens aren't used before launch") = tokens;
+}
+
+fn persist(tokens: Option<&NativeTokens>) {
+    let path = tokens_path();
+    let result = match tokens {
+        Some(tokens) => write_private(
+            &path,
+            &serde_json::to_vec(tokens).expect("tokens are serializable"),
+        ),
+        None => fs::remove_file(&path).or_else(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        }),
+    };
+    if let Err(e) = result {
+        tracing::error!("failed to persist tokens in {}: {e}", path.display());
+    }
+}
+
+/// Writes the file readable only by the current user.
+fn write_private(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(bytes)
+}
+
+/// Returns a valid access token, rotating the pair when it's about to expire, or when the
+/// server rejected `rejected`, e.g. after its key changed.
+fn access_token(rejected: Option<String>) -> Pin<Box<dyn Future<Output = Option<String>> + Send>> {
+    Box::pin(async move {
+        let mut tokens = TOKENS.lock().await;
+        let current = tokens.as_ref()?;
+        // another call may have rotated the pair while this one waited for the lock
+        let was_rejected = rejected.as_ref() == Some(&current.access_token);
+        if !was_rejected && current.access_expires_at > Utc::now() + Duration::seconds(30) {
+            return Some(current.access_token.clone());
+        }
+        if current.refresh_expires_at <= Utc::now() {
+            *tokens = None;
+            persist(None);
+            return None;
+        }
+        match refresh_native_tokens(current.refresh_token.clone()).await {
+            Ok(refreshed) => {
+                let access_token = refreshed.access_token.clone();
+                persist(Some(&refreshed));
+                *tokens = Some(refreshed);
+                Some(access_token)
+            }
+            // the server rejected the refresh token, it was revoked or reused
+            Err(ServerFnError::ServerError(e)) => {
+                tracing::warn!("refresh token rejected: {e}");
+                *tokens = None;
+                persist(None);
+                None
+            }
+            Err(e) => {
+                tracing::warn!("failed to refresh tokens: {e}");
+                None
+            }
+        }
+    })
+}
+
+fn authorize(req: &mut reqwest::Request, token: &str) {
+    if let Ok(value) = HeaderValue::from_str(&format!("Bearer {token}")) {
+        req.headers_mut().insert(AUTHORIZATION, value);
+    }
+}
+
+/// Logs in and keeps the issued tokens.
+pub async fn login(payload: Credentials) -> Result<Option<LoggedUser>, ServerFnError> {
+    let login = login_native(payload).await?;
+    persist(Some(&login.tokens));
+    *TOKENS.lock().await = Some(login.tokens);
+    Ok(Some(login.user))
+}
+
+/// Forgets the tokens and revokes them in the server.
+pub async fn logout() -> Result<(), ServerFnError> {
+    let tokens = TOKENS.lock().await.take();
+    persist(None);
+    if let Some(tokens) = tokens {
+        revoke_native_tokens(tokens.refresh_token).await?;
+    }
+    Ok(())
+}
+
+/// Server function client that sends the access token as `Authorization: Bearer`.
+pub struct NativeClient;
+
+impl<CustErr> Client<CustErr> for NativeClient {
+    type Request = reqwest::Request;
+    type Response = reqwest::Response;
+
+    async fn send(
+        mut req: Self::Request,
+    ) -> Result<Self::Response, server_fn::ServerFnError<CustErr>> {
+        let path = req.url().path();
+        // these are authenticated by the credentials or the refresh token in the payload
+        let anonymous =
+            path.ends_with(LoginNative::PATH) || path.ends_with(RefreshNativeTokens::PATH);
+        if anonymous {
+            return <ReqwestClient as Client<CustErr>>::send(req).await;
+        }
+        let Some(token) = access_token(None).await else {
+            return <ReqwestClient as Client<CustErr>>::send(req).await;
+        };
+        authorize(&mut req, &token);
+        // the body is in memory, so the request can be sent again
+        let retry = req.try_clone();
+        let res = <ReqwestClient as Client<CustErr>>::send(req).await?;
+        if res.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(res);
+        }
+        // the access token was rejected before it expired, e.g. the server restarted with
+        // another key, so the pair is rotated and the call retried once
+        match (retry, access_token(Some(token)).await) {
+            (Some(mut retry), Some(token)) => {
+                authorize(&mut retry, &token);
+                <ReqwestClient as Client<CustErr>>::send(retry).await
+            }
+            _ => Ok(res),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+use crate::backend::auth::SessionWrapper;
+
+use super::{ServerClient, user::UserPermission};
+
+/// An administrative action, as recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub c_at: DateTime<Utc>,
+    pub actor_id: i64,
+    /// `None` once the actor is deleted.
+    pub actor_email: Option<String>,
+    pub permission: UserPermission,
+    pub action: String,
+    pub target_id: Option<i64>,
+    pub target_email: Option<String>,
+    /// JSON of the changed values before the action.
+    pub before: String,
+    /// JSON of the changed values after the action.
+    pub after: String,
+    pub request_id: Option<String>,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditFilter {
+    pub actor_id: Option<i64>,
+    pub target_id: Option<i64>,
+    pub permission: Option<UserPermission>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Result of checking the hash chain of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditVerification {
+    /// Records checked until the end or the first broken link.
+    pub checked: i64,
+    /// Id of the first record that doesn't match its hash or its predecessor.
+    pub broken_at: Option<i64>,
+    /// Hash of the last intact record, to compare with the head logged by the appends.
+    pub head: String,
+}
+
+#[server(ListAuditLog, endpoint = "list_audit_log", client = ServerClient)]
+pub async fn list_audit_log(filter: AuditFilter) -> Result<Vec<AuditRecord>, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    session.require_perm(UserPermission::ViewAuditLog).await?;
+    Ok(session.session.backend.audit.list(&filter).await?)
+}
+
+/// The records matching `filter` as CSV.
+#[server(ExportAuditLog, endpoint = "export_audit_log", client = ServerClient)]
+pub async fn export_audit_log(filter: AuditFilter) -> Result<String, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    session.require_perm(UserPermission::ViewAuditLog).await?;
+    let records = session.session.backend.audit.list(&filter).await?;
+    Ok(crate::backend::audit::to_csv(&records))
+}
+
+#[server(VerifyAuditLog, endpoint = "verify_audit_log", client = ServerClient)]
+pub async fn verify_audit_log() -> Result<AuditVerification, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    session.require_perm(UserPermission::ViewAuditLog).await?;
+    Ok(session.session.backend.audit.verify().await?)
+}
@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::ServerClient;
+
+/// A proof-of-work challenge issued by the server, required to register.
+///
+/// Solving it means finding a `counter` where the SHA-256 of `{token}:{counter}`
+/// starts with `difficulty` zero bits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistrationChallenge {
+    /// Signed by the server, carries the difficulty and the expiry.
+    pub token: String,
+    pub difficulty: u32,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ChallengeSolution {
+    pub token: String,
+    pub counter: u64,
+}
+
+/// Checks if `counter` solves the challenge `token` with `difficulty`.
+pub fn is_solution(token: &str, counter: u64, difficulty: u32) -> bool {
+    let digest = Sha256::digest(format!("{token}:{counter}").as_bytes());
+    let mut zeros = 0;
+    for byte in digest {
+        zeros += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    zeros >= difficulty
+}
+
+/// Hashes tried before yielding, keeps the UI responsive while solving.
+const HASHES_PER_YIELD: u64 = 2_000;
+
+/// Searches the solution in small batches, yielding to the event loop between them
+/// so it can run in the background of the register form.
+pub async fn solve(challenge: &RegistrationChallenge) -> ChallengeSolution {
+    let mut counter = 0;
+    while !is_solution(&challenge.token, counter, challenge.difficulty) {
+        counter += 1;
+        if counter % HASHES_PER_YIELD == 0 {
+            dioxus_time::sleep(std::time::Duration::ZERO).await;
+        }
+    }
+    ChallengeSolution {
+        token: challenge.token.clone(),
+        counter,
+    }
+}
+
+#[server(GetRegistrationChallenge, endpoint = "get_registration_challenge", client = ServerClient)]
+pub async fn get_registration_challenge() -> Result<RegistrationChallenge, ServerFnError> {
+    let auth: axum::Extension<crate::backend::BackendState> = extract().await?;
+    Ok(crate::backend::challenge::issue_challenge(&auth.0).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::is_solution;
+
+    /// The leading zero bits of the hash, counted on its first 128 bits.
+    fn zero_bits(token: &str, counter: u64) -> u32 {
+        let digest = Sha256::digest(format!("{token}:{counter}").as_bytes());
+        u128::from_be_bytes(digest[..16].try_into().unwrap()).leading_zeros()
+    }
+
+    #[test]
+    fn counts_the_leading_zero_bits() {
+        let mut whole_bytes = 0;
+        for counter in 0..5_000 {
+            let zeros = zero_bits("token", counter);
+            assert!(is_solution("token", counter, zeros));
+            assert!(!is_solution("token", counter, zeros + 1));
+            assert!(is_solution("token", counter, 0));
+            if zeros >= 8 {
+                whole_bytes += 1;
+            }
+        }
+        // the count goes on past a zero byte
+        assert!(whole_bytes > 0);
+    }
+
+    #[test]
+    fn depends_on_the_token() {
+        let counter = (0..).find(|&c| is_solution("token", c, 12)).unwrap();
+        assert!(zero_bits("token", counter) >= 12);
+        assert!(!is_solution("other", counter, 12));
+    }
+}
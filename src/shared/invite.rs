@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use validator::Validate;
+
+#[cfg(feature = "server")]
+use crate::backend::auth::SessionWrapper;
+
+use super::ServerClient;
+
+/// Who is allowed to create an account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RegistrationMode {
+    #[default]
+    Open,
+    Closed,
+    /// Requires a single-use invite code created by an admin.
+    Invite,
+    /// Only emails from the allowlisted domains can register.
+    Domains,
+}
+
+impl std::str::FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "closed" => Ok(Self::Closed),
+            "invite" => Ok(Self::Invite),
+            "domains" => Ok(Self::Domains),
+            other => Err(format!("unknown registration mode: {other}")),
+        }
+    }
+}
+
+/// Who can ask if an email is already registered, the answer reveals which accounts exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum EmailCheckMode {
+    #[default]
+    Open,
+    /// Only callers holding a valid invite code.
+    Invite,
+    Disabled,
+}
+
+impl std::str::FromStr for EmailCheckMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "invite" => Ok(Self::Invite),
+            "disabled" => Ok(Self::Disabled),
+            other => Err(format!("unknown email check mode: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invite {
+    pub id: i64,
+    pub code: String,
+    pub email: Option<String>,
+    pub c_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub redeemed_at: Option<DateTime<Utc>>,
+    pub redeemed_by: Option<String>,
+}
+
+/// Struct for invite creation payload (from frontend to backend).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(Validate))]
+pub struct CreateInvite {
+    /// Restricts the invite to this email, when set.
+    #[cfg_attr(feature = "server", validate(email))]
+    pub email: Option<String>,
+    #[cfg_attr(feature = "server", validate(range(min = 1, max = 90)))]
+    pub days: u32,
+}
+
+#[server(GetRegistrationMode, endpoint = "get_registration_mode", client = ServerClient)]
+pub async fn get_registration_mode() -> Result<RegistrationMode, ServerFnError> {
+    let auth: axum::Extension<crate::backend::BackendState> = extract().await?;
+    Ok(auth.0.registration.mode)
+}
+
+#[server(GetEmailCheckMode, endpoint = "get_email_check_mode", client = ServerClient)]
+pub async fn get_email_check_mode() -> Result<EmailCheckMode, ServerFnError> {
+    let auth: axum::Extension<crate::backend::BackendState> = extract().await?;
+    Ok(auth.0.registration.email_check)
+}
+
+/// Returns the email an invite is bound to, used to prefill the register form.
+#[server(GetInviteEmail, endpoint = "get_invite_email", client = ServerClient)]
+pub async fn get_invite_email(code: String) -> Result<Option<String>, ServerFnError> {
+    let auth: axum::Extension<crate::backend::BackendState> = extract().await?;
+    Ok(crate::backend::invite::get_invite_email(&*auth.0.invites, &code).await?)
+}
+
+#[server(SubmitCreateInvite, endpoint = "submit_create_invite", client = ServerClient)]
+pub async fn submit_create_invite(payload: CreateInvite) -> Result<Invite, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    let user = session
+        .require_perm(crate::shared::user::UserPermission::InviteUser)
+        .await?;
+    payload.validate()?;
+    let invites = &*session.session.backend.invites;
+    Ok(
+        crate::backend::invite::create_invite(invites, user.id, payload.email, payload.days)
+            .await?,
+    )
+}
+
+#[server(ListInvites, endpoint = "list_invites", client = ServerClient)]
+pub async fn list_invites() -> Result<Vec<Invite>, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    session
+        .require_perm(crate::shared::user::UserPermission::InviteUser)
+        .await?;
+    Ok(crate::backend::invite::list_invites(&*session.session.backend.invites).await?)
+}
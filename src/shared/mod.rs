@@ -1,13 +1,27 @@
 use dioxus::prelude::*;
+pub mod audit;
+pub mod challenge;
+pub mod invite;
+pub mod native_auth;
+pub mod request_id;
+pub mod session;
+pub mod token;
+pub mod trace;
 pub mod user;
 
-#[server(EchoServer)]
+/// The client used by every server function.
+///
+/// The desktop and mobile builds attach their access token, see `crate::native`,
+/// the web build attaches the CSRF token, see `crate::csrf`.
+#[cfg(any(feature = "desktop", feature = "mobile"))]
+pub type ServerClient = crate::native::NativeClient;
+#[cfg(not(any(feature = "desktop", feature = "mobile")))]
+pub type ServerClient = crate::csrf::CsrfClient;
+
+#[server(EchoServer, endpoint = "echo_server", client = ServerClient)]
 pub async fn echo_server(input: String) -> Result<String, ServerFnError> {
-    let auth: axum::Extension<crate::backend::BackendState> = extract().await?;
-    let pg = auth.0.db.get().await?;
     tracing::debug!("echo {}", &input);
 
-    dbg!(&pg.query("SELECT 1", &[]).await?);
     // The body of server function like this comment are only included on the server. If you have any server-only logic like
     // database queries, you can put it here. Any imports for the server function should either be imported inside the function
     // or imported under a `#[cfg(feature = "server")]` block.
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use validator::Validate;
+
+#[cfg(feature = "server")]
+use crate::backend::{BackendState, auth::SessionWrapper, errors::BackendError};
+
+use super::{
+    ServerClient,
+    user::{Credentials, LoggedUser},
+};
+
+/// Tokens used by the desktop and mobile clients instead of the session cookie.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NativeTokens {
+    pub access_token: String,
+    pub access_expires_at: DateTime<Utc>,
+    pub refresh_token: String,
+    pub refresh_expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NativeLogin {
+    pub user: LoggedUser,
+    pub tokens: NativeTokens,
+}
+
+#[server(LoginNative, endpoint = "login_native", client = ServerClient)]
+pub async fn login_native(payload: Credentials) -> Result<NativeLogin, ServerFnError> {
+    use axum_login::AuthnBackend;
+    let session: SessionWrapper = extract().await?;
+    let info: crate::backend::client_info::ClientInfo = extract().await?;
+    payload.validate()?;
+    let locale = payload.locale.clone();
+    let backend = &session.session.backend;
+    let user = backend
+        .authenticate(payload)
+        .await?
+        .ok_or(BackendError::Unauthorized)?;
+    // a preference of the emails, not worth failing the login over
+    if let Some(locale) = locale
+        && let Err(e) =
+            crate::backend::user::set_user_locale(&*backend.users, user.id, &locale).await
+    {
+        tracing::warn!("ignored the locale {locale:?} of user {}: {e}", user.id);
+    }
+    crate::backend::user::track_login(&*backend.users, user.id, &info).await;
+    let tokens = crate::backend::native_token::issue_tokens(
+        &*backend.tokens,
+        backend.key.signing(),
+        &backend.native_tokens,
+        &user,
+        uuid::Uuid::new_v4(),
+    )
+    .await?;
+    let perms = session.permissions(&user).await?;
+    Ok(NativeLogin {
+        user: LoggedUser { user, perms },
+        tokens,
+    })
+}
+
+#[server(RefreshNativeTokens, endpoint = "refresh_native_tokens", client = ServerClient)]
+pub async fn refresh_native_tokens(refresh_token: String) -> Result<NativeTokens, ServerFnError> {
+    let auth: axum::Extension<BackendState> = extract().await?;
+    Ok(crate::backend::native_token::refresh_tokens(
+        &*auth.0.tokens,
+        auth.0.key.signing(),
+        &auth.0.native_tokens,
+        &refresh_token,
+    )
+    .await?)
+}
+
+#[server(RevokeNativeTokens, endpoint = "revoke_native_tokens", client = ServerClient)]
+pub async fn revoke_native_tokens(refresh_token: String) -> Result<(), ServerFnError> {
+    let auth: axum::Extension<BackendState> = extract().await?;
+    Ok(crate::backend::native_token::revoke_tokens(&*auth.0.tokens, &refresh_token).await?)
+}
@@ -0,0 +1,45 @@
+//! The id of a request, reported with its errors so they can be found in the traces.
+
+use dioxus::prelude::ServerFnError;
+
+/// Header the id is accepted from and returned in.
+#[cfg(feature = "server")]
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Separates the request id the server appends to the message of a failed server function.
+pub const REQUEST_ID_SEPARATOR: &str = " @request ";
+
+/// The message of an error and the id of the request that failed, when it has one.
+pub fn split_request_id(message: &str) -> (&str, Option<&str>) {
+    match message.rsplit_once(REQUEST_ID_SEPARATOR) {
+        Some((message, id)) if !id.is_empty() => (message, Some(id)),
+        _ => (message, None),
+    }
+}
+
+/// The message key of a server function error, without the id of the request.
+pub fn error_key(error: &ServerFnError) -> Option<&str> {
+    match error {
+        ServerFnError::ServerError(message) => Some(split_request_id(message).0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dioxus::prelude::ServerFnError;
+
+    use super::error_key;
+
+    #[test]
+    fn error_key_drops_the_request_id() {
+        let error = ServerFnError::ServerError("reauth.required @request 4f2a".into());
+        assert_eq!(error_key(&error), Some("reauth.required"));
+
+        let error = ServerFnError::ServerError("frm-password.breached".into());
+        assert_eq!(error_key(&error), Some("frm-password.breached"));
+
+        let error = ServerFnError::Request("reauth.required".into());
+        assert_eq!(error_key(&error), None);
+    }
+}
@@ -0,0 +1,39 @@
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+use crate::backend::{auth::SessionWrapper, errors::BackendError};
+
+use super::ServerClient;
+
+/// How long the web session of the logged user has left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionStatus {
+    /// Seconds until it expires, by inactivity or by reaching its maximum age.
+    pub expires_in: i64,
+}
+
+/// Every request slides the idle timeout, calling it is enough to extend the session.
+#[server(ExtendSession, endpoint = "extend_session", client = ServerClient)]
+pub async fn extend_session() -> Result<SessionStatus, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    let (Some(_), Some(cookie)) = (&session.session.user, &session.cookie) else {
+        Err(BackendError::LoginRequired)?
+    };
+    let expires_in =
+        crate::backend::auth::session::expires_in(cookie, &session.session.backend.session).await?;
+    Ok(SessionStatus { expires_in })
+}
+
+/// Confirms the password of the logged user, unlocking the sensitive actions for a few minutes.
+#[server(Reauthenticate, endpoint = "reauthenticate", client = ServerClient)]
+pub async fn reauthenticate(password: String) -> Result<(), ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    let (Some(user), Some(cookie)) = (&session.session.user, &session.cookie) else {
+        Err(BackendError::LoginRequired)?
+    };
+    crate::backend::user::validate_password(&*session.session.backend.users, user.id, &password)
+        .await?;
+    crate::backend::auth::session::confirm(cookie).await?;
+    Ok(())
+}
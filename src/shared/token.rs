@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use validator::Validate;
+
+#[cfg(feature = "server")]
+use crate::backend::{auth::SessionWrapper, errors::BackendError};
+
+use super::{ServerClient, user::UserPermission};
+
+/// A personal access token, the secret is only returned once by [`submit_create_api_token`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<UserPermission>,
+    pub c_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreatedApiToken {
+    pub token: ApiToken,
+    pub secret: String,
+}
+
+/// Struct for personal access token creation payload (from frontend to backend).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(Validate))]
+pub struct CreateApiToken {
+    #[cfg_attr(feature = "server", validate(length(min = 1, max = 64)))]
+    pub name: String,
+    pub scopes: HashSet<UserPermission>,
+    #[cfg_attr(feature = "server", validate(range(min = 1, max = 365)))]
+    pub days: u32,
+}
+
+#[server(ListApiTokens, endpoint = "list_api_tokens", client = ServerClient)]
+pub async fn list_api_tokens() -> Result<Vec<ApiToken>, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    let user = session.session.user.ok_or(BackendError::LoginRequired)?;
+    Ok(crate::backend::token::list_tokens(&*session.session.backend.tokens, user.id).await?)
+}
+
+#[server(SubmitCreateApiToken, endpoint = "submit_create_api_token", client = ServerClient)]
+pub async fn submit_create_api_token(
+    payload: CreateApiToken,
+) -> Result<CreatedApiToken, ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    payload.validate()?;
+    let user = session.require_recent_auth().await?;
+    // a token can't grant more than the ones creating it has
+    if !payload.scopes.is_subset(&session.permissions(&user).await?) {
+        Err(BackendError::Forbidden)?;
+    }
+    Ok(crate::backend::token::create_token(
+        &*session.session.backend.tokens,
+        user.id,
+        payload.name,
+        payload.scopes.into_iter().collect(),
+        payload.days,
+    )
+    .await?)
+}
+
+#[server(RevokeApiToken, endpoint = "revoke_api_token", client = ServerClient)]
+pub async fn revoke_api_token(id: i64) -> Result<(), ServerFnError> {
+    let session: SessionWrapper = extract().await?;
+    let user = session.require_recent_auth().await?;
+    Ok(crate::backend::token::revoke_token(&*session.session.backend.tokens, user.id, id).await?)
+}
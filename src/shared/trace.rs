@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use dioxus::prelude::{server_fn::codec::Json, *};
+use serde::{Deserialize, Serialize};
+
+use super::ServerClient;
+
+/// A span ended in the browser, see `crate::trace`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientSpan {
+    pub name: String,
+    /// W3C ids in lowercase hex, 32 and 16 characters.
+    pub trace_id: String,
+    pub span_id: String,
+    /// `None` for the navigation starting the trace.
+    pub parent_span_id: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The page it happened on.
+    pub route: String,
+    /// Set on the server function calls.
+    pub server_fn: Option<String>,
+    pub status: Option<u16>,
+}
+
+/// Hands the spans of the browser to the exporter of the server, which has the collector.
+///
+/// A batch holds the spans of a single trace, the one in its `traceparent`.
+#[server(RecordClientSpans, endpoint = "record_client_spans", client = ServerClient, input = Json)]
+pub async fn record_client_spans(spans: Vec<ClientSpan>) -> Result<(), ServerFnError> {
+    let headers: axum::http::HeaderMap = extract().await?;
+    crate::backend::otlp::record_client_spans(&headers, spans);
+    Ok(())
+}
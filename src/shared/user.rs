@@ -10,22 +10,30 @@ use validator::Validate;
 #[cfg(feature = "server")]
 use crate::backend::auth::SessionWrapper;
 
+use super::{ServerClient, challenge::ChallengeSolution};
+
 /// Struct for user login payload (from frontend to backend).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "server", derive(Validate))]
 pub struct Credentials {
     #[cfg_attr(feature = "server", validate(email))]
     pub email: String,
-    #[cfg_attr(feature = "server", validate(length(min = 8, max = 16)))]
+    #[cfg_attr(feature = "server", validate(length(min = 8, max = 128)))]
     pub password: String,
+    /// Language of the client, the security emails are sent in it.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Keeps the web session for days instead of expiring it after the idle timeout.
+    #[serde(default)]
+    pub remember: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "server", derive(Validate))]
 pub struct ChangePassword {
-    #[cfg_attr(feature = "server", validate(length(min = 8, max = 16)))]
+    #[cfg_attr(feature = "server", validate(length(min = 8, max = 128)))]
     pub old_password: String,
-    #[cfg_attr(feature = "server", validate(length(min = 8, max = 16)))]
+    #[cfg_attr(feature = "server", validate(length(min = 8, max = 128)))]
     pub new_password: String,
 }
 
@@ -36,8 +44,13 @@ pub struct RegisterPayload {
     #[cfg_attr(feature = "server", validate(email))]
     pub email: String,
     // #[cfg_attr(feature = "server", validate(must_match(other = "password2")))]
+    #[cfg_attr(feature = "server", validate(length(min = 8, max = 128)))]
     pub password: String,
     // pub password2: String,
+    /// Invite code, required when registration is invite-only.
+    pub invite: Option<String>,
+    /// Solved proof-of-work from [`get_registration_challenge`](super::challenge::get_registration_challenge).
+    pub challenge: ChallengeSolution,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -45,6 +58,8 @@ pub struct RegisterPayload {
 pub struct CheckEmail {
     #[cfg_attr(feature = "server", validate(email))]
     pub email: String,
+    /// Required when the check is gated by [`EmailCheckMode::Invite`](super::invite::EmailCheckMode).
+    pub invite: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
@@ -66,6 +81,22 @@ pub enum UserRole {
     Naughty,
 }
 
+impl std::str::FromStr for UserRole {
+    type Err = String;
+
+    /// Parses the name of the role, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "admin" => Ok(Self::Admin),
+            "staff" => Ok(Self::Staff),
+            "user" => Ok(Self::User),
+            "guest" => Ok(Self::Guest),
+            "naughty" => Ok(Self::Naughty),
+            other => Err(format!("unknown role: {other}")),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(
     feature = "server",
@@ -83,6 +114,28 @@ pub enum UserPermission {
     EditUserPermissions,
     #[cfg_attr(feature = "server", postgres(name = "read"))]
     Read,
+    #[cfg_attr(feature = "server", postgres(name = "inviteuser"))]
+    InviteUser,
+    #[cfg_attr(feature = "server", postgres(name = "viewauditlog"))]
+    ViewAuditLog,
+}
+
+impl std::str::FromStr for UserPermission {
+    type Err = String;
+
+    /// Parses the `Debug` representation, as rendered in the views.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "DeleteUser" => Ok(Self::DeleteUser),
+            "MarkAsNaughty" => Ok(Self::MarkAsNaughty),
+            "ProDemoteUser" => Ok(Self::ProDemoteUser),
+            "EditUserPermissions" => Ok(Self::EditUserPermissions),
+            "Read" => Ok(Self::Read),
+            "InviteUser" => Ok(Self::InviteUser),
+            "ViewAuditLog" => Ok(Self::ViewAuditLog),
+            other => Err(format!("unknown permission: {other}")),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -101,30 +154,94 @@ pub struct LoggedUser {
     pub perms: HashSet<UserPermission>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub user: LoggedUser,
     pub next: Option<String>,
 }
 
-#[server(SubmitCreateUser)]
+/// Registers the visitor, or the guest they're browsing as.
+///
+/// With the email check disabled the user isn't returned, whether it was created or
+/// the email was already taken.
+#[server(SubmitCreateUser, endpoint = "submit_create_user", client = ServerClient)]
 pub async fn submit_create_user(payload: RegisterPayload) -> Result<Option<User>, ServerFnError> {
+    use crate::shared::invite::EmailCheckMode;
+
     let auth: axum::Extension<crate::backend::BackendState> = extract().await?;
-    let client = auth.0.db.get().await?;
+    let mut session: SessionWrapper = extract().await?;
     payload.validate()?;
+    // a guest registering keeps its id, and everything tied to it
+    let guest = session
+        .session
+        .user
+        .as_ref()
+        .filter(|user| user.role == UserRole::Guest)
+        .map(|user| user.id);
+    let entry = crate::backend::user::register_user(&auth.0, guest, payload).await?;
+    if let Some(entry) = entry.as_ref().filter(|_| guest.is_some()) {
+        // refreshes the role cached in the session
+        session.session.login(entry).await?;
+    }
+    // without the email check the answer must not reveal whether the email was taken
+    let hide_taken = auth.0.registration.email_check == EmailCheckMode::Disabled;
 
-    use crate::backend::user::create_user;
-    let entry = create_user(&client, payload.email, payload.password).await?;
+    Ok(entry.filter(|_| !hide_taken))
+}
 
-    Ok(Some(entry))
+/// Logs the visitor in to a new anonymous guest account, upgraded by registering.
+///
+/// Costs a solved registration challenge like registering, guests are free to create otherwise.
+#[server(StartGuestSession, endpoint = "start_guest_session", client = ServerClient)]
+pub async fn start_guest_session(
+    challenge: ChallengeSolution,
+) -> Result<LoggedUser, ServerFnError> {
+    let mut session: SessionWrapper = extract().await?;
+    if session.session.user.is_some() {
+        Err(crate::backend::errors::BackendError::Forbidden)?
+    }
+    let backend = &session.session.backend;
+    crate::backend::challenge::redeem_challenge(
+        &*backend.challenges,
+        backend.key.signing(),
+        &challenge,
+    )
+    .await?;
+    let user = crate::backend::user::create_guest(&*session.session.backend.users).await?;
+    session.session.login(&user).await?;
+    if let Some(cookie) = &session.cookie {
+        crate::backend::auth::session::start(cookie, &session.session.backend.session, false)
+            .await?;
+    }
+    let perms = session.permissions(&user).await?;
+    Ok(LoggedUser { user, perms })
 }
 
-#[server(LoginUser)]
+#[server(LoginUser, endpoint = "login_user", client = ServerClient)]
 pub async fn login_user(payload: Credentials) -> Result<Option<LoggedUser>, ServerFnError> {
     use axum_login::AuthnBackend;
     let mut session: SessionWrapper = extract().await?;
+    let info: crate::backend::client_info::ClientInfo = extract().await?;
+    let (locale, remember) = (payload.locale.clone(), payload.remember);
     if let Some(user) = session.session.backend.authenticate(payload).await? {
         session.session.login(&user).await?;
+        if let Some(cookie) = &session.cookie {
+            crate::backend::auth::session::start(
+                cookie,
+                &session.session.backend.session,
+                remember,
+            )
+            .await?;
+        }
+        let users = &*session.session.backend.users;
+        // a preference of the emails, not worth failing the login over
+        if let Some(locale) = locale
+            && let Err(e) = crate::backend::user::set_user_locale(users, user.id, &locale).await
+        {
+            tracing::warn!("ignored the locale {locale:?} of user {}: {e}", user.id);
+        }
+        crate::backend::user::track_login(users, user.id, &info).await;
         let perms = session
             .session
             .backend
@@ -138,16 +255,16 @@ pub async fn login_user(payload: Credentials) -> Result<Option<LoggedUser>, Serv
     }
 }
 
-#[server(ChangeUserPassword)]
+#[server(ChangeUserPassword, endpoint = "change_password", client = ServerClient)]
 pub async fn change_password(payload: ChangePassword) -> Result<(), ServerFnError> {
     let session: SessionWrapper = extract().await?;
     match session.session.user {
         Some(user) => {
-            let client = session.session.backend.db.get().await?;
-            crate::backend::user::validate_password(&client, user.id, &payload.old_password)
-                .await?;
+            payload.validate()?;
+            let users = &*session.session.backend.users;
+            crate::backend::user::validate_password(users, user.id, &payload.old_password).await?;
             Ok(
-                crate::backend::user::set_user_password(&client, user.id, &payload.new_password)
+                crate::backend::user::set_user_password(users, user.id, &payload.new_password)
                     .await?,
             )
         }
@@ -155,38 +272,57 @@ pub async fn change_password(payload: ChangePassword) -> Result<(), ServerFnErro
     }
 }
 
-#[server(LogoutUser)]
+#[server(SetUserRole, endpoint = "set_user_role", client = ServerClient)]
+pub async fn set_user_role(user: i64, role: UserRole) -> Result<User, ServerFnError> {
+    use crate::backend::audit::AuditContext;
+
+    let session: SessionWrapper = extract().await?;
+    let headers: axum::http::HeaderMap = extract().await?;
+    // marking as naughty is the only role change staff can make
+    let permission = if role == UserRole::Naughty {
+        UserPermission::MarkAsNaughty
+    } else {
+        UserPermission::ProDemoteUser
+    };
+    let actor = session.require_perm(permission).await?;
+    session.require_recent_auth().await?;
+    let context = AuditContext::new(actor.id, &headers);
+    let users = &*session.session.backend.users;
+    Ok(crate::backend::user::set_user_role(users, &context, permission, user, role).await?)
+}
+
+/// Locks the account from the "this wasn't me" link of a security email.
+#[server(LockAccount, endpoint = "lock_account", client = ServerClient)]
+pub async fn lock_account(token: String) -> Result<(), ServerFnError> {
+    let mut session: SessionWrapper = extract().await?;
+    crate::backend::notify::lock_account(&*session.session.backend.users, &token).await?;
+    session.session.logout().await?;
+    Ok(())
+}
+
+#[server(LogoutUser, endpoint = "logout_user", client = ServerClient)]
 pub async fn logout_user() -> Result<(), ServerFnError> {
     let mut session: SessionWrapper = extract().await?;
     session.session.logout().await?;
     Ok(())
 }
 
-#[server(CheckUserIsFree)]
+#[server(CheckUserIsFree, endpoint = "check_user_is_free", client = ServerClient)]
 pub async fn check_user_is_free(payload: CheckEmail) -> Result<Option<bool>, ServerFnError> {
     let auth: axum::Extension<crate::backend::BackendState> = extract().await?;
-    let client = auth.0.db.get().await?;
     payload.validate()?;
-
-    use crate::backend::user::check_email;
-    let entry = check_email(&client, payload.email).await?;
-
-    Ok(Some(entry))
+    Ok(Some(
+        crate::backend::user::check_email_is_free(&auth.0, payload).await?,
+    ))
 }
 
-#[server(GetUserSession)]
+#[server(GetUserSession, endpoint = "get_user_session", client = ServerClient)]
 pub async fn get_user_session() -> Result<Option<LoggedUser>, ServerFnError> {
     let session: SessionWrapper = extract().await?;
 
-    match session.session.user {
+    match session.session.user.clone() {
         Some(user) => {
-            let perms = session
-                .session
-                .backend
-                .groups
-                .get(&user.role)
-                .ok_or(crate::backend::errors::BackendError::Unauthorized)?
-                .clone();
+            let perms = session.permissions(&user).await?;
 
             Ok(Some(LoggedUser { user, perms }))
         }
@@ -194,7 +330,7 @@ pub async fn get_user_session() -> Result<Option<LoggedUser>, ServerFnError> {
     }
 }
 
-#[server(UserSessionLogout)]
+#[server(UserSessionLogout, endpoint = "user_session_logout", client = ServerClient)]
 pub async fn user_session_logout() -> Result<(), ServerFnError> {
     let mut session: SessionWrapper = extract().await?;
     session.session.logout().await?;
@@ -0,0 +1,233 @@
+//! Browser spans of the web build.
+//!
+//! Every navigation starts a trace, the server function calls made until the next one are
+//! its children and send it in [`TRACEPARENT`], so the server continues it. The ended spans
+//! are handed to the server in batches, see [`crate::shared::trace::record_client_spans`].
+
+use std::{sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+
+use crate::{app::Route, shared::trace::ClientSpan};
+
+/// W3C trace context header.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// How often the ended spans are sent, besides on every navigation.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Spans kept while the server can't be reached, the oldest are dropped first.
+const MAX_PENDING: usize = 256;
+
+struct Navigation {
+    trace_id: String,
+    span_id: String,
+    sampled: bool,
+    route: String,
+    start: DateTime<Utc>,
+}
+
+struct Tracer {
+    /// Share of the traces sampled, rendered by the server, 0 when it exports nothing.
+    ratio: f64,
+    navigation: Option<Navigation>,
+    ended: Vec<ClientSpan>,
+    /// `traceparent` of the batch of spans being sent.
+    batch: Option<String>,
+}
+
+static TRACER: Mutex<Tracer> = Mutex::new(Tracer {
+    ratio: 0.0,
+    navigation: None,
+    ended: Vec::new(),
+    batch: None,
+});
+
+/// A server function call in flight.
+pub struct Call {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+    sampled: bool,
+    route: String,
+    server_fn: String,
+    start: DateTime<Utc>,
+}
+
+impl Call {
+    pub fn traceparent(&self) -> String {
+        let flags = if self.sampled { "01" } else { "00" };
+        format!("00-{}-{}-{flags}", self.trace_id, self.span_id)
+    }
+}
+
+/// The random bytes of a v4 uuid, skipping its version and variant bits.
+fn random_bytes() -> [u8; 8] {
+    let b = uuid::Uuid::new_v4().into_bytes();
+    [b[0], b[1], b[2], b[3], b[4], b[5], b[9], b[10]]
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The decision of the `TraceIdRatioBased` sampler of the server for `low`, the last 8
+/// bytes of the trace id, so both sides keep or drop the same traces.
+fn sampled(ratio: f64, low: [u8; 8]) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    let bound = (ratio.max(0.0) * (1_u64 << 63) as f64) as u64;
+    (u64::from_be_bytes(low) >> 1) < bound
+}
+
+fn push(tracer: &mut Tracer, span: ClientSpan) {
+    if tracer.ended.len() >= MAX_PENDING {
+        tracer.ended.remove(0);
+    }
+    tracer.ended.push(span);
+}
+
+/// Keeps the sample ratio rendered by the server.
+pub fn set_sample_ratio(ratio: f64) {
+    if let Ok(mut tracer) = TRACER.lock() {
+        tracer.ratio = ratio;
+    }
+}
+
+/// Ends the trace of the previous page and starts one for `route`.
+fn navigate(route: String) {
+    let Ok(mut tracer) = TRACER.lock() else {
+        return;
+    };
+    let now = Utc::now();
+    if let Some(previous) = tracer.navigation.take()
+        && previous.sampled
+    {
+        let span = ClientSpan {
+            name: format!("navigate {}", previous.route),
+            trace_id: previous.trace_id,
+            span_id: previous.span_id,
+            parent_span_id: None,
+            start: previous.start,
+            end: now,
+            route: previous.route,
+            server_fn: None,
+            status: None,
+        };
+        push(&mut tracer, span);
+    }
+    if tracer.ratio <= 0.0 {
+        return;
+    }
+    let (high, low) = (random_bytes(), random_bytes());
+    tracer.navigation = Some(Navigation {
+        trace_id: hex(&[high, low].concat()),
+        span_id: hex(&random_bytes()),
+        sampled: sampled(tracer.ratio, low),
+        route,
+        start: now,
+    });
+}
+
+/// Starts the span of a call to `url`, `None` outside of a trace.
+pub fn start_call(url: &str) -> Option<Call> {
+    let server_fn = url.split('?').next()?.rsplit('/').next()?;
+    // sending the spans isn't traced, or every batch would produce the next one
+    if server_fn.starts_with("record_client_spans") {
+        return None;
+    }
+    let tracer = TRACER.lock().ok()?;
+    let navigation = tracer.navigation.as_ref()?;
+    Some(Call {
+        trace_id: navigation.trace_id.clone(),
+        span_id: hex(&random_bytes()),
+        parent_span_id: navigation.span_id.clone(),
+        sampled: navigation.sampled,
+        route: navigation.route.clone(),
+        server_fn: server_fn
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .to_owned(),
+        start: Utc::now(),
+    })
+}
+
+/// The `traceparent` of a batch of spans sent to `url`, the server only takes the spans
+/// of that trace.
+pub fn batch_traceparent(url: &str) -> Option<String> {
+    let server_fn = url.split('?').next()?.rsplit('/').next()?;
+    if !server_fn.starts_with("record_client_spans") {
+        return None;
+    }
+    TRACER.lock().ok()?.batch.take()
+}
+
+/// Ends the span of `call`, `status` is `None` when the request failed.
+pub fn end_call(call: Call, status: Option<u16>) {
+    if !call.sampled {
+        return;
+    }
+    if let Ok(mut tracer) = TRACER.lock() {
+        let span = ClientSpan {
+            name: format!("call {}", call.server_fn),
+            trace_id: call.trace_id,
+            span_id: call.span_id,
+            parent_span_id: Some(call.parent_span_id),
+            start: call.start,
+            end: Utc::now(),
+            route: call.route,
+            server_fn: Some(call.server_fn),
+            status,
+        };
+        push(&mut tracer, span);
+    }
+}
+
+/// Sends the ended spans, a batch for every trace.
+async fn flush() {
+    let spans = match TRACER.lock() {
+        Ok(mut tracer) => std::mem::take(&mut tracer.ended),
+        Err(_) => return,
+    };
+    let mut batches: Vec<Vec<ClientSpan>> = Vec::new();
+    for span in spans {
+        match batches
+            .iter_mut()
+            .find(|batch| batch[0].trace_id == span.trace_id)
+        {
+            Some(batch) => batch.push(span),
+            None => batches.push(vec![span]),
+        }
+    }
+    for batch in batches {
+        let first = &batch[0];
+        let parent = first.parent_span_id.as_ref().unwrap_or(&first.span_id);
+        let traceparent = format!("00-{}-{parent}-01", first.trace_id);
+        // taken by the client sending the batch, before anything else can run
+        if let Ok(mut tracer) = TRACER.lock() {
+            tracer.batch = Some(traceparent);
+        }
+        if let Err(e) = crate::shared::trace::record_client_spans(batch).await {
+            tracing::debug!("failed to send the browser spans: {e}");
+        }
+    }
+}
+
+/// Traces the navigation between the routes, and sends the ended spans.
+pub fn use_navigation_trace() {
+    let route = use_route::<Route>();
+    use_effect(use_reactive!(|route| {
+        navigate(route.to_string());
+        spawn(flush());
+    }));
+    use_future(|| async {
+        if cfg!(feature = "server") {
+            return;
+        }
+        loop {
+            dioxus_time::sleep(FLUSH_INTERVAL).await;
+            flush().await;
+        }
+    });
+}
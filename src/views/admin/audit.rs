@@ -0,0 +1,220 @@
+use chrono::{NaiveDate, Utc};
+use dioxus::prelude::*;
+use dioxus_i18n::tid;
+
+use crate::{
+    app::{AppGlobalState, Route},
+    components::Alert,
+    shared::{
+        audit::{AuditFilter, AuditRecord, export_audit_log, list_audit_log, verify_audit_log},
+        user::{LoggedUser, UserPermission},
+    },
+};
+
+/// Permissions whose actions are audited, offered in the filter.
+const AUDITED: [UserPermission; 4] = [
+    UserPermission::DeleteUser,
+    UserPermission::MarkAsNaughty,
+    UserPermission::ProDemoteUser,
+    UserPermission::EditUserPermissions,
+];
+
+#[component]
+pub fn AdminAuditLog() -> Element {
+    let auth = use_context::<Signal<Option<LoggedUser>>>();
+    let mut app_state = use_context::<AppGlobalState>();
+    let path: Route = use_route();
+    let nav = use_navigator();
+
+    match auth() {
+        Some(user) if user.perms.contains(&UserPermission::ViewAuditLog) => rsx! {
+            AuditLogViewer {}
+        },
+        Some(_) => {
+            app_state.alert.set(Some((Alert::Error, tid!("forbidden"))));
+            rsx!()
+        }
+        None => {
+            app_state.alert.set(Some((Alert::Error, tid!("forbidden"))));
+            app_state.redirect.set(path.to_string());
+            nav.push(Route::Login {});
+            rsx!()
+        }
+    }
+}
+
+/// Start of the day of a `date` input, in UTC.
+fn parse_day(value: Option<&String>) -> Option<chrono::DateTime<Utc>> {
+    NaiveDate::parse_from_str(value?, "%Y-%m-%d")
+        .ok()?
+        .and_hms_opt(0, 0, 0)
+        .map(|d| d.and_utc())
+}
+
+/// Percent-encodes `csv` into a `data:` url the browser can download.
+fn csv_data_url(csv: &str) -> String {
+    let mut url = String::from("data:text/csv;charset=utf-8,");
+    for b in csv.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            url.push(b as char);
+        } else {
+            url.push_str(&format!("%{b:02X}"));
+        }
+    }
+    url
+}
+
+#[component]
+fn AuditLogViewer() -> Element {
+    let mut alert = use_context::<AppGlobalState>();
+    let mut filter = use_signal(AuditFilter::default);
+    let records = use_resource(move || list_audit_log(filter()));
+    let mut download = use_signal(|| None::<String>);
+
+    let form_submit = move |evt: Event<FormData>| {
+        evt.prevent_default();
+        let values = evt.values();
+        let id = |name: &str| {
+            values
+                .get(name)
+                .and_then(|v| v.first())
+                .and_then(|v| v.parse().ok())
+        };
+        let until = parse_day(values.get("until").and_then(|v| v.first()))
+            .map(|d| d + chrono::Duration::days(1));
+        download.set(None);
+        filter.set(AuditFilter {
+            actor_id: id("actor"),
+            target_id: id("target"),
+            permission: values
+                .get("permission")
+                .and_then(|v| v.first())
+                .and_then(|v| v.parse().ok()),
+            since: parse_day(values.get("since").and_then(|v| v.first())),
+            until,
+        });
+    };
+    let export = move |_| async move {
+        match export_audit_log(filter()).await {
+            Ok(csv) => download.set(Some(csv_data_url(&csv))),
+            Err(e) => alert.alert.set(Some((Alert::Error, e.to_string()))),
+        }
+    };
+    let verify = move |_| async move {
+        match verify_audit_log().await {
+            Ok(result) => match result.broken_at {
+                None => alert.alert.set(Some((
+                    Alert::Success,
+                    tid!("audit.intact", count: result.checked),
+                ))),
+                Some(id) => alert
+                    .alert
+                    .set(Some((Alert::Error, tid!("audit.broken", id: id)))),
+            },
+            Err(e) => alert.alert.set(Some((Alert::Error, e.to_string()))),
+        }
+    };
+
+    rsx! {
+        div { class: "card bg-base-200 w-full",
+            div { class: "card-body",
+                h2 { class: "card-title", {tid!("audit.admin")} }
+                form {
+                    // a fix for bug [prevent_default()](https://github.com/DioxusLabs/dioxus/issues/4303)
+                    action: "#",
+                    method: "dialog",
+                    class: "flex flex-wrap gap-2 items-end",
+                    onsubmit: form_submit,
+                    input {
+                        class: "input w-32",
+                        r#type: "number",
+                        name: "actor",
+                        placeholder: tid!("audit.actor"),
+                    }
+                    input {
+                        class: "input w-32",
+                        r#type: "number",
+                        name: "target",
+                        placeholder: tid!("audit.target"),
+                    }
+                    select { class: "select w-48", name: "permission",
+                        option { value: "", {tid!("audit.any-permission")} }
+                        for perm in AUDITED {
+                            option { value: "{perm:?}", "{perm:?}" }
+                        }
+                    }
+                    input {
+                        class: "input w-40",
+                        r#type: "date",
+                        name: "since",
+                        title: tid!("audit.since"),
+                    }
+                    input {
+                        class: "input w-40",
+                        r#type: "date",
+                        name: "until",
+                        title: tid!("audit.until"),
+                    }
+                    button { class: "btn btn-neutral", r#type: "submit", {tid!("audit.filter")} }
+                    button { class: "btn", r#type: "button", onclick: export, {tid!("audit.export")} }
+                    button { class: "btn", r#type: "button", onclick: verify, {tid!("audit.verify")} }
+                }
+                if let Some(url) = download() {
+                    a {
+                        class: "link link-primary",
+                        href: url,
+                        download: "audit-log.csv",
+                        {tid!("audit.download")}
+                    }
+                }
+                match &*records.read() {
+                    Some(Ok(list)) => rsx! {
+                        table { class: "table table-sm",
+                            thead {
+                                tr {
+                                    th { {tid!("date.c-at")} }
+                                    th { {tid!("audit.actor")} }
+                                    th { {tid!("audit.permission")} }
+                                    th { {tid!("audit.action")} }
+                                    th { {tid!("audit.target")} }
+                                    th { {tid!("audit.before")} }
+                                    th { {tid!("audit.after")} }
+                                    th { {tid!("audit.request-id")} }
+                                }
+                            }
+                            tbody {
+                                for record in list.iter() {
+                                    AuditRow { key: "{record.id}", record: record.clone() }
+                                }
+                            }
+                        }
+                    },
+                    Some(Err(e)) => rsx! { div { class: "alert alert-error", {e.to_string()} } },
+                    None => rsx! { span { class: "loading loading-spinner" } },
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn AuditRow(record: AuditRecord) -> Element {
+    let c_at = record.c_at.format("%Y-%m-%d %H:%M:%S");
+    let user = |id: Option<i64>, email: &Option<String>| match (id, email) {
+        (Some(id), Some(email)) => format!("{email} (#{id})"),
+        (Some(id), None) => format!("#{id}"),
+        (None, _) => String::new(),
+    };
+    rsx! {
+        tr { title: "{record.hash}",
+            td { "{c_at}" }
+            td { {user(Some(record.actor_id), &record.actor_email)} }
+            td { "{record.permission:?}" }
+            td { {record.action.clone()} }
+            td { {user(record.target_id, &record.target_email)} }
+            td { code { {record.before.clone()} } }
+            td { code { {record.after.clone()} } }
+            td { code { {record.request_id.clone().unwrap_or_default()} } }
+        }
+    }
+}
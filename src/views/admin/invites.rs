@@ -0,0 +1,165 @@
+use dioxus::prelude::*;
+use dioxus_i18n::tid;
+
+use crate::{
+    app::{AppGlobalState, Route},
+    components::Alert,
+    shared::{
+        invite::{CreateInvite, Invite, list_invites, submit_create_invite},
+        user::{LoggedUser, UserPermission},
+    },
+};
+
+#[component]
+pub fn AdminInvites() -> Element {
+    let auth = use_context::<Signal<Option<LoggedUser>>>();
+    let mut app_state = use_context::<AppGlobalState>();
+    let path: Route = use_route();
+    let nav = use_navigator();
+
+    match auth() {
+        Some(user) if user.perms.contains(&UserPermission::InviteUser) => rsx! {
+            InviteManager {}
+        },
+        Some(_) => {
+            app_state.alert.set(Some((Alert::Error, tid!("forbidden"))));
+            rsx!()
+        }
+        None => {
+            app_state.alert.set(Some((Alert::Error, tid!("forbidden"))));
+            app_state.redirect.set(path.to_string());
+            nav.push(Route::Login {});
+            rsx!()
+        }
+    }
+}
+
+#[component]
+fn InviteManager() -> Element {
+    let mut alert = use_context::<AppGlobalState>();
+    let mut invites = use_resource(list_invites);
+    let mut created = use_signal(|| None::<Invite>);
+
+    let form_submit = move |evt: Event<FormData>| {
+        evt.prevent_default();
+        let values = evt.values();
+        let payload = CreateInvite {
+            email: values
+                .get("email")
+                .and_then(|v| v.first())
+                .filter(|v| !v.is_empty())
+                .cloned(),
+            days: values
+                .get("days")
+                .and_then(|v| v.first())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(7),
+        };
+        async move {
+            match submit_create_invite(payload).await {
+                Ok(invite) => {
+                    created.set(Some(invite));
+                    invites.restart();
+                }
+                Err(e) => {
+                    alert.alert.set(Some((Alert::Error, e.to_string())));
+                }
+            }
+        }
+    };
+
+    let label = tid!("invite.create");
+    rsx! {
+        div { class: "card bg-base-200 w-full",
+            div { class: "card-body",
+                form {
+                    // a fix for bug [prevent_default()](https://github.com/DioxusLabs/dioxus/issues/4303)
+                    action: "#",
+                    method: "dialog",
+                    onsubmit: form_submit,
+                    fieldset { class: "fieldset bg-base-200 border-base-300 rounded-box w-xs border p-4",
+                        legend { class: "fieldset-legend", {label.clone()} }
+                        input {
+                            class: "input",
+                            r#type: "email",
+                            name: "email",
+                            placeholder: tid!("invite.email"),
+                        }
+                        input {
+                            class: "input",
+                            r#type: "number",
+                            name: "days",
+                            min: "1",
+                            max: "90",
+                            value: "7",
+                            title: tid!("invite.days"),
+                        }
+                        button { class: "btn btn-neutral mt-4",
+                            r#type: "submit",
+                            { label }
+                        }
+                    }
+                }
+                if let Some(invite) = created() {
+                    div { class: "alert alert-success",
+                        span { {tid!("invite.link")} }
+                        code { {invite_link(&invite.code)} }
+                    }
+                }
+                match &*invites.read() {
+                    Some(Ok(list)) => rsx! {
+                        table { class: "table",
+                            thead {
+                                tr {
+                                    th { {tid!("invite.code")} }
+                                    th { {tid!("invite.email")} }
+                                    th { {tid!("date.c-at")} }
+                                    th { {tid!("invite.expires-at")} }
+                                    th { {tid!("invite.status")} }
+                                }
+                            }
+                            tbody {
+                                for invite in list.iter() {
+                                    InviteRow { key: "{invite.id}", invite: invite.clone() }
+                                }
+                            }
+                        }
+                    },
+                    Some(Err(e)) => rsx! { div { class: "alert alert-error", {e.to_string()} } },
+                    None => rsx! { span { class: "loading loading-spinner" } },
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn InviteRow(invite: Invite) -> Element {
+    let c_at = invite.c_at.format("%Y-%m-%d %H:%M");
+    let expires_at = invite.expires_at.format("%Y-%m-%d %H:%M");
+    let status = match (&invite.redeemed_at, &invite.redeemed_by) {
+        (Some(at), by) => tid!(
+            "invite.redeemed",
+            date: at.format("%Y-%m-%d %H:%M").to_string(),
+            email: by.clone().unwrap_or_default()
+        ),
+        (None, _) if invite.expires_at < chrono::Utc::now() => tid!("invite.expired"),
+        (None, _) => tid!("invite.pending"),
+    };
+    rsx! {
+        tr {
+            td { code { {invite_link(&invite.code)} } }
+            td { {invite.email.clone().unwrap_or_default()} }
+            td { "{c_at}" }
+            td { "{expires_at}" }
+            td { {status} }
+        }
+    }
+}
+
+fn invite_link(code: &str) -> String {
+    Route::Register {
+        invite: code.to_string(),
+    }
+    .to_string()
+}
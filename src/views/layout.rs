@@ -1,11 +1,19 @@
-use crate::{app::Route, components::AlertDisplay, views::navbar::NavBar};
+use crate::{
+    app::Route,
+    components::{AlertDisplay, ReauthDialog, SessionTimeout},
+    views::navbar::NavBar,
+};
 use dioxus::prelude::*;
 use dioxus_i18n::tid;
 
 #[component]
 pub fn MainLayout() -> Element {
+    #[cfg(not(any(feature = "desktop", feature = "mobile")))]
+    crate::trace::use_navigation_trace();
     rsx! {
         NavBar {  }
+        SessionTimeout {}
+        ReauthDialog {}
         div {
             class: "container mx-auto px-4",
             AlertDisplay {}
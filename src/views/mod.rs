@@ -10,9 +10,14 @@ mod navbar;
 mod layout;
 pub use layout::MainLayout;
 
+mod admin;
+pub use admin::{audit::AdminAuditLog, invites::AdminInvites};
+
 mod user;
 pub use user::{
     create::Register,
+    lock::LockAccount,
     login::Login,
     settings::{UpdatePassword, UserSettings, UserSettingsResume},
+    tokens::ApiTokens,
 };
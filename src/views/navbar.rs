@@ -2,7 +2,7 @@ use crate::{
     app::{AppGlobalState, Route},
     components::{Alert, ThemeControl},
     i18n::LanguageSelect,
-    shared::user::LoggedUser,
+    shared::user::{LoggedUser, UserPermission, UserRole},
 };
 use dioxus::prelude::*;
 use dioxus_i18n::tid;
@@ -46,6 +46,9 @@ fn AuthOptions() -> Element {
     let mut alert = use_context::<AppGlobalState>();
     let nav = use_navigator();
     let logout = move |_: Event<_>| async move {
+        #[cfg(any(feature = "desktop", feature = "mobile"))]
+        let _ = crate::native::logout().await;
+        #[cfg(not(any(feature = "desktop", feature = "mobile")))]
         let _ = crate::shared::user::user_session_logout().await;
         auth.set(None);
         alert.alert.set(Some((Alert::Info, tid!("logout.suc"))));
@@ -77,10 +80,35 @@ fn AuthOptions() -> Element {
                 ul {
                     class: "menu menu-sm dropdown-content bg-base-100 rounded-box z-1 mt-3 w-52 p-2 shadow",
                     tabindex: "0",
-                    li {
-                        Link {
-                            to: Route::UserSettingsResume {  },
-                            {auth_acc.user.email}
+                    if auth_acc.user.role == UserRole::Guest {
+                        li {
+                            Link {
+                                to: Route::Register { invite: String::new() },
+                                {tid!("guest.register")}
+                            }
+                        }
+                    } else {
+                        li {
+                            Link {
+                                to: Route::UserSettingsResume {  },
+                                {auth_acc.user.email}
+                            }
+                        }
+                    }
+                    if auth_acc.perms.contains(&UserPermission::InviteUser) {
+                        li {
+                            Link {
+                                to: Route::AdminInvites {  },
+                                {tid!("invite.admin")}
+                            }
+                        }
+                    }
+                    if auth_acc.perms.contains(&UserPermission::ViewAuditLog) {
+                        li {
+                            Link {
+                                to: Route::AdminAuditLog {  },
+                                {tid!("audit.admin")}
+                            }
                         }
                     }
                     li {
@@ -97,7 +125,7 @@ fn AuthOptions() -> Element {
                 class: "menu menu-horizontal px-1",
                 li {
                     Link {
-                        to: Route::Register {  },
+                        to: Route::Register { invite: String::new() },
                         {tid!("register")}
                     }
                 }
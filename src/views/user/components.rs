@@ -42,6 +42,7 @@ pub fn PasswordInput(name: &'static str, placeholder: String, title: String) ->
 pub struct EmailInputProps {
     name: &'static str,
     placeholder: String,
+    value: Option<String>,
     onblur: Option<EventHandler<FocusEvent>>,
     oninput: Option<EventHandler<FormEvent>>,
 }
@@ -74,6 +75,7 @@ pub fn EmailInput(props: EmailInputProps) -> Element {
             input {
                 placeholder: props.placeholder,
                 name: props.name,
+                value: props.value,
                 r#type: "email",
                 required: true,
                 onblur: move |evt| {
@@ -4,16 +4,53 @@ use dioxus_i18n::tid;
 use crate::{
     app::{AppGlobalState, Route},
     components::Alert,
-    shared::user::{CheckEmail, RegisterPayload, check_user_is_free},
+    shared::{
+        challenge::{get_registration_challenge, solve},
+        invite::{
+            EmailCheckMode, RegistrationMode, get_email_check_mode, get_invite_email,
+            get_registration_mode,
+        },
+        request_id::error_key,
+        user::{
+            CheckEmail, LoggedUser, RegisterPayload, UserRole, check_user_is_free, get_user_session,
+        },
+    },
 };
 
 use super::components::{EmailInput, PasswordInput};
 
+/// The register form, `invite` is prefilled from the `?invite=` query of an invite link.
 #[component]
-pub fn Register() -> Element {
+pub fn Register(invite: String) -> Element {
     let mut alert = use_context::<AppGlobalState>();
+    let mut logged = use_context::<Signal<Option<LoggedUser>>>();
     let mut email = use_signal(String::new);
     let mut email_valid = use_signal(|| None);
+    let mode = use_server_future(get_registration_mode)?;
+    let email_check = use_server_future(get_email_check_mode)?;
+    let invite_code = invite.clone();
+    let invite_email = use_resource(move || {
+        let code = invite_code.clone();
+        async move {
+            if code.is_empty() {
+                None
+            } else {
+                get_invite_email(code).await.ok().flatten()
+            }
+        }
+    });
+
+    // the proof-of-work is solved in the background while the form is filled,
+    // a new challenge is solved after every attempt
+    let mut challenge_round = use_signal(|| 0_u32);
+    let solution = use_resource(move || async move {
+        challenge_round();
+        if cfg!(feature = "server") {
+            return None;
+        }
+        let challenge = get_registration_challenge().await.ok()?;
+        Some(solve(&challenge).await)
+    });
 
     let update_email = move |evt: Event<FormData>| {
         email.set(evt.value());
@@ -24,6 +61,7 @@ pub fn Register() -> Element {
     let form_submit = move |evt: Event<FormData>| {
         evt.prevent_default();
         let values = evt.values();
+        let challenge = solution().flatten();
         let payload = RegisterPayload {
             email: values
                 .get("email")
@@ -35,11 +73,38 @@ pub fn Register() -> Element {
                 .and_then(|v| v.first())
                 .cloned()
                 .unwrap_or_default(),
+            invite: values
+                .get("invite")
+                .and_then(|v| v.first())
+                .filter(|v| !v.is_empty())
+                .cloned(),
+            challenge: challenge.clone().unwrap_or_default(),
         };
 
         async move {
+            if challenge.is_none() {
+                alert
+                    .alert
+                    .set(Some((Alert::Info, tid!("challenge.pending"))));
+                return;
+            }
+            let guest = logged
+                .read()
+                .as_ref()
+                .is_some_and(|u| u.user.role == UserRole::Guest);
+            let submitted = payload.email.clone();
             let resp = crate::shared::user::submit_create_user(payload).await;
+            challenge_round += 1;
             match resp {
+                Ok(Some(user)) if guest => {
+                    // the guest session now belongs to the registered account
+                    logged.set(get_user_session().await.ok().flatten());
+                    alert.alert.set(Some((
+                        Alert::Success,
+                        tid!("guest.upgraded", username: user.email.clone()),
+                    )));
+                    nav.push(Route::Home {});
+                }
                 Ok(Some(user)) => {
                     tracing::info!("Created {:?}", &user);
                     alert.alert.set(Some((
@@ -50,19 +115,48 @@ pub fn Register() -> Element {
                 }
                 Err(e) => {
                     tracing::info!("the error {}", &e);
-                    alert.alert.set(Some((Alert::Error, e.to_string())));
+                    let kind = if error_key(&e) == Some("frm-password.breached") {
+                        Alert::Warning
+                    } else {
+                        Alert::Error
+                    };
+                    alert.alert.set(Some((kind, e.to_string())));
                 }
+                // the server doesn't tell whether the email was taken
                 Ok(None) => {
-                    tracing::info!("Nothing in the response");
+                    if guest {
+                        logged.set(get_user_session().await.ok().flatten());
+                    }
+                    alert.alert.set(Some((
+                        Alert::Info,
+                        tid!("register.submitted", username: submitted),
+                    )));
+                    nav.push(if guest {
+                        Route::Home {}
+                    } else {
+                        Route::Login {}
+                    });
                 }
             }
         }
     };
 
-    let check_if_valid = move |_: Event<_>| async move {
-        if email().len() > 6 {
+    let check_invite = invite.clone();
+    let check_if_valid = move |_: Event<_>| {
+        let invite = Some(check_invite.clone()).filter(|code| !code.is_empty());
+        async move {
+            let check = email_check().and_then(Result::ok).unwrap_or_default();
+            let allowed = match check {
+                EmailCheckMode::Open => true,
+                EmailCheckMode::Invite => invite.is_some(),
+                EmailCheckMode::Disabled => false,
+            };
+            if !allowed || email().len() <= 6 {
+                return;
+            }
             let exists = check_user_is_free(CheckEmail {
                 email: email().clone(),
+                invite,
             })
             .await;
             match exists {
@@ -75,6 +169,15 @@ pub fn Register() -> Element {
         }
     };
     let register_label = tid!("register");
+    let mode = mode().and_then(Result::ok).unwrap_or_default();
+    if mode == RegistrationMode::Closed {
+        return rsx! {
+            div {
+                class: "flex justify-center items-center min-h-screen",
+                div { class: "alert alert-info", {tid!("register.closed")} }
+            }
+        };
+    }
 
     rsx! {
         div {
@@ -89,6 +192,7 @@ pub fn Register() -> Element {
                     EmailInput {
                         name: "email",
                         placeholder: "mail@site.com",
+                        value: invite_email().flatten(),
                         onblur: check_if_valid,
                         oninput: update_email,
                     }
@@ -107,6 +211,17 @@ pub fn Register() -> Element {
                         placeholder: tid!("frm-password"),
                         title: tid!("frm-password.err"),
                     }
+                    if mode == RegistrationMode::Invite {
+                        label { class: "input",
+                            input {
+                                r#type: "text",
+                                name: "invite",
+                                required: true,
+                                placeholder: tid!("invite.code"),
+                                value: invite,
+                            }
+                        }
+                    }
                     button { class: "btn btn-neutral mt-4",
                         { register_label }
                     }
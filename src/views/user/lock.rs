@@ -0,0 +1,48 @@
+use dioxus::prelude::*;
+use dioxus_i18n::tid;
+
+use crate::{
+    app::{AppGlobalState, Route},
+    components::Alert,
+    shared::user::LoggedUser,
+};
+
+/// Landing page of the "this wasn't me" link sent in the security emails.
+#[component]
+pub fn LockAccount(token: String) -> Element {
+    let mut alert = use_context::<AppGlobalState>();
+    let mut logged = use_context::<Signal<Option<LoggedUser>>>();
+    let nav = use_navigator();
+
+    let lock = move |_| {
+        let token = token.clone();
+        async move {
+            match crate::shared::user::lock_account(token).await {
+                Ok(()) => {
+                    logged.set(None);
+                    alert
+                        .alert
+                        .set(Some((Alert::Warning, tid!("account-lock.done"))));
+                    nav.push(Route::Home {});
+                }
+                Err(e) => {
+                    alert.alert.set(Some((Alert::Error, e.to_string())));
+                }
+            }
+        }
+    };
+
+    rsx! {
+        div { class: "flex justify-center items-center min-h-screen",
+            div { class: "card bg-base-200 w-96",
+                div { class: "card-body",
+                    h2 { class: "card-title", {tid!("account-lock")} }
+                    p { {tid!("account-lock.description")} }
+                    div { class: "card-actions justify-end",
+                        button { class: "btn btn-error", onclick: lock, {tid!("account-lock.confirm")} }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use dioxus::prelude::*;
-use dioxus_i18n::tid;
+use dioxus_i18n::{prelude::i18n, tid};
 
 use super::components::{EmailInput, PasswordInput};
 use crate::{
-    app::AppGlobalState,
+    app::{AppGlobalState, Route},
     components::Alert,
-    shared::user::{Credentials, LoggedUser},
+    shared::{
+        challenge::{get_registration_challenge, solve},
+        user::{Credentials, LoggedUser},
+    },
 };
 
 #[component]
@@ -29,10 +32,18 @@ pub fn Login() -> Element {
                 .and_then(|v| v.first())
                 .cloned()
                 .unwrap_or_default(),
+            locale: Some(i18n().language().to_string()),
+            remember: values
+                .get("remember")
+                .and_then(|v| v.first())
+                .is_some_and(|v| v == "true"),
         };
 
         async move {
             tracing::debug!("sending to server");
+            #[cfg(any(feature = "desktop", feature = "mobile"))]
+            let response = crate::native::login(payload.clone()).await;
+            #[cfg(not(any(feature = "desktop", feature = "mobile")))]
             let response = crate::shared::user::login_user(payload.clone()).await;
             match response {
                 Ok(Some(user)) => {
@@ -54,6 +65,26 @@ pub fn Login() -> Element {
         }
     };
 
+    let start_guest = move |_| async move {
+        // guests cost a proof-of-work like registering
+        let challenge = match get_registration_challenge().await {
+            Ok(challenge) => challenge,
+            Err(e) => return alert.alert.set(Some((Alert::Error, e.to_string()))),
+        };
+        alert
+            .alert
+            .set(Some((Alert::Info, tid!("challenge.solving"))));
+        let solution = solve(&challenge).await;
+        match crate::shared::user::start_guest_session(solution).await {
+            Ok(user) => {
+                logged.set(Some(user));
+                alert.alert.set(Some((Alert::Info, tid!("guest.started"))));
+                nav.push(Route::Home {});
+            }
+            Err(e) => alert.alert.set(Some((Alert::Error, e.to_string()))),
+        }
+    };
+
     let login_label = tid!("login");
     rsx! {
         div {
@@ -79,10 +110,29 @@ pub fn Login() -> Element {
                         placeholder: tid!("frm-password"),
                         title: tid!("frm-password.err"),
                     }
+                    // the desktop and mobile clients keep their tokens, there's no session to remember
+                    if !cfg!(any(feature = "desktop", feature = "mobile")) {
+                        label { class: "label mt-2",
+                            input {
+                                r#type: "checkbox",
+                                name: "remember",
+                                value: "true",
+                                class: "checkbox checkbox-sm",
+                            }
+                            {tid!("login.remember")}
+                        }
+                    }
                     button { class: "btn btn-neutral mt-4",
                         r#type: "submit",
                         { login_label }
                     }
+                    if !cfg!(any(feature = "desktop", feature = "mobile")) {
+                        button { class: "btn btn-ghost mt-2",
+                            r#type: "button",
+                            onclick: start_guest,
+                            {tid!("guest.start")}
+                        }
+                    }
                 }
             }
         }
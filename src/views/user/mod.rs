@@ -1,5 +1,7 @@
 mod components;
 
 pub mod create;
+pub mod lock;
 pub mod login;
 pub mod settings;
+pub mod tokens;
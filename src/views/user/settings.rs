@@ -4,7 +4,10 @@ use dioxus_i18n::tid;
 use crate::{
     app::{AppGlobalState, Route},
     components::Alert,
-    shared::user::{ChangePassword, LoggedUser},
+    shared::{
+        request_id::error_key,
+        user::{ChangePassword, LoggedUser},
+    },
 };
 
 use super::components::PasswordInput;
@@ -23,7 +26,7 @@ pub fn UpdatePassword() -> Element {
                 .cloned()
                 .unwrap_or_default(),
             new_password: values
-                .get("old_password")
+                .get("new_password")
                 .and_then(|v| v.first())
                 .cloned()
                 .unwrap_or_default(),
@@ -38,7 +41,12 @@ pub fn UpdatePassword() -> Element {
                     navigator.push(Route::UserSettingsResume {});
                 }
                 Err(e) => {
-                    alert.alert.set(Some((Alert::Error, e.to_string())));
+                    let kind = if error_key(&e) == Some("frm-password.breached") {
+                        Alert::Warning
+                    } else {
+                        Alert::Error
+                    };
+                    alert.alert.set(Some((kind, e.to_string())));
                 }
             }
         }
@@ -81,6 +89,9 @@ pub fn UserSettingsResume() -> Element {
     let mut app_state = use_context::<AppGlobalState>();
     let nav = use_navigator();
     let logout = move |_: Event<_>| async move {
+        #[cfg(any(feature = "desktop", feature = "mobile"))]
+        let _ = crate::native::logout().await;
+        #[cfg(not(any(feature = "desktop", feature = "mobile")))]
         let _ = crate::shared::user::user_session_logout().await;
         auth.set(None);
         app_state.alert.set(Some((Alert::Info, tid!("logout.suc"))));
@@ -168,6 +179,16 @@ pub fn UserSettings() -> Element {
                         to: Route::UpdatePassword {  },
                         {tid!("frm-password.change")}
                     }
+                    Link {
+                        class: if matches!(path, Route::ApiTokens { .. }) {
+                            "tab tab-active"
+                        } else {
+                            "tab"
+                        },
+                        role: "tab",
+                        to: Route::ApiTokens {  },
+                        {tid!("token.admin")}
+                    }
                 }
                 Outlet::<Route> {}
             }
@@ -0,0 +1,173 @@
+use dioxus::prelude::*;
+use dioxus_i18n::tid;
+
+use crate::{
+    app::AppGlobalState,
+    components::show_error,
+    shared::{
+        token::{
+            ApiToken, CreateApiToken, list_api_tokens, revoke_api_token, submit_create_api_token,
+        },
+        user::LoggedUser,
+    },
+};
+
+#[component]
+pub fn ApiTokens() -> Element {
+    let auth = use_context::<Signal<Option<LoggedUser>>>();
+    let alert = use_context::<AppGlobalState>();
+    let mut tokens = use_resource(list_api_tokens);
+    let mut secret = use_signal(|| None::<String>);
+    let perms = auth().map(|u| u.perms).unwrap_or_default();
+
+    let form_submit = move |evt: Event<FormData>| {
+        evt.prevent_default();
+        let values = evt.values();
+        let payload = CreateApiToken {
+            name: values
+                .get("name")
+                .and_then(|v| v.first())
+                .cloned()
+                .unwrap_or_default(),
+            scopes: values
+                .get("scopes")
+                .map(|v| v.iter().filter_map(|s| s.parse().ok()).collect())
+                .unwrap_or_default(),
+            days: values
+                .get("days")
+                .and_then(|v| v.first())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+        };
+        async move {
+            match submit_create_api_token(payload).await {
+                Ok(created) => {
+                    secret.set(Some(created.secret));
+                    tokens.restart();
+                }
+                Err(e) => show_error(alert, e),
+            }
+        }
+    };
+
+    let label = tid!("token.create");
+    rsx! {
+        div { class: "card bg-base-200 w-full",
+            div { class: "card-body",
+                form {
+                    // a fix for bug [prevent_default()](https://github.com/DioxusLabs/dioxus/issues/4303)
+                    action: "#",
+                    method: "dialog",
+                    onsubmit: form_submit,
+                    fieldset { class: "fieldset bg-base-200 border-base-300 rounded-box w-xs border p-4",
+                        legend { class: "fieldset-legend", {label.clone()} }
+                        input {
+                            class: "input",
+                            r#type: "text",
+                            name: "name",
+                            required: true,
+                            placeholder: tid!("token.name"),
+                        }
+                        input {
+                            class: "input",
+                            r#type: "number",
+                            name: "days",
+                            min: "1",
+                            max: "365",
+                            value: "30",
+                            title: tid!("token.days"),
+                        }
+                        for perm in perms.iter() {
+                            label { class: "label",
+                                input {
+                                    class: "checkbox",
+                                    r#type: "checkbox",
+                                    name: "scopes",
+                                    value: "{perm:?}",
+                                }
+                                "{perm:?}"
+                            }
+                        }
+                        button { class: "btn btn-neutral mt-4",
+                            r#type: "submit",
+                            { label }
+                        }
+                    }
+                }
+                if let Some(secret) = secret() {
+                    div { class: "alert alert-warning",
+                        span { {tid!("token.secret-once")} }
+                        code { "{secret}" }
+                    }
+                }
+                match &*tokens.read() {
+                    Some(Ok(list)) => rsx! {
+                        table { class: "table",
+                            thead {
+                                tr {
+                                    th { {tid!("token.name")} }
+                                    th { {tid!("token.scopes")} }
+                                    th { {tid!("date.c-at")} }
+                                    th { {tid!("token.expires-at")} }
+                                    th { {tid!("token.last-used")} }
+                                    th {}
+                                }
+                            }
+                            tbody {
+                                for token in list.iter() {
+                                    ApiTokenRow {
+                                        key: "{token.id}",
+                                        token: token.clone(),
+                                        onrevoke: move |_| tokens.restart(),
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    Some(Err(e)) => rsx! { div { class: "alert alert-error", {e.to_string()} } },
+                    None => rsx! { span { class: "loading loading-spinner" } },
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn ApiTokenRow(token: ApiToken, onrevoke: EventHandler<()>) -> Element {
+    let alert = use_context::<AppGlobalState>();
+    let id = token.id;
+    let revoke = move |_: Event<_>| async move {
+        match revoke_api_token(id).await {
+            Ok(()) => onrevoke.call(()),
+            Err(e) => show_error(alert, e),
+        }
+    };
+    let scopes = token
+        .scopes
+        .iter()
+        .map(|s| format!("{s:?}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let c_at = token.c_at.format("%Y-%m-%d %H:%M");
+    let expires_at = token.expires_at.format("%Y-%m-%d %H:%M");
+    let last_used = token
+        .last_used_at
+        .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    rsx! {
+        tr {
+            td { {token.name.clone()} }
+            td { "{scopes}" }
+            td { "{c_at}" }
+            td { "{expires_at}" }
+            td { "{last_used}" }
+            td {
+                if token.revoked_at.is_some() {
+                    span { class: "badge", {tid!("token.revoked")} }
+                } else {
+                    button { class: "btn btn-sm btn-error", onclick: revoke, {tid!("token.revoke")} }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+ALTER TYPE app_user_permission ADD VALUE IF NOT EXISTS 'inviteuser';
+
+CREATE TABLE IF NOT EXISTS app_invite (
+    id BIGSERIAL PRIMARY KEY,
+    c_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    code TEXT NOT NULL UNIQUE,
+    email TEXT,
+    created_by BIGINT NOT NULL REFERENCES app_user (id) ON DELETE CASCADE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    redeemed_at TIMESTAMP WITH TIME ZONE,
+    redeemed_by BIGINT REFERENCES app_user (id) ON DELETE SET NULL
+);
+
+INSERT INTO
+    app_groups_permissions (role, permission)
+VALUES
+    ('admin', 'inviteuser')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,8 @@
+-- guests are anonymous until they register, keeping their id
+ALTER TABLE app_user ALTER COLUMN email DROP NOT NULL;
+ALTER TABLE app_user ALTER COLUMN password_hash DROP NOT NULL;
+ALTER TABLE app_user ADD CONSTRAINT app_user_guest_credentials CHECK (
+    role = 'guest' OR (email IS NOT NULL AND password_hash IS NOT NULL)
+);
+
+CREATE INDEX IF NOT EXISTS app_user_guest_idx ON app_user (c_at) WHERE role = 'guest';
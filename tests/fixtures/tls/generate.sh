@@ -0,0 +1,31 @@
+#!/bin/sh
+# Regenerates the throwaway CA, the `localhost` server certificate and the
+# `postgres` client certificate of the TLS tests. The CA keys are discarded.
+set -eu
+cd "$(dirname "$0")"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+ca() {
+    openssl req -x509 -new -newkey ec -pkeyopt ec_paramgen_curve:P-256 -nodes \
+        -keyout "$tmp/$1.key" -out "$1.pem" -days 36500 -subj "/CN=$2" \
+        -addext basicConstraints=critical,CA:TRUE \
+        -addext keyUsage=critical,keyCertSign,cRLSign
+}
+
+leaf() {
+    openssl req -new -newkey ec -pkeyopt ec_paramgen_curve:P-256 -nodes \
+        -keyout "$1.key" -out "$tmp/$1.csr" -subj "/CN=$2"
+    printf '%s\n' "basicConstraints=critical,CA:FALSE" \
+        "keyUsage=critical,digitalSignature" "$3" > "$tmp/$1.ext"
+    openssl x509 -req -in "$tmp/$1.csr" -CA ca.pem -CAkey "$tmp/ca.key" \
+        -CAcreateserial -CAserial "$tmp/ca.srl" -out "$1.pem" -days 36500 \
+        -extfile "$tmp/$1.ext"
+    chmod 600 "$1.key"
+}
+
+ca ca "Test CA"
+ca other-ca "Other test CA"
+leaf server localhost "extendedKeyUsage=serverAuth
+subjectAltName=DNS:localhost,IP:127.0.0.1"
+leaf client postgres "extendedKeyUsage=clientAuth"